@@ -1,14 +1,30 @@
-use actix_web::{App, HttpServer};
+use actix_web::{web, App, HttpServer};
+use lib::store::{Store, StoreHandle};
+use routes::index::DbPath;
 use std::env;
 
 mod routes;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    env_logger::init();
     let bind = env::var("DASHBOARD_BIND").unwrap();
+    let path = Store::path_from_env().map_err(std::io::Error::other)?;
+    // One connection for every request, on its own thread
+    let store = web::Data::new(StoreHandle::spawn(&path).map_err(std::io::Error::other)?);
+    let path = web::Data::new(DbPath(path));
+    // Shared so TMDB, Radarr, Plex and engine calls reuse connections
+    let client = web::Data::new(reqwest::Client::new());
     println!("dashboard listening on http://{bind}");
-    HttpServer::new(|| App::new().service(routes::index::index))
-        .bind(bind)?
-        .run()
-        .await
+    HttpServer::new(move || {
+        App::new()
+            .app_data(store.clone())
+            .app_data(path.clone())
+            .app_data(client.clone())
+            .service(routes::index::index)
+            .service(routes::feedback::feedback)
+    })
+    .bind(bind)?
+    .run()
+    .await
 }
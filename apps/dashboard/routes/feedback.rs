@@ -0,0 +1,118 @@
+use actix_web::{http::header, post, web, HttpRequest, HttpResponse, Responder};
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::Sha256;
+use std::env;
+
+use super::index::urlencode;
+
+/// The buttons on a recommendation card
+pub const KINDS: [(&str, &str, &str); 4] = [
+    ("like", "👍", "More like this"),
+    ("dislike", "👎", "Not for me"),
+    ("not_interested", "✕", "Not interested"),
+    ("seen", "👁", "Already seen"),
+];
+
+#[derive(Debug, Deserialize)]
+struct FeedbackForm {
+    /// The bucket's user; empty for the shared buckets
+    user: String,
+    media_type: String,
+    tmdb_id: u32,
+    kind: String,
+    /// The user filter to go back to
+    back: Option<String>,
+}
+
+/// Whether the form was posted from one of the dashboard's own pages: the browser's
+/// `Origin` (or, failing that, `Referer`) must name the host the request came in on.
+fn same_origin(req: &HttpRequest) -> bool {
+    let header = |name| req.headers().get(name).and_then(|v| v.to_str().ok());
+    let Some(source) = header(header::ORIGIN).or_else(|| header(header::REFERER)) else {
+        return false;
+    };
+    let info = req.connection_info();
+    source
+        .split_once("://")
+        .and_then(|(_, rest)| rest.split('/').next())
+        .is_some_and(|host| host.eq_ignore_ascii_case(info.host()))
+}
+
+/// `sha256=<hex>` of `body` keyed with `secret`, as the engine's `X-Alfred-Signature` expects
+fn signature(secret: &str, body: &[u8]) -> Option<String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).ok()?;
+    mac.update(body);
+    Some(format!(
+        "sha256={}",
+        hex::encode(mac.finalize().into_bytes())
+    ))
+}
+
+/// Pass a card's feedback button on to the engine's `POST /feedback` (`ENGINE_URL`, with
+/// `WEBHOOK_TOKEN` and/or a `WEBHOOK_HMAC_SECRET` signature), which checks and stores it,
+/// then go back to the page it came from. Posts from other sites are refused, since the
+/// engine's secret is added here.
+#[post("/feedback")]
+async fn feedback(
+    req: HttpRequest,
+    form: web::Form<FeedbackForm>,
+    client: web::Data<Client>,
+) -> impl Responder {
+    if !same_origin(&req) {
+        log::error!(
+            "refused cross-site feedback post from {:?}",
+            req.peer_addr()
+        );
+        return HttpResponse::Forbidden().body("feedback must come from the dashboard");
+    }
+    let f = form.into_inner();
+    let Ok(engine) = env::var("ENGINE_URL") else {
+        log::error!("ENGINE_URL is not set; can't save feedback");
+        return HttpResponse::ServiceUnavailable().body("ENGINE_URL is not set");
+    };
+    let body = json!({
+        "tmdb_id": f.tmdb_id,
+        "media_type": f.media_type,
+        "kind": f.kind,
+        "user": Some(f.user.as_str()).filter(|u| !u.is_empty()),
+    })
+    .to_string();
+    let var = |k| env::var(k).ok().filter(|v: &String| !v.trim().is_empty());
+    let mut call = client
+        .post(format!("{}/feedback", engine.trim_end_matches('/')))
+        .header(header::CONTENT_TYPE.as_str(), "application/json");
+    if let Some(token) = var("WEBHOOK_TOKEN") {
+        call = call.header("X-Alfred-Token", token);
+    }
+    if let Some(sig) = var("WEBHOOK_HMAC_SECRET").and_then(|s| signature(&s, body.as_bytes())) {
+        call = call.header("X-Alfred-Signature", sig);
+    }
+    match call.body(body).send().await {
+        Ok(res) if res.status().is_success() => {}
+        Ok(res) => {
+            let status = res.status();
+            let body = res.text().await.unwrap_or_default();
+            log::error!(
+                "engine rejected feedback tmdb_id={}: {status} {body}",
+                f.tmdb_id
+            );
+            let status = actix_web::http::StatusCode::from_u16(status.as_u16())
+                .unwrap_or(actix_web::http::StatusCode::BAD_GATEWAY);
+            return HttpResponse::build(status).body(format!("could not save feedback: {body}"));
+        }
+        Err(e) => {
+            log::error!("feedback request failed tmdb_id={}: {e:#}", f.tmdb_id);
+            return HttpResponse::BadGateway().body(format!("could not save feedback: {e:#}"));
+        }
+    }
+    let back = match f.back.as_deref().filter(|u| !u.is_empty()) {
+        Some(u) => format!("/?user={}", urlencode(u)),
+        None => "/".to_string(),
+    };
+    HttpResponse::SeeOther()
+        .insert_header((header::LOCATION, back))
+        .finish()
+}
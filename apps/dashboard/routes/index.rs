@@ -1,38 +1,80 @@
-use actix_web::{get, HttpResponse, Responder};
+use actix_web::{get, web, HttpResponse, Responder};
 use futures::{stream, StreamExt};
+use lib::store::{Store, StoreHandle};
 use once_cell::sync::Lazy;
 use reqwest::Client;
 use serde::Deserialize;
 use std::{
+    collections::HashMap,
     env,
-    fs::File,
-    io::{BufRead, BufReader},
     sync::Mutex,
     time::{Duration, Instant},
 };
 use v_htmlescape::escape;
 
+use super::feedback::KINDS;
+
 // default to your project path
 // const DEFAULT_FILE: &str = "db/movie_recommendation_engine/movie_recommendation_engine.ndjson";
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug)]
 struct RecItem {
     tmdb_id: u32,
+    /// "movie" or "tv"
+    media_type: Option<String>,
+    /// "llm" or "tmdb"
+    source: Option<String>,
+    /// Why it was picked, in the LLM's words
+    reason: Option<String>,
+    /// Tmdb ids of the bucket's watches it follows from
+    because_of: Vec<u32>,
 }
 
-#[derive(Debug, Deserialize)]
+impl RecItem {
+    fn is_tv(&self) -> bool {
+        self.media_type.as_deref() == Some("tv")
+    }
+
+    /// Tag for picks that didn't come from the LLM
+    fn source_label(&self) -> Option<(&'static str, &'static str)> {
+        match self.source.as_deref() {
+            Some("tmdb") => Some(("TMDB", "From TMDB's related titles")),
+            Some("index") => Some(("Similar", "From the local similarity index")),
+            _ => None,
+        }
+    }
+}
+
+/// "Because you watched A", "… A and B", "… A, B and C"
+fn because_line(titles: &[String]) -> Option<String> {
+    let (last, rest) = titles.split_last()?;
+    Some(if rest.is_empty() {
+        format!("Because you watched {last}")
+    } else {
+        format!("Because you watched {} and {last}", rest.join(", "))
+    })
+}
+
+#[derive(Debug)]
 struct BatchLine {
-    // we only deserialize what we need; unknown fields are ignored
     bucket: Option<String>,
+    /// Plex user the bucket belongs to; absent on shared/legacy buckets
+    user: Option<String>,
     count: Option<usize>,
     updated_at: Option<String>,
     recommendations_generated_at: Option<String>,
     recommendations: Option<Vec<RecItem>>,
+    /// Titles of the bucket's watches by tmdb id, movies and series apart (`true` for tv)
+    watched_titles: HashMap<(bool, u32), String>,
+    /// The user's feedback kind on each title, keyed the same way
+    feedback: HashMap<(bool, u32), String>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
 struct TmdbMovie {
+    #[serde(alias = "name")]
     title: Option<String>,
+    #[serde(alias = "first_air_date")]
     release_date: Option<String>,
     poster_path: Option<String>,
     vote_average: Option<f32>,
@@ -102,8 +144,8 @@ struct CacheEntry<T> {
     val: Option<T>,
 }
 
-static TMDB_CACHE: Lazy<Mutex<std::collections::HashMap<u32, CacheEntry<TmdbMovie>>>> =
-    Lazy::new(|| Mutex::new(std::collections::HashMap::new()));
+/// TMDB details barely change; keep them in the store's enrichment cache for a week.
+const TMDB_CACHE_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
 static RADARR_CACHE: Lazy<Mutex<std::collections::HashMap<u32, CacheEntry<bool>>>> =
     Lazy::new(|| Mutex::new(std::collections::HashMap::new()));
 static PLEX_CACHE: Lazy<Mutex<std::collections::HashMap<u32, CacheEntry<bool>>>> =
@@ -140,32 +182,106 @@ fn set_cached<T: Clone>(
     }
 }
 
-fn read_batches(path: &str) -> Vec<BatchLine> {
+/// Newest buckets first (optionally one user's) with their recommendations, plus all user names.
+fn read_batches(
+    store: &Store,
+    user: Option<&str>,
+    limit: usize,
+) -> anyhow::Result<(Vec<String>, Vec<BatchLine>)> {
+    let users = store.users()?;
+    let mut feedback_by_user: HashMap<Option<String>, HashMap<(bool, u32), String>> =
+        HashMap::new();
     let mut out = Vec::new();
-    if let Ok(f) = File::open(path) {
-        for line in BufReader::new(f).lines().flatten() {
-            if let Ok(b) = serde_json::from_str::<BatchLine>(&line) {
-                if b.bucket.is_some() {
-                    out.push(b);
-                }
+    for b in store.buckets(user, limit)? {
+        let recs = store.recommendations(b.id)?;
+        let feedback = match feedback_by_user.get(&b.user) {
+            Some(f) => f.clone(),
+            None => {
+                let f: HashMap<_, _> = store
+                    .feedback(b.user.as_deref())?
+                    .into_iter()
+                    .map(|f| ((f.media_type == "tv", f.tmdb_id), f.kind))
+                    .collect();
+                feedback_by_user.insert(b.user.clone(), f.clone());
+                f
             }
-        }
+        };
+        let watched_titles = store
+            .bucket_events(b.id)?
+            .into_iter()
+            .filter_map(|e| {
+                let tv = matches!(
+                    e.row.media_type.as_deref(),
+                    Some("episode" | "show" | "season")
+                );
+                let title = if tv {
+                    e.row.show_title
+                } else {
+                    Some(e.row.title)
+                };
+                Some(((tv, e.row.tmdb_id?), title?))
+            })
+            .collect();
+        out.push(BatchLine {
+            bucket: Some(b.tag),
+            user: b.user,
+            count: Some(b.count),
+            updated_at: b.updated_at,
+            recommendations: b.recommendations_generated_at.as_ref().map(|_| {
+                recs.into_iter()
+                    .map(|r| RecItem {
+                        tmdb_id: r.tmdb_id,
+                        media_type: Some(r.media_type),
+                        source: r.source,
+                        reason: r.reason,
+                        because_of: r.because_of,
+                    })
+                    .collect()
+            }),
+            recommendations_generated_at: b.recommendations_generated_at,
+            watched_titles,
+            feedback,
+        });
     }
-    // newest first by bucket string (lexicographically works with your tag format)
-    out.sort_by(|a, b| b.bucket.cmp(&a.bucket));
-    out
+    Ok((users, out))
+}
+
+/// Minimal percent-encoding for a query-string value
+pub(crate) fn urlencode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
 }
 
 fn year_from_release_date(d: &Option<String>) -> Option<String> {
     d.as_ref().and_then(|s| s.get(0..4)).map(|y| y.to_string())
 }
 
-async fn fetch_tmdb_movie(client: &Client, api_key: &str, id: u32) -> Option<TmdbMovie> {
-    if let Some(cached) = get_cached(&TMDB_CACHE, id, Duration::from_secs(3)) {
-        return cached;
+/// Movie details, or series details (`name`/`first_air_date` mapped onto the same fields) when `tv`
+async fn fetch_tmdb_movie(
+    client: &Client,
+    store: &StoreHandle,
+    api_key: &str,
+    id: u32,
+    tv: bool,
+) -> Option<TmdbMovie> {
+    let kind = if tv { "tmdb_tv" } else { "tmdb_movie" };
+    let cached = store
+        .read(move |s| s.cache_get(kind, &id.to_string(), TMDB_CACHE_TTL))
+        .await;
+    if let Ok(Some(cached)) = cached {
+        if let Ok(val) = serde_json::from_str::<TmdbMovie>(&cached) {
+            return Some(val);
+        }
     }
     let url = format!(
-        "https://api.themoviedb.org/3/movie/{id}?api_key={api_key}&language=en-US",
+        "https://api.themoviedb.org/3/{kind}/{id}?api_key={api_key}&language=en-US",
+        kind = if tv { "tv" } else { "movie" },
         id = id,
         api_key = api_key
     );
@@ -176,9 +292,15 @@ async fn fetch_tmdb_movie(client: &Client, api_key: &str, id: u32) -> Option<Tmd
         .await
     {
         Ok(resp) if resp.status().is_success() => {
-            let val = resp.json::<TmdbMovie>().await.ok();
-            set_cached(&TMDB_CACHE, id, val.clone());
-            val
+            let body = resp.text().await.ok()?;
+            let val = serde_json::from_str::<TmdbMovie>(&body).ok()?;
+            let stored = store
+                .write(move |s| s.cache_put(kind, &id.to_string(), &body))
+                .await;
+            if let Err(e) = stored {
+                println!("[dashboard] tmdb cache write failed tmdb_id={id}: {e:#}");
+            }
+            Some(val)
         }
         _ => None,
     }
@@ -382,17 +504,74 @@ async fn fetch_plex_watched(
     watched
 }
 
+#[derive(Debug, Deserialize)]
+struct NotifyHealth {
+    ok: bool,
+    last_event_age_secs: Option<i64>,
+}
+
+/// `/healthz` of notify_new_movie's embedded status server, if configured and reachable
+async fn fetch_notify_health(client: &Client, base_url: &str) -> Option<NotifyHealth> {
+    let url = format!("{}/healthz", base_url.trim_end_matches('/'));
+    // 503 still carries the JSON body, so don't bail on non-2xx.
+    let resp = client
+        .get(&url)
+        .timeout(std::time::Duration::from_secs(2))
+        .send()
+        .await
+        .ok()?;
+    resp.json::<NotifyHealth>().await.ok()
+}
+
+fn format_age(secs: i64) -> String {
+    match secs {
+        s if s < 120 => format!("{s}s ago"),
+        s if s < 2 * 3600 => format!("{}m ago", s / 60),
+        s if s < 2 * 86400 => format!("{}h ago", s / 3600),
+        s => format!("{}d ago", s / 86400),
+    }
+}
+
+/// The database file the store was opened on, shown on the page
+pub struct DbPath(pub String);
+
+#[derive(Debug, Deserialize)]
+struct IndexQuery {
+    /// Only show this user's buckets
+    user: Option<String>,
+}
+
 #[get("/")]
-async fn index() -> impl Responder {
-    let path = env::var("NDJSON_PATH").unwrap();
+async fn index(
+    query: web::Query<IndexQuery>,
+    store: web::Data<StoreHandle>,
+    path: web::Data<DbPath>,
+    client: web::Data<Client>,
+) -> impl Responder {
+    let path = &path.0;
     let tmdb_key = env::var("TMDB_API_KEY").ok();
     let radarr_url = env::var("RADARR_URL").ok();
     let radarr_key = env::var("RADARR_API_KEY").ok();
     let plex_url = env::var("PLEX_URL").ok();
     let plex_token = env::var("PLEX_TOKEN").ok();
     let plex_section = env::var("PLEX_SECTION").ok(); // e.g., movies library key "1"
-    let client = Client::new();
-    let batches = read_batches(&path);
+    let notify_status_url = env::var("NOTIFY_STATUS_URL").ok();
+    let selected_user = query.user.as_deref().filter(|u| !u.is_empty());
+    // show the newest 8 buckets
+    let wanted = selected_user.map(String::from);
+    // One snapshot, so a bucket never shows without the recommendations written with it.
+    let loaded = store
+        .read(move |s| read_batches(s, wanted.as_deref(), 8))
+        .await;
+    let (users, batches) = match loaded {
+        Ok(l) => l,
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .content_type("text/plain; charset=utf-8")
+                .body(format!("could not read {path}: {e:#}"));
+        }
+    };
+    let store = store.get_ref();
 
     let mut html = String::new();
     html.push_str(
@@ -415,12 +594,19 @@ async fn index() -> impl Responder {
             .badge-watched{background:#2e7dd7;top:40px;}
             .badge-not-watched{background:#757575;top:40px;}
             .card-text{display:flex;flex-direction:column;gap:.35rem}
+            .because{font-size:.85rem;color:#aaa;font-style:italic}
+            .fb{display:flex;gap:.3rem;margin:0}
+            .fb button{background:#9992;border:1px solid #7774;border-radius:999px;padding:.1rem .45rem;cursor:pointer;font:inherit;font-size:.85rem}
+            .fb button.fb-active{background:#2e7dd7;color:#fff;border-color:#2e7dd7}
             .title{font-weight:600}
             .meta-line{font-size:.85rem;color:#aaa;display:flex;gap:.5rem;align-items:center}
             .tag-downloaded{background:#1dbf73;color:#000;border-radius:999px;padding:.15rem .55rem;font-weight:700}
             .tag-not-downloaded{background:#c62828;color:#fff;border-radius:999px;padding:.15rem .55rem;font-weight:700}
             .tag-watched{background:#2e7dd7;color:#fff;border-radius:999px;padding:.15rem .55rem;font-weight:700}
             .tag-not-watched{background:#757575;color:#fff;border-radius:999px;padding:.15rem .55rem;font-weight:700}
+            .users{display:flex;gap:.5rem;flex-wrap:wrap;margin-bottom:1rem}
+            .users a{text-decoration:none}
+            .tag-active{background:#2e7dd7;color:#fff}
             a{color:inherit}
         </style>"#,
     );
@@ -428,8 +614,51 @@ async fn index() -> impl Responder {
     html.push_str("<h1>Alfred · Movie Recommendations</h1>");
     html.push_str(&format!(
         r#"<div class="meta">Source: <code>{}</code></div>"#,
-        escape(&path)
+        escape(path)
     ));
+    if let Some(u) = notify_status_url.as_deref() {
+        let status = match fetch_notify_health(&client, u).await {
+            Some(h) => format!(
+                "{} · last new file {}",
+                if h.ok {
+                    "✓ watching"
+                } else {
+                    "✗ watcher down"
+                },
+                h.last_event_age_secs
+                    .map(format_age)
+                    .unwrap_or_else(|| "—".to_string())
+            ),
+            None => "✗ unreachable".to_string(),
+        };
+        html.push_str(&format!(
+            r#"<div class="meta">notify_new_movie: {}</div>"#,
+            escape(&status)
+        ));
+    }
+
+    if !users.is_empty() {
+        html.push_str(r#"<div class="users">"#);
+        let all_cls = if selected_user.is_none() {
+            "tag tag-active"
+        } else {
+            "tag"
+        };
+        html.push_str(&format!(r#"<a class="{all_cls}" href="/">everyone</a>"#));
+        for u in &users {
+            let cls = if selected_user == Some(u.as_str()) {
+                "tag tag-active"
+            } else {
+                "tag"
+            };
+            html.push_str(&format!(
+                r#"<a class="{cls}" href="/?user={href}">{name}</a>"#,
+                href = escape(&urlencode(u)),
+                name = escape(u)
+            ));
+        }
+        html.push_str("</div>");
+    }
 
     if batches.is_empty() {
         html.push_str("<p>No batches found.</p>");
@@ -438,8 +667,7 @@ async fn index() -> impl Responder {
             .body(html);
     }
 
-    for b in batches.iter().take(8) {
-        // show the newest 8 buckets
+    for b in batches.iter() {
         let bucket = b.bucket.as_deref().unwrap_or("-");
         let count = b.count.unwrap_or(0);
         let gen_ts = b
@@ -451,9 +679,11 @@ async fn index() -> impl Responder {
         html.push_str(r#"<div class="bucket">"#);
         html.push_str(&format!(
             r#"<div class="hdr"><strong>Bucket:</strong> <span class="tag">{}</span>
+               <span class="tag">user: {}</span>
                <span class="tag">plays: {}</span>
                <span class="tag">generated: {}</span></div>"#,
             escape(bucket),
+            escape(b.user.as_deref().unwrap_or("everyone")),
             count,
             escape(gen_ts)
         ));
@@ -472,7 +702,8 @@ async fn index() -> impl Responder {
                         let plex_section = plex_section.clone();
                         async move {
                             let tmdb = if let Some(k) = tmdb_key.as_ref() {
-                                let t = fetch_tmdb_movie(&client, k, r.tmdb_id).await;
+                                let t =
+                                    fetch_tmdb_movie(&client, store, k, r.tmdb_id, r.is_tv()).await;
                                 if t.is_none() {
                                     println!(
                                         "[dashboard] tmdb_id={} tmdb lookup failed",
@@ -483,8 +714,9 @@ async fn index() -> impl Responder {
                             } else {
                                 None
                             };
-                            let has_file = if let (Some(u), Some(k)) =
-                                (radarr_url.as_ref(), radarr_key.as_ref())
+                            // Radarr and the Plex movie section only know movies
+                            let has_file = if let (Some(u), Some(k), false) =
+                                (radarr_url.as_ref(), radarr_key.as_ref(), r.is_tv())
                             {
                                 let hf = fetch_radarr_has_file(&client, u, k, r.tmdb_id).await;
                                 println!(
@@ -495,8 +727,8 @@ async fn index() -> impl Responder {
                             } else {
                                 None
                             };
-                            let watched = if let (Some(u), Some(t)) =
-                                (plex_url.as_ref(), plex_token.as_ref())
+                            let watched = if let (Some(u), Some(t), false) =
+                                (plex_url.as_ref(), plex_token.as_ref(), r.is_tv())
                             {
                                 if let Some(rk) = fetch_plex_rating_key(
                                     &client,
@@ -577,7 +809,11 @@ async fn index() -> impl Responder {
                         .map(|v| format!("{:.1}", v));
                     let has_file = cm.and_then(|m| m.has_file);
                     let watched = cm.and_then(|m| m.watched);
-                    let url = format!("https://www.themoviedb.org/movie/{}", r.tmdb_id);
+                    let url = format!(
+                        "https://www.themoviedb.org/{}/{}",
+                        if r.is_tv() { "tv" } else { "movie" },
+                        r.tmdb_id
+                    );
 
                     html.push_str("<li>");
                     html.push_str(r#"<div class="poster-wrap">"#);
@@ -613,8 +849,22 @@ async fn index() -> impl Responder {
                         title = escape(&title),
                         year = escape(&year)
                     ));
-                    if has_file.is_some() || watched.is_some() {
+                    if has_file.is_some()
+                        || watched.is_some()
+                        || r.is_tv()
+                        || r.source_label().is_some()
+                    {
                         html.push_str(r#"<div class="meta-line">"#);
+                        if r.is_tv() {
+                            html.push_str(r#"<span class="tag">TV</span>"#);
+                        }
+                        if let Some((label, hint)) = r.source_label() {
+                            html.push_str(&format!(
+                                r#"<span class="tag" title="{hint}">{label}</span>"#,
+                                hint = escape(hint),
+                                label = escape(label)
+                            ));
+                        }
                         if let Some(hf) = has_file {
                             let icon = if hf { "⬇" } else { "⌁" };
                             let cls = if hf {
@@ -639,6 +889,44 @@ async fn index() -> impl Responder {
                         }
                         html.push_str("</div>");
                     }
+                    let mut sources = Vec::new();
+                    for id in &r.because_of {
+                        let known = b.watched_titles.get(&(r.is_tv(), *id)).cloned();
+                        let title = match (known, tmdb_key.as_ref()) {
+                            (Some(t), _) => Some(t),
+                            (None, Some(k)) => fetch_tmdb_movie(&client, store, k, *id, r.is_tv())
+                                .await
+                                .and_then(|t| t.title),
+                            (None, None) => None,
+                        };
+                        sources.extend(title);
+                    }
+                    if let Some(line) = because_line(&sources).or_else(|| r.reason.clone()) {
+                        html.push_str(&format!(
+                            r#"<div class="because" title="{reason}">{line}</div>"#,
+                            reason = escape(r.reason.as_deref().unwrap_or("")),
+                            line = escape(&line)
+                        ));
+                    }
+                    let current = b.feedback.get(&(r.is_tv(), r.tmdb_id));
+                    html.push_str(&format!(
+                        r#"<form class="fb" method="post" action="/feedback"><input type="hidden" name="user" value="{user}"><input type="hidden" name="media_type" value="{media}"><input type="hidden" name="tmdb_id" value="{id}"><input type="hidden" name="back" value="{back}">"#,
+                        user = escape(b.user.as_deref().unwrap_or("")),
+                        media = if r.is_tv() { "tv" } else { "movie" },
+                        id = r.tmdb_id,
+                        back = escape(selected_user.unwrap_or(""))
+                    ));
+                    for (kind, icon, hint) in KINDS {
+                        let cls = if current.map(String::as_str) == Some(kind) {
+                            "fb-active"
+                        } else {
+                            ""
+                        };
+                        html.push_str(&format!(
+                            r#"<button class="{cls}" name="kind" value="{kind}" title="{hint}">{icon}</button>"#
+                        ));
+                    }
+                    html.push_str("</form>");
                     html.push_str("</div>");
                     html.push_str("</li>");
                 }
@@ -1 +1,2 @@
+pub mod feedback;
 pub mod index;
@@ -0,0 +1,351 @@
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use lib::store::retention::{self, ArchivedBucket, RetentionPolicy};
+use lib::store::{Bucket, Recommendation, StoredEvent};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use time::OffsetDateTime;
+
+use crate::auth::WebhookAuth;
+use crate::commands;
+use crate::events::{EventKind, IngestConfig, WatchEvent};
+use crate::recommend::MediaType;
+
+const DEFAULT_PAGE: usize = 20;
+const MAX_PAGE: usize = 200;
+
+#[derive(Debug, Deserialize)]
+struct PageQuery {
+    user: Option<String>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+/// Picks out one user's bucket for a tag; without `user`, the shared bucket (or for
+/// regenerate, every user's bucket with that tag)
+#[derive(Debug, Deserialize)]
+struct UserQuery {
+    user: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct BucketPage {
+    buckets: Vec<Bucket>,
+    total: usize,
+    limit: usize,
+    offset: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct EventJson {
+    id: i64,
+    ts: Option<String>,
+    source: String,
+    event: String,
+    user: Option<String>,
+    title: String,
+    media_type: Option<String>,
+    tmdb_id: Option<u32>,
+    tvdb_id: Option<u32>,
+    show_title: Option<String>,
+    /// The stored event as it was normalized at ingest
+    data: Value,
+}
+
+impl From<StoredEvent> for EventJson {
+    fn from(e: StoredEvent) -> Self {
+        let r = e.row;
+        EventJson {
+            id: e.id,
+            data: serde_json::from_str(&r.data).unwrap_or(Value::String(r.data)),
+            ts: r.ts,
+            source: r.source,
+            event: r.event,
+            user: r.user,
+            title: r.title,
+            media_type: r.media_type,
+            tmdb_id: r.tmdb_id,
+            tvdb_id: r.tvdb_id,
+            show_title: r.show_title,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct BucketDetail {
+    bucket: Bucket,
+    events: Vec<EventJson>,
+    recommendations: Vec<Recommendation>,
+    /// What validation dropped the last time recommendations were generated
+    validation: Option<Value>,
+}
+
+/// A bucket that has been moved to the archive, as its archive line
+#[derive(Debug, Serialize)]
+struct ArchivedDetail {
+    archived: ArchivedBucket,
+    bucket: Value,
+}
+
+/// Body of `POST /watched`
+#[derive(Debug, Deserialize)]
+pub struct ManualWatch {
+    pub tmdb_id: u32,
+    /// "movie" (default) or "tv" for a series
+    #[serde(default)]
+    pub media_type: MediaType,
+    pub user: Option<String>,
+    pub title: Option<String>,
+    /// Add to this bucket instead of the user's current one
+    pub bucket: Option<String>,
+}
+
+impl ManualWatch {
+    fn into_event(self, user: Option<String>, raw: String) -> WatchEvent {
+        let ts = OffsetDateTime::now_utc()
+            .format(&time::format_description::well_known::Rfc3339)
+            .ok();
+        WatchEvent {
+            user,
+            title: self.title.unwrap_or_default(),
+            // Series are matched on the "show" media type, like a Tautulli episode.
+            media_type: Some(match self.media_type {
+                MediaType::Movie => "movie".into(),
+                MediaType::Tv => "show".into(),
+            }),
+            tmdb_id: Some(self.tmdb_id),
+            event: EventKind::Watched,
+            ..WatchEvent::unparsed("manual", ts, raw)
+        }
+    }
+}
+
+fn store_error(e: anyhow::Error) -> HttpResponse {
+    log::error!("admin store error: {e:#}");
+    HttpResponse::InternalServerError().json(json!({ "error": format!("{e:#}") }))
+}
+
+fn not_found(what: String) -> HttpResponse {
+    HttpResponse::NotFound().json(json!({ "error": format!("{what} not found") }))
+}
+
+/// `GET /buckets?user=&limit=&offset=`: newest first
+#[get("/buckets")]
+async fn list_buckets(
+    req: HttpRequest,
+    body: web::Bytes,
+    auth: web::Data<WebhookAuth>,
+    ingest: web::Data<IngestConfig>,
+    query: web::Query<PageQuery>,
+) -> impl Responder {
+    if auth.verify(&req, &body).is_err() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    let PageQuery {
+        user,
+        limit,
+        offset,
+    } = query.into_inner();
+    let limit = limit.unwrap_or(DEFAULT_PAGE).clamp(1, MAX_PAGE);
+    let offset = offset.unwrap_or(0);
+    let page = ingest
+        .store
+        .read(move |s| {
+            Ok(BucketPage {
+                buckets: s.buckets_page(user.as_deref(), limit, offset)?,
+                total: s.count_buckets(user.as_deref())?,
+                limit,
+                offset,
+            })
+        })
+        .await;
+    match page {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(e) => store_error(e),
+    }
+}
+
+/// `GET /buckets/{tag}?user=`: the bucket with its events and recommendations, read
+/// from the archive if it has been moved there
+#[get("/buckets/{tag}")]
+async fn get_bucket(
+    req: HttpRequest,
+    body: web::Bytes,
+    auth: web::Data<WebhookAuth>,
+    ingest: web::Data<IngestConfig>,
+    retention: web::Data<RetentionPolicy>,
+    tag: web::Path<String>,
+    query: web::Query<UserQuery>,
+) -> impl Responder {
+    if auth.verify(&req, &body).is_err() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    let (tag, user) = (tag.into_inner(), query.into_inner().user);
+    let what = format!("bucket {tag}");
+    let archive_dir = retention.archive_dir.clone();
+    let detail = ingest
+        .store
+        .read(move |s| {
+            let Some(bucket) = s.bucket(&tag, user.as_deref())? else {
+                let Some(archived) = s.archived_bucket(&tag, user.as_deref())? else {
+                    return Ok(None);
+                };
+                let bucket = retention::read_archived(&archive_dir, &archived)?
+                    .ok_or_else(|| anyhow::anyhow!("{tag} is missing from {}", archived.file))?;
+                return Ok(Some(serde_json::to_value(ArchivedDetail {
+                    archived,
+                    bucket,
+                })?));
+            };
+            Ok(Some(serde_json::to_value(BucketDetail {
+                events: s
+                    .bucket_events(bucket.id)?
+                    .into_iter()
+                    .map(EventJson::from)
+                    .collect(),
+                recommendations: s.recommendations(bucket.id)?,
+                validation: s
+                    .recommendation_report(bucket.id)?
+                    .and_then(|r| serde_json::from_str(&r).ok()),
+                bucket,
+            })?))
+        })
+        .await;
+    match detail {
+        Ok(Some(d)) => HttpResponse::Ok().json(d),
+        Ok(None) => not_found(what),
+        Err(e) => store_error(e),
+    }
+}
+
+/// `DELETE /buckets/{tag}?user=`: removes its events and recommendations too
+#[delete("/buckets/{tag}")]
+async fn delete_bucket(
+    req: HttpRequest,
+    body: web::Bytes,
+    auth: web::Data<WebhookAuth>,
+    ingest: web::Data<IngestConfig>,
+    tag: web::Path<String>,
+    query: web::Query<UserQuery>,
+) -> impl Responder {
+    if auth.verify(&req, &body).is_err() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    let (tag, user) = (tag.into_inner(), query.into_inner().user);
+    let what = format!("bucket {tag}");
+    let deleted = ingest
+        .store
+        .write(move |s| match s.bucket(&tag, user.as_deref())? {
+            Some(b) => s.delete_bucket(b.id),
+            None => Ok(false),
+        })
+        .await;
+    match deleted {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => not_found(what),
+        Err(e) => store_error(e),
+    }
+}
+
+/// `POST /buckets/{tag}/regenerate?user=`: generate now, overwriting any recommendations
+#[post("/buckets/{tag}/regenerate")]
+async fn regenerate_bucket(
+    req: HttpRequest,
+    body: web::Bytes,
+    auth: web::Data<WebhookAuth>,
+    ingest: web::Data<IngestConfig>,
+    client: web::Data<reqwest::Client>,
+    tag: web::Path<String>,
+    query: web::Query<UserQuery>,
+) -> impl Responder {
+    if auth.verify(&req, &body).is_err() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    let (tag, user) = (tag.into_inner(), query.into_inner().user);
+    let buckets = match ingest.store.read(|s| s.all_buckets()).await {
+        Ok(all) => commands::tagged(all, &tag, user.as_deref()),
+        Err(e) => return store_error(e),
+    };
+    if buckets.is_empty() {
+        return not_found(format!("bucket {tag}"));
+    }
+    match commands::generate(&ingest.store, &client, buckets).await {
+        Ok(Some(report)) => HttpResponse::Ok().json(report),
+        Ok(None) => HttpResponse::Conflict()
+            .json(json!({ "error": "recommendations are already being generated" })),
+        Err(e) => store_error(e),
+    }
+}
+
+/// `DELETE /events/{id}`: any stored event, bucketed or analytics
+#[delete("/events/{id}")]
+async fn delete_event(
+    req: HttpRequest,
+    body: web::Bytes,
+    auth: web::Data<WebhookAuth>,
+    ingest: web::Data<IngestConfig>,
+    id: web::Path<i64>,
+) -> impl Responder {
+    if auth.verify(&req, &body).is_err() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    let id = id.into_inner();
+    match ingest.store.write(move |s| s.delete_event(id)).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => not_found(format!("event {id}")),
+        Err(e) => store_error(e),
+    }
+}
+
+/// `POST /watched`: record a watch by hand, e.g. something seen outside the media server
+#[post("/watched")]
+async fn add_watched(
+    req: HttpRequest,
+    body: web::Bytes,
+    auth: web::Data<WebhookAuth>,
+    ingest: web::Data<IngestConfig>,
+) -> impl Responder {
+    if auth.verify(&req, &body).is_err() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    let raw = String::from_utf8_lossy(&body).into_owned();
+    let watch: ManualWatch = match serde_json::from_str(&raw) {
+        Ok(w) => w,
+        Err(e) => return HttpResponse::BadRequest().json(json!({ "error": e.to_string() })),
+    };
+    let Some(user) = ingest.users.resolve(watch.user.as_deref()) else {
+        return HttpResponse::UnprocessableEntity()
+            .json(json!({ "error": "user is in IGNORED_USERS" }));
+    };
+    let bucket = watch.bucket.clone();
+    let event = watch.into_event(user.clone(), raw);
+    let appended = match bucket {
+        Some(tag) => {
+            let row = event.to_row();
+            let now = event.ts.clone().unwrap_or_default();
+            ingest
+                .store
+                .write(move |s| s.append_event(&tag, user.as_deref(), &now, &row))
+                .await
+        }
+        None => crate::append_event(&ingest.store, ingest.buckets, user, &event).await,
+    };
+    let bucket = match appended {
+        Ok(id) => ingest.store.read(move |s| s.bucket_by_id(id)).await,
+        Err(e) => Err(e),
+    };
+    match bucket {
+        Ok(bucket) => HttpResponse::Created().json(json!({ "bucket": bucket })),
+        Err(e) => store_error(e),
+    }
+}
+
+/// Register the admin endpoints. Needs `WebhookAuth`, `IngestConfig`, a
+/// `reqwest::Client` and the `RetentionPolicy` as app data.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_buckets)
+        .service(get_bucket)
+        .service(delete_bucket)
+        .service(regenerate_bucket)
+        .service(delete_event)
+        .service(add_watched);
+}
@@ -0,0 +1,241 @@
+use actix_web::{web, HttpRequest};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::{
+    collections::HashMap,
+    env, fmt,
+    net::IpAddr,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Header carrying the shared secret (Tautulli: Settings → Notification Agents → Webhook → Headers)
+pub const TOKEN_HEADER: &str = "X-Alfred-Token";
+/// Header carrying `sha256=<hex>` of the raw body, keyed with `WEBHOOK_HMAC_SECRET`
+pub const SIGNATURE_HEADER: &str = "X-Alfred-Signature";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthError {
+    IpNotAllowed,
+    MissingCredentials,
+    BadToken,
+    BadSignature,
+}
+
+impl AuthError {
+    fn label(self) -> &'static str {
+        match self {
+            AuthError::IpNotAllowed => "ip_not_allowed",
+            AuthError::MissingCredentials => "missing_credentials",
+            AuthError::BadToken => "bad_token",
+            AuthError::BadSignature => "bad_signature",
+        }
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+static FAILURES: [AtomicU64; 4] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+const REASONS: [AuthError; 4] = [
+    AuthError::IpNotAllowed,
+    AuthError::MissingCredentials,
+    AuthError::BadToken,
+    AuthError::BadSignature,
+];
+
+/// `(reason, count)` of rejected requests since startup
+pub fn failure_counts() -> Vec<(&'static str, u64)> {
+    REASONS
+        .iter()
+        .zip(FAILURES.iter())
+        .map(|(r, n)| (r.label(), n.load(Ordering::Relaxed)))
+        .collect()
+}
+
+fn count_failure(err: AuthError) {
+    let idx = REASONS.iter().position(|r| *r == err).unwrap_or(0);
+    FAILURES[idx].fetch_add(1, Ordering::Relaxed);
+}
+
+/// An IPv4/IPv6 network like `192.168.1.0/24` (a bare address means a single host)
+#[derive(Debug, Clone, Copy)]
+pub struct IpRange {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpRange {
+    pub fn parse(s: &str) -> Option<Self> {
+        let (addr, prefix) = match s.trim().split_once('/') {
+            Some((a, p)) => (a.parse::<IpAddr>().ok()?, Some(p.parse::<u8>().ok()?)),
+            None => (s.trim().parse::<IpAddr>().ok()?, None),
+        };
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max);
+        (prefix <= max).then_some(Self { addr, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // Treat IPv4-mapped IPv6 peers (::ffff:a.b.c.d) as IPv4.
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+            v4 => v4,
+        };
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Shared-secret / HMAC / source-IP checks for inbound webhooks.
+///
+/// A request passes when its source IP is allowed (if an allowlist is set) and it carries
+/// either the shared token (header or `?token=`) or a valid body signature. With neither a
+/// token nor an HMAC secret configured, only the IP allowlist applies; `from_env` only
+/// allows that with `WEBHOOK_AUTH=off`.
+#[derive(Clone, Default)]
+pub struct WebhookAuth {
+    token: Option<String>,
+    hmac_secret: Option<Vec<u8>>,
+    allowed_ips: Vec<IpRange>,
+}
+
+impl WebhookAuth {
+    pub fn new(
+        token: Option<String>,
+        hmac_secret: Option<String>,
+        allowed_ips: Vec<IpRange>,
+    ) -> Self {
+        Self {
+            token: token.filter(|t| !t.is_empty()),
+            hmac_secret: hmac_secret
+                .filter(|s| !s.is_empty())
+                .map(String::into_bytes),
+            allowed_ips,
+        }
+    }
+
+    /// Reads `WEBHOOK_TOKEN`, `WEBHOOK_HMAC_SECRET` and `WEBHOOK_ALLOWED_IPS` (comma-separated).
+    /// Fails without a token or HMAC secret unless `WEBHOOK_AUTH=off` opts out.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let allowed_ips = env::var("WEBHOOK_ALLOWED_IPS")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|s| !s.trim().is_empty())
+            .filter_map(|s| {
+                let r = IpRange::parse(s);
+                if r.is_none() {
+                    log::warn!("Ignoring invalid WEBHOOK_ALLOWED_IPS entry {s:?}");
+                }
+                r
+            })
+            .collect();
+        let auth = Self::new(
+            env::var("WEBHOOK_TOKEN").ok(),
+            env::var("WEBHOOK_HMAC_SECRET").ok(),
+            allowed_ips,
+        );
+        if !auth.requires_secret() {
+            if !env::var("WEBHOOK_AUTH").is_ok_and(|v| v.eq_ignore_ascii_case("off")) {
+                anyhow::bail!(
+                    "Set WEBHOOK_TOKEN or WEBHOOK_HMAC_SECRET, or WEBHOOK_AUTH=off to accept unauthenticated webhooks"
+                );
+            }
+            log::warn!("WEBHOOK_AUTH=off; webhooks are unauthenticated");
+        }
+        Ok(auth)
+    }
+
+    pub fn requires_secret(&self) -> bool {
+        self.token.is_some() || self.hmac_secret.is_some()
+    }
+
+    /// Check a request, counting any failure.
+    pub fn verify(&self, req: &HttpRequest, body: &[u8]) -> Result<(), AuthError> {
+        let res = self.check(req, body);
+        if let Err(e) = res {
+            count_failure(e);
+            log::warn!(
+                "Rejected {} {} from {:?}: {e}",
+                req.method(),
+                req.path(),
+                req.peer_addr().map(|a| a.ip())
+            );
+        }
+        res
+    }
+
+    fn check(&self, req: &HttpRequest, body: &[u8]) -> Result<(), AuthError> {
+        if !self.allowed_ips.is_empty() {
+            let ip = req.peer_addr().map(|a| a.ip());
+            if !ip.is_some_and(|ip| self.allowed_ips.iter().any(|r| r.contains(ip))) {
+                return Err(AuthError::IpNotAllowed);
+            }
+        }
+        if !self.requires_secret() {
+            return Ok(());
+        }
+
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|s| s.trim().to_string())
+        };
+
+        if let (Some(secret), Some(sig)) = (&self.hmac_secret, header(SIGNATURE_HEADER)) {
+            let hex_sig = sig.strip_prefix("sha256=").unwrap_or(&sig);
+            let expected = hex::decode(hex_sig).map_err(|_| AuthError::BadSignature)?;
+            let mut mac =
+                Hmac::<Sha256>::new_from_slice(secret).map_err(|_| AuthError::BadSignature)?;
+            mac.update(body);
+            return mac
+                .verify_slice(&expected)
+                .map_err(|_| AuthError::BadSignature);
+        }
+
+        if let Some(token) = &self.token {
+            let presented = header(TOKEN_HEADER)
+                .or_else(|| {
+                    header("Authorization")
+                        .and_then(|a| a.strip_prefix("Bearer ").map(|t| t.trim().to_string()))
+                })
+                .or_else(|| query_param(req.query_string(), "token"));
+            return match presented {
+                Some(p) if constant_time_eq(p.as_bytes(), token.as_bytes()) => Ok(()),
+                Some(_) => Err(AuthError::BadToken),
+                None => Err(AuthError::MissingCredentials),
+            };
+        }
+
+        Err(AuthError::MissingCredentials)
+    }
+}
+
+/// A percent-decoded query parameter
+fn query_param(query: &str, key: &str) -> Option<String> {
+    web::Query::<HashMap<String, String>>::from_query(query)
+        .ok()?
+        .into_inner()
+        .remove(key)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -3,6 +3,7 @@ use reqwest::Client;
 
 use lib::clients::tmdb::get_movie_by_id::get_movie_by_id;
 use lib::clients::tmdb::get_movie_by_id::TmdbMovie;
+use lib::clients::tmdb::get_tv_by_id::{get_tv_by_id, TmdbTv};
 
 pub async fn fetch_movies_batch(
     client: &Client,
@@ -38,3 +39,32 @@ pub async fn fetch_movies_batch(
 
     (ok, err)
 }
+
+pub async fn fetch_tv_batch(client: &Client, ids: &[u32]) -> (Vec<TmdbTv>, Vec<(u32, String)>) {
+    let fetches = stream::iter(ids.iter().copied().map(|id| {
+        let client = client.clone();
+        async move {
+            match get_tv_by_id(&client, id).await {
+                Ok(tv) => Ok(tv),
+                Err(e) => Err((id, format!("{:#}", e))),
+            }
+        }
+    }));
+
+    let mut ok = Vec::new();
+    let mut err = Vec::new();
+
+    let results = fetches.buffer_unordered(12).collect::<Vec<_>>().await;
+
+    for res in results {
+        match res {
+            Ok(tv) => ok.push(tv),
+            Err((id, msg)) => {
+                log::warn!("TMDB tv fetch failed for id={id}: {msg}");
+                err.push((id, msg));
+            }
+        }
+    }
+
+    (ok, err)
+}
@@ -0,0 +1,264 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use lib::scheduler::Schedule;
+use lib::store::Bucket;
+use std::{collections::BTreeMap, env, fmt};
+
+/// How completed watches are grouped into buckets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    /// Back-to-back windows of this many minutes, restarting at local midnight
+    Fixed(u32),
+    /// Local calendar day
+    Day,
+    /// ISO week (Monday to Sunday, local time)
+    Week,
+    /// A bucket closes after this many completed watches
+    Count(usize),
+}
+
+/// A [`Window`] plus the timezone its boundaries are aligned to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BucketStrategy {
+    pub window: Window,
+    pub tz: Tz,
+}
+
+impl Default for BucketStrategy {
+    /// The original behaviour: 6-hour UTC windows tagged like "2025-09-29T12Z"
+    fn default() -> Self {
+        Self {
+            window: Window::Fixed(6 * 60),
+            tz: Tz::UTC,
+        }
+    }
+}
+
+impl fmt::Display for BucketStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.window {
+            Window::Fixed(m) if m % 60 == 0 => write!(f, "fixed:{}h", m / 60)?,
+            Window::Fixed(m) => write!(f, "fixed:{m}m")?,
+            Window::Day => write!(f, "day")?,
+            Window::Week => write!(f, "week")?,
+            Window::Count(n) => return write!(f, "count:{n}"),
+        }
+        write!(f, " ({})", self.tz)
+    }
+}
+
+const DAY_MINUTES: u32 = 24 * 60;
+
+fn parse_minutes(s: &str) -> Option<u32> {
+    let s = s.trim();
+    if let Some(h) = s.strip_suffix('h') {
+        h.parse::<u32>().ok().map(|h| h * 60)
+    } else if let Some(m) = s.strip_suffix('m') {
+        m.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// `naive` as local time in `tz`, or the first instant after it if a DST gap skips it
+fn local_start(tz: Tz, naive: NaiveDateTime) -> DateTime<Utc> {
+    let mut t = naive;
+    // A DST gap has no such local time; step forward until one exists.
+    for _ in 0..4 {
+        if let Some(dt) = tz.from_local_datetime(&t).earliest() {
+            return dt.with_timezone(&Utc);
+        }
+        t += Duration::minutes(30);
+    }
+    Utc.from_utc_datetime(&naive)
+}
+
+impl BucketStrategy {
+    /// `spec` is `fixed:<n>h`, `fixed:<n>m`, `day`, `week` or `count:<n>`; `tz` an IANA name.
+    pub fn parse(spec: &str, tz: &str) -> Result<Self, String> {
+        let tz: Tz = tz
+            .trim()
+            .parse()
+            .map_err(|_| format!("unknown timezone {tz:?}"))?;
+        let spec = spec.trim().to_ascii_lowercase();
+        let window = match spec.split_once(':') {
+            None if spec == "day" => Window::Day,
+            None if spec == "week" => Window::Week,
+            Some(("fixed", len)) => match parse_minutes(len) {
+                Some(m) if m > 0 && m <= DAY_MINUTES => Window::Fixed(m),
+                _ => return Err(format!("fixed window must be 1m..24h, got {len:?}")),
+            },
+            Some(("count", n)) => match n.trim().parse::<usize>() {
+                Ok(n) if n > 0 => Window::Count(n),
+                _ => return Err(format!("count must be a positive number, got {n:?}")),
+            },
+            _ => return Err(format!("unknown bucket strategy {spec:?}")),
+        };
+        Ok(Self { window, tz })
+    }
+
+    /// Reads `BUCKET_STRATEGY` (default `fixed:6h`) and `BUCKET_TZ` (default `UTC`).
+    pub fn from_env() -> Self {
+        // An empty value (as left by .env.example) means the default too.
+        let var = |k: &str| env::var(k).ok().filter(|v| !v.trim().is_empty());
+        let spec = var("BUCKET_STRATEGY").unwrap_or_else(|| "fixed:6h".into());
+        let tz = var("BUCKET_TZ").unwrap_or_else(|| "UTC".into());
+        match Self::parse(&spec, &tz) {
+            Ok(s) => s,
+            Err(e) => {
+                log::error!("Invalid BUCKET_STRATEGY/BUCKET_TZ ({e}); using 6h UTC windows");
+                Self::default()
+            }
+        }
+    }
+
+    /// Start of the time window containing `at` (`None` for count-based buckets)
+    pub fn window_start(&self, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let local = at.with_timezone(&self.tz).naive_local();
+        let midnight = local.date().and_hms_opt(0, 0, 0)?;
+        let start = match self.window {
+            Window::Fixed(len) => {
+                let since = (local - midnight).num_minutes() as u32;
+                midnight + Duration::minutes(((since / len) * len) as i64)
+            }
+            Window::Day => midnight,
+            Window::Week => {
+                let back = local.weekday().num_days_from_monday() as i64;
+                midnight - Duration::days(back)
+            }
+            Window::Count(_) => return None,
+        };
+        Some(local_start(self.tz, start))
+    }
+
+    /// End of the time window that starts at `start`
+    pub fn window_end(&self, start: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let local = start.with_timezone(&self.tz).naive_local();
+        let next_midnight = local.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+        let end = match self.window {
+            Window::Fixed(len) => (local + Duration::minutes(len as i64)).min(next_midnight),
+            Window::Day => next_midnight,
+            Window::Week => local
+                .date()
+                .checked_add_days(chrono::Days::new(7))?
+                .and_hms_opt(0, 0, 0)?,
+            Window::Count(_) => return None,
+        };
+        Some(local_start(self.tz, end))
+    }
+
+    /// When the current window closes (`None` for count-based buckets)
+    pub fn next_boundary(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.window_end(self.window_start(now)?)
+    }
+
+    /// Tag for a time-window bucket starting at `start`. 6h-style UTC windows keep the
+    /// original "2025-09-29T12Z" format.
+    fn format_tag(&self, start: DateTime<Utc>) -> String {
+        let local = start.with_timezone(&self.tz);
+        match self.window {
+            Window::Day => local.format("%Y-%m-%d").to_string(),
+            Window::Week => local.format("%G-W%V").to_string(),
+            Window::Count(_) => start.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            Window::Fixed(_) if self.tz == Tz::UTC && start.format("%M").to_string() == "00" => {
+                start.format("%Y-%m-%dT%HZ").to_string()
+            }
+            Window::Fixed(_) if self.tz == Tz::UTC => start.format("%Y-%m-%dT%H:%MZ").to_string(),
+            Window::Fixed(_) => local.format("%Y-%m-%dT%H:%M%:z").to_string(),
+        }
+    }
+
+    /// Which bucket a watch completed at `now` goes into. `latest` is the user's most
+    /// recent bucket as (tag, event count); only count-based buckets look at it.
+    pub fn tag_for(&self, now: DateTime<Utc>, latest: Option<(&str, usize)>) -> String {
+        match (self.window, latest) {
+            (Window::Count(n), Some((tag, count))) if count < n => tag.to_string(),
+            (Window::Count(_), _) => self.format_tag(now),
+            _ => self.format_tag(self.window_start(now).unwrap_or(now)),
+        }
+    }
+
+    /// First instant covered by `tag`, for any tag format this or an older build wrote.
+    /// Date-only and week tags are read in this strategy's timezone.
+    pub fn tag_start(&self, tag: &str) -> Option<DateTime<Utc>> {
+        let tag = tag.trim();
+        if let Some(s) = tag.strip_suffix('Z') {
+            // "2025-09-29T12Z" (the original 6h tags) has no minutes
+            let s = if s.len() == 13 {
+                format!("{s}:00")
+            } else {
+                s.to_string()
+            };
+            for fmt in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"] {
+                if let Ok(n) = NaiveDateTime::parse_from_str(&s, fmt) {
+                    return Some(Utc.from_utc_datetime(&n));
+                }
+            }
+            return None;
+        }
+        if let Ok(dt) = DateTime::parse_from_str(tag, "%Y-%m-%dT%H:%M%:z") {
+            return Some(dt.with_timezone(&Utc));
+        }
+        if let Ok(d) = NaiveDate::parse_from_str(tag, "%Y-%m-%d") {
+            return Some(local_start(self.tz, d.and_hms_opt(0, 0, 0)?));
+        }
+        let (year, week) = tag.split_once("-W")?;
+        let d = NaiveDate::from_isoywd_opt(year.parse().ok()?, week.parse().ok()?, Weekday::Mon)?;
+        Some(local_start(self.tz, d.and_hms_opt(0, 0, 0)?))
+    }
+
+    /// Whether a bucket is finished and ready for recommendations.
+    pub fn is_closed(&self, tag: &str, count: usize, now: DateTime<Utc>) -> bool {
+        if let Window::Count(n) = self.window {
+            return count >= n;
+        }
+        // Tags from a previous strategy are judged by this one's window length.
+        match self.tag_start(tag).and_then(|s| self.window_end(s)) {
+            Some(end) => now >= end,
+            None => true,
+        }
+    }
+}
+
+/// Webhooks can arrive a little after the window they belong to has closed.
+const CLOSE_GRACE_SECS: i64 = 60;
+/// Count-based buckets close on a webhook, so recommendations just sweep this often.
+const COUNT_SWEEP_SECS: i64 = 15 * 60;
+
+/// Default schedule for generating recommendations: a minute after each window closes.
+impl Schedule for BucketStrategy {
+    fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let grace = Duration::seconds(CLOSE_GRACE_SECS);
+        match self.next_boundary(after - grace) {
+            Some(boundary) => Some(boundary + grace),
+            None => {
+                let sweep = (after.timestamp() / COUNT_SWEEP_SECS + 1) * COUNT_SWEEP_SECS;
+                DateTime::from_timestamp(sweep, 0)
+            }
+        }
+    }
+}
+
+/// For each user, the newest bucket that is closed under `strategy` and still has no
+/// recommendations. `unprocessed` is [`Store::unprocessed_buckets`](lib::store::Store::unprocessed_buckets).
+pub fn latest_closed_per_user(
+    strategy: &BucketStrategy,
+    unprocessed: Vec<Bucket>,
+    now: DateTime<Utc>,
+) -> Vec<Bucket> {
+    let mut latest: BTreeMap<Option<String>, Bucket> = BTreeMap::new();
+    for b in unprocessed {
+        if !strategy.is_closed(&b.tag, b.count, now) {
+            continue;
+        }
+        // Ids grow with creation, which breaks ties between unparseable tags.
+        let key = |b: &Bucket| (strategy.tag_start(&b.tag), b.id);
+        match latest.get(&b.user) {
+            Some(cur) if key(cur) >= key(&b) => {}
+            _ => {
+                latest.insert(b.user.clone(), b);
+            }
+        }
+    }
+    latest.into_values().collect()
+}
@@ -0,0 +1,260 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use lib::scheduler::run_exclusive;
+use lib::store::retention::{self, RetentionPolicy, RetentionReport};
+use lib::store::{Bucket, StoreHandle};
+use reqwest::Client;
+use serde::Serialize;
+use std::{fmt, path::Path, time::Duration};
+
+use crate::bucketing::BucketStrategy;
+use crate::server::{RECOMMENDATIONS_JOB, RETENTION_JOB, SIMILARITY_JOB};
+use crate::similarity::{self, RefreshReport};
+
+/// A CLI run shares the scheduled job's lock; give up on a stuck run after this long.
+const MAX_RUNTIME: Duration = Duration::from_secs(60 * 60);
+
+/// Where a bucket is in its lifecycle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    /// Still collecting watches
+    Open,
+    /// Closed, waiting for recommendations
+    Pending,
+    /// `n` recommendations generated at the given time
+    Done(usize, String),
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Open => write!(f, "open"),
+            Status::Pending => write!(f, "pending"),
+            Status::Done(n, at) => write!(f, "{n} recs @ {at}"),
+        }
+    }
+}
+
+/// One row of `list`
+#[derive(Debug, Clone)]
+pub struct BucketLine {
+    pub bucket: Bucket,
+    pub status: Status,
+}
+
+impl fmt::Display for BucketLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let b = &self.bucket;
+        write!(
+            f,
+            "{:<26} {:<12} {:>6}  {}",
+            b.tag,
+            b.user.as_deref().unwrap_or("-"),
+            b.count,
+            self.status
+        )
+    }
+}
+
+/// Newest buckets first, with their status under `strategy`.
+pub async fn list(
+    store: &StoreHandle,
+    strategy: BucketStrategy,
+    user: Option<String>,
+    limit: usize,
+) -> Result<Vec<BucketLine>> {
+    let now = Utc::now();
+    store
+        .read(move |s| {
+            let mut out = Vec::new();
+            for bucket in s.buckets(user.as_deref(), limit)? {
+                let status = match &bucket.recommendations_generated_at {
+                    Some(at) => Status::Done(s.recommendations(bucket.id)?.len(), at.clone()),
+                    None if strategy.is_closed(&bucket.tag, bucket.count, now) => Status::Pending,
+                    None => Status::Open,
+                };
+                out.push(BucketLine { bucket, status });
+            }
+            Ok(out)
+        })
+        .await
+}
+
+/// `YYYY-MM-DD` (midnight in `tz`) or an RFC 3339 timestamp.
+pub fn parse_since(s: &str, tz: Tz) -> Result<DateTime<Utc>> {
+    let s = s.trim();
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Ok(dt.with_timezone(&Utc));
+    }
+    let day = NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map_err(|_| anyhow!("expected YYYY-MM-DD or an RFC 3339 time, got {s:?}"))?;
+    tz.from_local_datetime(&day.and_hms_opt(0, 0, 0).unwrap_or_default())
+        .earliest()
+        .map(|d| d.with_timezone(&Utc))
+        .ok_or_else(|| anyhow!("{s} has no midnight in {tz}"))
+}
+
+/// Closed buckets that started at or after `since`, oldest first. A bucket whose tag
+/// can't be read falls back to when its first watch was stored.
+pub fn closed_since(
+    strategy: &BucketStrategy,
+    buckets: Vec<Bucket>,
+    since: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> Vec<Bucket> {
+    buckets
+        .into_iter()
+        .filter(|b| strategy.is_closed(&b.tag, b.count, now))
+        .filter(|b| {
+            strategy
+                .tag_start(&b.tag)
+                .or_else(|| {
+                    let started = b.started_at.as_deref()?;
+                    Some(
+                        DateTime::parse_from_rfc3339(started)
+                            .ok()?
+                            .with_timezone(&Utc),
+                    )
+                })
+                .is_some_and(|start| start >= since)
+        })
+        .collect()
+}
+
+/// Buckets tagged `tag`, for one user or (with `None`) all of them.
+pub fn tagged(buckets: Vec<Bucket>, tag: &str, user: Option<&str>) -> Vec<Bucket> {
+    buckets
+        .into_iter()
+        .filter(|b| b.tag == tag && (user.is_none() || b.user.as_deref() == user))
+        .collect()
+}
+
+/// What a batch/backfill/regenerate run did
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct BatchReport {
+    pub buckets: usize,
+    pub recommendations: usize,
+    /// Buckets with no tmdb ids to recommend from
+    pub empty: usize,
+    pub failed: usize,
+}
+
+impl fmt::Display for BatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} buckets: {} recommendations written, {} with nothing to recommend from, {} failed",
+            self.buckets, self.recommendations, self.empty, self.failed
+        )
+    }
+}
+
+/// Generate recommendations for each bucket in turn, holding the `recommendations` job
+/// lock so a scheduled run can't overlap. `Ok(None)` if a run was already in progress.
+pub async fn generate(
+    store: &StoreHandle,
+    client: &Client,
+    buckets: Vec<Bucket>,
+) -> Result<Option<BatchReport>> {
+    run_exclusive(store, RECOMMENDATIONS_JOB, MAX_RUNTIME, || async {
+        let mut report = BatchReport::default();
+        for b in buckets {
+            report.buckets += 1;
+            let res = crate::generate_recommendations_for_bucket(
+                store,
+                &b.tag,
+                b.user.as_deref(),
+                client,
+            );
+            match res.await {
+                Ok(0) => report.empty += 1,
+                Ok(n) => report.recommendations += n,
+                Err(e) => {
+                    log::error!("{e:#}");
+                    report.failed += 1;
+                }
+            }
+        }
+        Ok(report)
+    })
+    .await
+}
+
+/// What `compact` did
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct CompactReport {
+    pub retention: RetentionReport,
+    /// Database plus WAL, in bytes
+    pub size_before: u64,
+    pub size_after: u64,
+}
+
+impl CompactReport {
+    pub fn reclaimed(&self) -> u64 {
+        self.size_before.saturating_sub(self.size_after)
+    }
+}
+
+impl fmt::Display for CompactReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let r = &self.retention;
+        write!(
+            f,
+            "stripped {} raw payloads, archived {} buckets ({} events){}; {} -> {} bytes, {} reclaimed",
+            r.raw_stripped,
+            r.buckets_archived,
+            r.events_archived,
+            if r.files.is_empty() {
+                String::new()
+            } else {
+                format!(" to {}", r.files.join(", "))
+            },
+            self.size_before,
+            self.size_after,
+            self.reclaimed()
+        )
+    }
+}
+
+/// Apply `policy` (archiving only buckets `strategy` has closed, or that have
+/// recommendations), then rebuild the database file at `db_path`. Holds the `retention`
+/// job lock; `Ok(None)` if a scheduled pass is in progress.
+pub async fn compact(
+    store: &StoreHandle,
+    strategy: BucketStrategy,
+    policy: RetentionPolicy,
+    db_path: &Path,
+) -> Result<Option<CompactReport>> {
+    run_exclusive(store, RETENTION_JOB, MAX_RUNTIME, || async {
+        let size_before = retention::db_size(db_path);
+        let retention = store
+            .write(move |s| {
+                let now = Utc::now();
+                let report =
+                    s.apply_retention(&policy, now, |b| strategy.is_closed(&b.tag, b.count, now))?;
+                s.vacuum()?;
+                Ok(report)
+            })
+            .await?;
+        Ok(CompactReport {
+            retention,
+            size_before,
+            size_after: retention::db_size(db_path),
+        })
+    })
+    .await
+}
+
+/// Refresh the local similarity index now. Holds the `similarity_index` job lock;
+/// `Ok(None)` if a scheduled refresh is in progress.
+pub async fn refresh_index(
+    store: &StoreHandle,
+    client: &Client,
+    pool_pages: u32,
+) -> Result<Option<RefreshReport>> {
+    run_exclusive(store, SIMILARITY_JOB, MAX_RUNTIME, || {
+        similarity::refresh(store, client, pool_pages)
+    })
+    .await
+}
@@ -0,0 +1,224 @@
+use lib::store::{EventRow, StoreHandle};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::bucketing::BucketStrategy;
+use crate::users::UserPolicy;
+
+/// Playback lifecycle events we understand, regardless of which server sent them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    Play,
+    Pause,
+    Resume,
+    Stop,
+    Watched,
+    Other,
+}
+
+impl EventKind {
+    pub fn parse(s: &str) -> Self {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "play" | "start" => EventKind::Play,
+            "pause" => EventKind::Pause,
+            "resume" => EventKind::Resume,
+            "stop" => EventKind::Stop,
+            "watched" | "scrobble" => EventKind::Watched,
+            _ => EventKind::Other,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventKind::Play => "play",
+            EventKind::Pause => "pause",
+            EventKind::Resume => "resume",
+            EventKind::Stop => "stop",
+            EventKind::Watched => "watched",
+            EventKind::Other => "other",
+        }
+    }
+}
+
+/// One playback event, normalized from a webhook. This is what gets stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchEvent {
+    pub ts: Option<String>,
+    /// Which integration produced it, e.g. "tautulli"
+    pub source: String,
+    pub event: EventKind,
+    pub user: Option<String>,
+    pub title: String,
+    /// "movie", "episode", … as reported by the source
+    pub media_type: Option<String>,
+    /// TMDB movie id, or for episodes the TMDB id of the series
+    pub tmdb_id: Option<u32>,
+    /// TVDB series id for episodes, used when TMDB's isn't known
+    #[serde(default)]
+    pub tvdb_id: Option<u32>,
+    /// Series name for episodes
+    #[serde(default)]
+    pub show_title: Option<String>,
+    pub progress_percent: Option<f32>,
+    pub duration_secs: Option<u32>,
+    pub rating_key: Option<String>,
+    pub session_id: Option<String>,
+    /// Original request body, kept for debugging/reprocessing
+    pub raw: String,
+}
+
+impl WatchEvent {
+    /// Placeholder for a body we couldn't parse; kept for analytics/reprocessing.
+    pub fn unparsed(source: &str, ts: Option<String>, raw: String) -> Self {
+        WatchEvent {
+            ts,
+            source: source.to_string(),
+            event: EventKind::Other,
+            user: None,
+            title: String::new(),
+            media_type: None,
+            tmdb_id: None,
+            tvdb_id: None,
+            show_title: None,
+            progress_percent: None,
+            duration_secs: None,
+            rating_key: None,
+            session_id: None,
+            raw,
+        }
+    }
+
+    /// Queryable columns plus the whole event as JSON, for the store
+    pub fn to_row(&self) -> EventRow {
+        EventRow {
+            ts: self.ts.clone(),
+            source: self.source.clone(),
+            event: self.event.as_str().to_string(),
+            user: self.user.clone(),
+            title: self.title.clone(),
+            media_type: self.media_type.clone(),
+            tmdb_id: self.tmdb_id,
+            tvdb_id: self.tvdb_id,
+            show_title: self.show_title.clone(),
+            data: serde_json::to_string(self).unwrap_or_default(),
+        }
+    }
+
+    /// Did this event finish a watch? `watched` events and `stop`s past the threshold count;
+    /// when the source reports progress it must also clear the threshold.
+    pub fn is_completed_watch(&self, threshold_percent: f32) -> bool {
+        match self.event {
+            EventKind::Watched => self.progress_percent.is_none_or(|p| p >= threshold_percent),
+            EventKind::Stop => self
+                .progress_percent
+                .is_some_and(|p| p >= threshold_percent),
+            _ => false,
+        }
+    }
+
+    /// Whether `other` reports the same playback, e.g. a Tautulli `watched` and the later
+    /// `stop` of one session, or a Plex scrobble and its `media.stop`: same item, same
+    /// session (or player).
+    pub fn same_playback(&self, other: &WatchEvent) -> bool {
+        self.rating_key.is_some()
+            && self.rating_key == other.rating_key
+            && self.session_id == other.session_id
+            && self.source == other.source
+    }
+}
+
+/// Where incoming events go and what counts as "watched"
+#[derive(Debug, Clone)]
+pub struct IngestConfig {
+    /// Completed watches are bucketed, everything else kept for analytics
+    pub store: StoreHandle,
+    pub buckets: BucketStrategy,
+    pub watched_threshold_percent: f32,
+    pub users: UserPolicy,
+}
+
+impl IngestConfig {
+    /// Reads `WATCHED_THRESHOLD_PERCENT` (default 85, Tautulli's own default) and the
+    /// bucketing strategy (see [`BucketStrategy::from_env`]).
+    pub fn from_env(store: StoreHandle) -> Self {
+        let watched_threshold_percent = std::env::var("WATCHED_THRESHOLD_PERCENT")
+            .ok()
+            .and_then(|s| s.parse::<f32>().ok())
+            .unwrap_or(85.0);
+        Self {
+            store,
+            buckets: BucketStrategy::from_env(),
+            watched_threshold_percent,
+            users: UserPolicy::from_env(),
+        }
+    }
+
+    /// Route an event: completed watches into the user's recommendation bucket, the rest
+    /// (and anything from an ignored account) to analytics.
+    pub async fn ingest(&self, mut event: WatchEvent) {
+        let user = self.users.resolve(event.user.as_deref());
+        if let Some(canonical) = &user {
+            event.user = canonical.clone();
+        }
+        match user {
+            Some(user) if event.is_completed_watch(self.watched_threshold_percent) => {
+                if let Err(e) = crate::append_event(&self.store, self.buckets, user, &event).await {
+                    log::error!("append event failed: {e:#}");
+                }
+            }
+            None => {
+                log::info!("Ignoring watch from {:?} (IGNORED_USERS)", event.user);
+                crate::record_analytics_event(&self.store, &event).await;
+            }
+            Some(_) => {
+                crate::record_analytics_event(&self.store, &event).await;
+            }
+        }
+    }
+}
+
+/// Accept `12`, `12.5`, `"12"` or `""` (→ `None`); webhook templates usually send strings.
+pub fn lenient_f32<'de, D: Deserializer<'de>>(d: D) -> Result<Option<f32>, D::Error> {
+    Ok(match Option::<serde_json::Value>::deserialize(d)? {
+        Some(serde_json::Value::Number(n)) => n.as_f64().map(|f| f as f32),
+        Some(serde_json::Value::String(s)) => s.trim().parse::<f32>().ok(),
+        _ => None,
+    })
+}
+
+/// Like [`lenient_f32`], for whole numbers.
+pub fn lenient_u32<'de, D: Deserializer<'de>>(d: D) -> Result<Option<u32>, D::Error> {
+    Ok(lenient_f32(d)?.filter(|f| *f >= 0.0).map(|f| f as u32))
+}
+
+/// Like [`lenient_f32`], for media-server tick counts that overflow `u32`.
+pub fn lenient_u64<'de, D: Deserializer<'de>>(d: D) -> Result<Option<u64>, D::Error> {
+    Ok(match Option::<serde_json::Value>::deserialize(d)? {
+        Some(serde_json::Value::Number(n)) => n.as_u64(),
+        Some(serde_json::Value::String(s)) => s.trim().parse::<u64>().ok(),
+        _ => None,
+    })
+}
+
+/// Accept `true`, `"True"`, `"true"` or `1`; anything else is `None`/`false`.
+pub fn lenient_bool<'de, D: Deserializer<'de>>(d: D) -> Result<Option<bool>, D::Error> {
+    Ok(match Option::<serde_json::Value>::deserialize(d)? {
+        Some(serde_json::Value::Bool(b)) => Some(b),
+        Some(serde_json::Value::String(s)) => match s.trim().to_ascii_lowercase().as_str() {
+            "true" | "1" => Some(true),
+            "false" | "0" => Some(false),
+            _ => None,
+        },
+        Some(serde_json::Value::Number(n)) => n.as_u64().map(|n| n != 0),
+        _ => None,
+    })
+}
+
+/// Accept a string or number, treating `""` as `None`.
+pub fn lenient_string<'de, D: Deserializer<'de>>(d: D) -> Result<Option<String>, D::Error> {
+    Ok(match Option::<serde_json::Value>::deserialize(d)? {
+        Some(serde_json::Value::String(s)) if !s.trim().is_empty() => Some(s.trim().to_string()),
+        Some(serde_json::Value::Number(n)) => Some(n.to_string()),
+        _ => None,
+    })
+}
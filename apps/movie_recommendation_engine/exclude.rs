@@ -0,0 +1,176 @@
+use lib::clients::plex::get_watched::{get_watched, PlexWatched};
+use lib::clients::radarr::get_movies::{get_movies, RadarrMovie};
+use lib::store::{Feedback, StoreHandle};
+use reqwest::Client;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{collections::HashSet, future::Future, time::Duration};
+
+use crate::feedback::FeedbackKind;
+use crate::recommend::MediaType;
+
+/// Radarr's library and Plex's watched state are fetched at most this often.
+const SOURCE_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+/// A copy up to this old is used when a source can't be reached.
+const SOURCE_STALE_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Where the titles nobody should be recommended come from, besides the watch history
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExclusionConfig {
+    /// Radarr base URL and API key
+    pub radarr: Option<(String, String)>,
+    /// Plex base URL, token and optionally the one library section to read
+    pub plex: Option<(String, String, Option<String>)>,
+    /// At most this many ids per media type go into a prompt
+    pub prompt_limit: usize,
+}
+
+impl ExclusionConfig {
+    /// `RADARR_URL` + `RADARR_API_KEY`, `PLEX_URL` + `PLEX_TOKEN` (+ `PLEX_SECTION`), the
+    /// same settings the dashboard uses, and `EXCLUSION_PROMPT_LIMIT` (default 400).
+    pub fn from_env() -> Self {
+        let var = |k: &str| {
+            std::env::var(k)
+                .ok()
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+        Self {
+            radarr: var("RADARR_URL").zip(var("RADARR_API_KEY")),
+            plex: var("PLEX_URL")
+                .zip(var("PLEX_TOKEN"))
+                .map(|(url, token)| (url, token, var("PLEX_SECTION"))),
+            prompt_limit: var("EXCLUSION_PROMPT_LIMIT")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(400),
+        }
+    }
+}
+
+/// TMDB ids that are never recommended: watched by anyone, already in Radarr, or
+/// marked watched in Plex
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Exclusions {
+    pub movies: HashSet<u32>,
+    pub shows: HashSet<u32>,
+}
+
+impl Exclusions {
+    /// Combine the store's `watched_titles` with Radarr's library and Plex's watched state.
+    pub fn from_sources(
+        history: &[(String, u32)],
+        radarr: &[RadarrMovie],
+        plex: &PlexWatched,
+    ) -> Self {
+        let mut out = Self::default();
+        for (media_type, id) in history {
+            match media_type.as_str() {
+                "tv" => out.shows.insert(*id),
+                _ => out.movies.insert(*id),
+            };
+        }
+        out.movies.extend(radarr.iter().filter_map(|m| m.tmdb_id));
+        out.movies.extend(&plex.movies);
+        out.shows.extend(&plex.shows);
+        out
+    }
+
+    /// Add the titles a user disliked, isn't interested in or has already seen.
+    pub fn add_feedback(&mut self, feedback: &[Feedback]) {
+        for f in feedback {
+            if !FeedbackKind::parse(&f.kind).is_some_and(FeedbackKind::excludes) {
+                continue;
+            }
+            match f.media_type.as_str() {
+                "tv" => self.shows.insert(f.tmdb_id),
+                _ => self.movies.insert(f.tmdb_id),
+            };
+        }
+    }
+
+    pub fn for_type(&self, media_type: MediaType) -> &HashSet<u32> {
+        match media_type {
+            MediaType::Movie => &self.movies,
+            MediaType::Tv => &self.shows,
+        }
+    }
+
+    /// The ids to list in a prompt, lowest first: low TMDB ids are the long-established,
+    /// well-known titles a model reaches for most. The rest are still filtered out of the
+    /// response.
+    pub fn prompt_ids(&self, media_type: MediaType, limit: usize) -> Vec<u32> {
+        let mut ids: Vec<u32> = self.for_type(media_type).iter().copied().collect();
+        ids.sort_unstable();
+        ids.truncate(limit);
+        ids
+    }
+}
+
+/// `fetch`, served from the store's cache for up to [`SOURCE_CACHE_TTL`]. Falls back to
+/// a copy up to [`SOURCE_STALE_TTL`] old, then to nothing, if the fetch fails.
+async fn cached<T, F>(store: &StoreHandle, kind: &'static str, fetch: F) -> T
+where
+    T: Serialize + DeserializeOwned + Default + Send + 'static,
+    F: Future<Output = anyhow::Result<T>>,
+{
+    let read = |max_age: Duration| {
+        store.read(move |s| {
+            Ok(s.cache_get(kind, "all", max_age)?
+                .and_then(|v| serde_json::from_str::<T>(&v).ok()))
+        })
+    };
+    if let Ok(Some(fresh)) = read(SOURCE_CACHE_TTL).await {
+        return fresh;
+    }
+    match fetch.await {
+        Ok(value) => {
+            let json = serde_json::to_string(&value);
+            let res = store.write(move |s| s.cache_put(kind, "all", &json?)).await;
+            if let Err(e) = res {
+                log::warn!("{kind} cache write failed: {e:#}");
+            }
+            value
+        }
+        Err(e) => {
+            log::warn!("Fetching {kind} for exclusions failed: {e:#}");
+            read(SOURCE_STALE_TTL)
+                .await
+                .ok()
+                .flatten()
+                .unwrap_or_default()
+        }
+    }
+}
+
+/// Build the exclusion set. Sources that aren't configured or can't be reached are
+/// left out.
+pub async fn load(store: &StoreHandle, client: &Client, config: &ExclusionConfig) -> Exclusions {
+    let history = match store.read(|s| s.watched_titles()).await {
+        Ok(h) => h,
+        Err(e) => {
+            log::error!("loading watched titles for exclusions failed: {e:#}");
+            Vec::new()
+        }
+    };
+    let radarr: Vec<RadarrMovie> = match &config.radarr {
+        Some((url, key)) => cached(store, "exclude_radarr", get_movies(client, url, key)).await,
+        None => Vec::new(),
+    };
+    let plex = match &config.plex {
+        Some((url, token, section)) => {
+            let fetch = get_watched(client, url, token, section.as_deref());
+            cached(store, "exclude_plex", fetch).await
+        }
+        None => PlexWatched::default(),
+    };
+    let out = Exclusions::from_sources(&history, &radarr, &plex);
+    log::info!(
+        "Excluding {} movies and {} series ({} watched, {} in Radarr, {}+{} watched in Plex)",
+        out.movies.len(),
+        out.shows.len(),
+        history.len(),
+        radarr.len(),
+        plex.movies.len(),
+        plex.shows.len()
+    );
+    out
+}
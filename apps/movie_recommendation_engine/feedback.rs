@@ -0,0 +1,289 @@
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use lib::store::Feedback;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+use crate::auth::WebhookAuth;
+use crate::events::IngestConfig;
+use crate::recommend::MediaType;
+
+/// At most this many liked and disliked titles of each media type go into a prompt.
+pub const PROMPT_LIMIT: usize = 20;
+
+/// What a user thought of a title
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedbackKind {
+    Like,
+    Dislike,
+    NotInterested,
+    /// Already watched somewhere the media server didn't see
+    Seen,
+}
+
+impl FeedbackKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FeedbackKind::Like => "like",
+            FeedbackKind::Dislike => "dislike",
+            FeedbackKind::NotInterested => "not_interested",
+            FeedbackKind::Seen => "seen",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "like" => Some(FeedbackKind::Like),
+            "dislike" => Some(FeedbackKind::Dislike),
+            "not_interested" => Some(FeedbackKind::NotInterested),
+            "seen" => Some(FeedbackKind::Seen),
+            _ => None,
+        }
+    }
+
+    /// Whether the title is never recommended to the user again
+    pub fn excludes(self) -> bool {
+        self != FeedbackKind::Like
+    }
+
+    /// Whether it counts against the recommendation, in prompts and stats
+    pub fn is_negative(self) -> bool {
+        matches!(self, FeedbackKind::Dislike | FeedbackKind::NotInterested)
+    }
+}
+
+fn media_type(f: &Feedback) -> MediaType {
+    match f.media_type.as_str() {
+        "tv" => MediaType::Tv,
+        _ => MediaType::Movie,
+    }
+}
+
+/// Feedback on one media type as the prompt's positive and negative examples, newest first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PromptFeedback {
+    pub liked: Vec<u32>,
+    pub disliked: Vec<u32>,
+}
+
+impl PromptFeedback {
+    /// Up to `limit` of each from `feedback` (newest first, as the store returns it).
+    pub fn new(feedback: &[Feedback], media: MediaType, limit: usize) -> Self {
+        let mut out = Self::default();
+        for f in feedback.iter().filter(|f| media_type(f) == media) {
+            let list = match FeedbackKind::parse(&f.kind) {
+                Some(FeedbackKind::Like) => &mut out.liked,
+                Some(k) if k.is_negative() => &mut out.disliked,
+                _ => continue,
+            };
+            if list.len() < limit {
+                list.push(f.tmdb_id);
+            }
+        }
+        out
+    }
+}
+
+/// Counts of each kind of feedback
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Tally {
+    pub total: usize,
+    pub like: usize,
+    pub dislike: usize,
+    pub not_interested: usize,
+    pub seen: usize,
+    /// Likes out of likes, dislikes and not-interested; `None` without any of those
+    pub like_rate: Option<f64>,
+}
+
+impl Tally {
+    fn add(&mut self, kind: FeedbackKind) {
+        self.total += 1;
+        match kind {
+            FeedbackKind::Like => self.like += 1,
+            FeedbackKind::Dislike => self.dislike += 1,
+            FeedbackKind::NotInterested => self.not_interested += 1,
+            FeedbackKind::Seen => self.seen += 1,
+        }
+        let rated = self.like + self.dislike + self.not_interested;
+        self.like_rate = (rated > 0).then(|| self.like as f64 / rated as f64);
+    }
+}
+
+/// Feedback counts for comparing prompt or recommender changes
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct FeedbackStats {
+    pub all: Tally,
+    /// By the recommender that suggested the title ("llm", "tmdb", "index"), or "none"
+    pub by_source: BTreeMap<String, Tally>,
+    /// "movie" or "tv"
+    pub by_media_type: BTreeMap<String, Tally>,
+}
+
+impl FeedbackStats {
+    /// Tally `feedback` given at or after `since`, e.g. since a prompt change.
+    pub fn new(feedback: &[Feedback], since: Option<DateTime<Utc>>) -> Self {
+        let mut out = Self::default();
+        for f in feedback {
+            if let Some(since) = since {
+                let given =
+                    DateTime::parse_from_rfc3339(&f.created_at).map(|d| d.with_timezone(&Utc));
+                if !given.is_ok_and(|d| d >= since) {
+                    continue;
+                }
+            }
+            let Some(kind) = FeedbackKind::parse(&f.kind) else {
+                continue;
+            };
+            out.all.add(kind);
+            let source = f.source.clone().unwrap_or_else(|| "none".into());
+            out.by_source.entry(source).or_default().add(kind);
+            out.by_media_type
+                .entry(f.media_type.clone())
+                .or_default()
+                .add(kind);
+        }
+        out
+    }
+}
+
+/// Body of `POST /feedback`
+#[derive(Debug, Deserialize)]
+pub struct FeedbackBody {
+    pub tmdb_id: u32,
+    /// "movie" (default) or "tv" for a series
+    #[serde(default)]
+    pub media_type: MediaType,
+    pub kind: FeedbackKind,
+    pub user: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FeedbackQuery {
+    user: Option<String>,
+    /// `YYYY-MM-DD` or RFC 3339; only feedback given since then is counted
+    since: Option<String>,
+}
+
+fn store_error(e: anyhow::Error) -> HttpResponse {
+    log::error!("feedback store error: {e:#}");
+    HttpResponse::InternalServerError().json(json!({ "error": format!("{e:#}") }))
+}
+
+fn ignored_user() -> HttpResponse {
+    HttpResponse::UnprocessableEntity().json(json!({ "error": "user is in IGNORED_USERS" }))
+}
+
+/// `POST /feedback`: a user's like, dislike, not-interested or already-seen on a title.
+/// A later verdict on the same title replaces the earlier one.
+#[post("/feedback")]
+async fn add_feedback(
+    req: HttpRequest,
+    body: web::Bytes,
+    auth: web::Data<WebhookAuth>,
+    ingest: web::Data<IngestConfig>,
+) -> impl Responder {
+    if auth.verify(&req, &body).is_err() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    let fb: FeedbackBody = match serde_json::from_slice(&body) {
+        Ok(f) => f,
+        Err(e) => return HttpResponse::BadRequest().json(json!({ "error": e.to_string() })),
+    };
+    let Some(user) = ingest.users.resolve(fb.user.as_deref()) else {
+        return ignored_user();
+    };
+    let now = crate::now_rfc3339().unwrap_or_default();
+    let stored = ingest
+        .store
+        .write(move |s| {
+            s.put_feedback(
+                user.as_deref(),
+                fb.media_type.as_str(),
+                fb.tmdb_id,
+                fb.kind.as_str(),
+                &now,
+            )
+        })
+        .await;
+    match stored {
+        Ok(f) => HttpResponse::Created().json(f),
+        Err(e) => store_error(e),
+    }
+}
+
+/// `GET /feedback?user=`: one user's feedback (without `user`, the shared buckets'),
+/// newest first
+#[get("/feedback")]
+async fn list_feedback(
+    req: HttpRequest,
+    body: web::Bytes,
+    auth: web::Data<WebhookAuth>,
+    ingest: web::Data<IngestConfig>,
+    query: web::Query<FeedbackQuery>,
+) -> impl Responder {
+    if auth.verify(&req, &body).is_err() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    let Some(user) = ingest.users.resolve(query.user.as_deref()) else {
+        return ignored_user();
+    };
+    match ingest
+        .store
+        .read(move |s| s.feedback(user.as_deref()))
+        .await
+    {
+        Ok(f) => HttpResponse::Ok().json(f),
+        Err(e) => store_error(e),
+    }
+}
+
+/// `GET /feedback/stats?user=&since=`: feedback counts overall, per recommender and per
+/// media type; everyone's unless `user` is given
+#[get("/feedback/stats")]
+async fn feedback_stats(
+    req: HttpRequest,
+    body: web::Bytes,
+    auth: web::Data<WebhookAuth>,
+    ingest: web::Data<IngestConfig>,
+    query: web::Query<FeedbackQuery>,
+) -> impl Responder {
+    if auth.verify(&req, &body).is_err() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    let FeedbackQuery { user, since } = query.into_inner();
+    let since = match since.map(|s| crate::commands::parse_since(&s, ingest.buckets.tz)) {
+        Some(Ok(t)) => Some(t),
+        Some(Err(e)) => {
+            return HttpResponse::BadRequest().json(json!({ "error": format!("since: {e:#}") }))
+        }
+        None => None,
+    };
+    let user = match user {
+        Some(u) => match ingest.users.resolve(Some(&u)) {
+            Some(u) => Some(u),
+            None => return ignored_user(),
+        },
+        None => None,
+    };
+    let feedback = ingest
+        .store
+        .read(move |s| match user {
+            Some(u) => s.feedback(u.as_deref()),
+            None => s.all_feedback(),
+        })
+        .await;
+    match feedback {
+        Ok(f) => HttpResponse::Ok().json(FeedbackStats::new(&f, since)),
+        Err(e) => store_error(e),
+    }
+}
+
+/// Register the feedback endpoints. Needs `WebhookAuth` and `IngestConfig` as app data.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(add_feedback)
+        .service(list_feedback)
+        .service(feedback_stats);
+}
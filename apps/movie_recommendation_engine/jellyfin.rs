@@ -0,0 +1,233 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::events::{lenient_bool, lenient_string, lenient_u64, EventKind, WatchEvent};
+
+/// .NET ticks (100ns) per second, as used by Jellyfin and Emby
+const TICKS_PER_SEC: u64 = 10_000_000;
+
+fn provider_id(ids: &HashMap<String, String>, provider: &str) -> Option<u32> {
+    ids.iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(provider))
+        .and_then(|(_, v)| v.trim().parse().ok())
+}
+
+fn progress(position: Option<u64>, runtime: Option<u64>) -> Option<f32> {
+    match (position, runtime) {
+        (Some(pos), Some(rt)) if rt > 0 => Some(pos as f32 * 100.0 / rt as f32),
+        _ => None,
+    }
+}
+
+/// Playback fields common to Jellyfin and Emby webhooks, before mapping to a [`WatchEvent`].
+struct Playback {
+    source: &'static str,
+    event: EventKind,
+    played_to_completion: bool,
+    user: Option<String>,
+    title: String,
+    item_type: Option<String>,
+    ids: HashMap<String, String>,
+    series_name: Option<String>,
+    position_ticks: Option<u64>,
+    runtime_ticks: Option<u64>,
+    item_id: Option<String>,
+    session_id: Option<String>,
+}
+
+/// Shared mapping: the server's own "played to completion" verdict wins over our threshold.
+fn normalize(p: Playback, ts: Option<String>, raw: String) -> WatchEvent {
+    let Playback {
+        source,
+        event,
+        played_to_completion,
+        user,
+        title,
+        item_type,
+        ids,
+        series_name,
+        position_ticks,
+        runtime_ticks,
+        item_id,
+        session_id,
+    } = p;
+    let media_type = item_type.map(|t| t.to_ascii_lowercase());
+    let episode = media_type.as_deref() == Some("episode");
+    let (event, progress_percent) = if played_to_completion {
+        (EventKind::Watched, None)
+    } else {
+        (event, progress(position_ticks, runtime_ticks))
+    };
+    WatchEvent {
+        ts,
+        source: source.to_string(),
+        event,
+        user,
+        title,
+        media_type,
+        // Episode provider ids are the episode's own, not the series'; the series is
+        // looked up by `show_title` when recommending.
+        tmdb_id: if episode {
+            None
+        } else {
+            provider_id(&ids, "Tmdb")
+        },
+        tvdb_id: None,
+        show_title: if episode { series_name } else { None },
+        progress_percent,
+        duration_secs: runtime_ticks
+            .map(|t| t / TICKS_PER_SEC)
+            .and_then(|s| u32::try_from(s).ok()),
+        rating_key: item_id,
+        session_id,
+        raw,
+    }
+}
+
+/// Body sent by the Jellyfin Webhook plugin's default "Generic" template. Both the
+/// flattened `Provider_tmdb` fields and a `ProviderIds` object are understood.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct JellyfinHook {
+    /// `PlaybackStart`, `PlaybackStop`, `PlaybackProgress`, `UserDataSaved`, `ItemAdded`, …
+    #[serde(default)]
+    pub notification_type: String,
+    #[serde(default, alias = "Username", deserialize_with = "lenient_string")]
+    pub notification_username: Option<String>,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub item_type: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub item_id: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub series_name: Option<String>,
+    #[serde(default, rename = "Provider_tmdb", deserialize_with = "lenient_string")]
+    pub provider_tmdb: Option<String>,
+    #[serde(default)]
+    pub provider_ids: HashMap<String, String>,
+    #[serde(default, deserialize_with = "lenient_bool")]
+    pub played_to_completion: Option<bool>,
+    #[serde(default, deserialize_with = "lenient_u64")]
+    pub playback_position_ticks: Option<u64>,
+    #[serde(default, deserialize_with = "lenient_u64")]
+    pub run_time_ticks: Option<u64>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub device_id: Option<String>,
+}
+
+impl JellyfinHook {
+    pub fn into_event(mut self, ts: Option<String>, raw: String) -> WatchEvent {
+        if let Some(id) = self.provider_tmdb.take() {
+            self.provider_ids.entry("Tmdb".to_string()).or_insert(id);
+        }
+        let event = match self.notification_type.as_str() {
+            "PlaybackStart" => EventKind::Play,
+            "PlaybackStop" => EventKind::Stop,
+            _ => EventKind::Other,
+        };
+        let playback = Playback {
+            source: "jellyfin",
+            event,
+            played_to_completion: self.played_to_completion.unwrap_or(false),
+            user: self.notification_username,
+            title: self.name,
+            item_type: self.item_type,
+            ids: self.provider_ids,
+            series_name: self.series_name,
+            position_ticks: self.playback_position_ticks,
+            runtime_ticks: self.run_time_ticks,
+            item_id: self.item_id,
+            session_id: self.device_id,
+        };
+        normalize(playback, ts, raw)
+    }
+}
+
+/// Body of an Emby Server webhook (_Settings → Notifications → Webhooks_). Emby posts it
+/// either as JSON or as `multipart/form-data` with the JSON in a `data` field.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmbyHook {
+    /// `playback.start`, `playback.pause`, `playback.unpause`, `playback.stop`, `item.markplayed`, …
+    #[serde(default)]
+    pub event: String,
+    pub user: Option<EmbyUser>,
+    pub item: Option<EmbyItem>,
+    pub playback_info: Option<EmbyPlaybackInfo>,
+    pub session: Option<EmbySession>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmbyUser {
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmbyItem {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default, rename = "Type", deserialize_with = "lenient_string")]
+    pub kind: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub id: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub series_name: Option<String>,
+    #[serde(default)]
+    pub provider_ids: HashMap<String, String>,
+    #[serde(default, deserialize_with = "lenient_u64")]
+    pub run_time_ticks: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmbyPlaybackInfo {
+    #[serde(default, deserialize_with = "lenient_bool")]
+    pub played_to_completion: Option<bool>,
+    #[serde(default, deserialize_with = "lenient_u64")]
+    pub position_ticks: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmbySession {
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub id: Option<String>,
+}
+
+impl EmbyHook {
+    pub fn into_event(self, ts: Option<String>, raw: String) -> WatchEvent {
+        let event = match self.event.as_str() {
+            "playback.start" => EventKind::Play,
+            "playback.pause" => EventKind::Pause,
+            "playback.unpause" => EventKind::Resume,
+            "playback.stop" => EventKind::Stop,
+            "item.markplayed" => EventKind::Watched,
+            _ => EventKind::Other,
+        };
+        let played = self
+            .playback_info
+            .as_ref()
+            .and_then(|p| p.played_to_completion)
+            .unwrap_or(false);
+        let item = self.item.unwrap_or_default();
+        let playback = Playback {
+            source: "emby",
+            event,
+            played_to_completion: played,
+            user: self.user.and_then(|u| u.name),
+            title: item.name,
+            item_type: item.kind,
+            ids: item.provider_ids,
+            series_name: item.series_name,
+            position_ticks: self.playback_info.and_then(|p| p.position_ticks),
+            runtime_ticks: item.run_time_ticks,
+            item_id: item.id,
+            session_id: self.session.and_then(|s| s.id),
+        };
+        normalize(playback, ts, raw)
+    }
+}
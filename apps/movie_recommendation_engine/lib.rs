@@ -1,160 +1,94 @@
+use lib::store::{Recommendation, StoreHandle};
 use reqwest::Client;
-use serde::Serialize;
-use std::{
-    fs::{File, OpenOptions},
-    io::{BufRead, BufReader, Write},
-    path::{Path, PathBuf},
-};
+use std::collections::HashSet;
 use time::{OffsetDateTime, UtcOffset};
 
+pub mod admin;
+pub mod auth;
 pub mod batch_movies_request;
+pub mod bucketing;
+pub mod commands;
+pub mod events;
+pub mod exclude;
+pub mod feedback;
+pub mod jellyfin;
+pub mod plex;
+pub mod recommend;
+pub mod related;
+pub mod resolve;
 pub mod server;
-use lib::clients::openai::get_recommendations;
+pub mod similarity;
+pub mod taste;
+pub mod tautulli;
+pub mod users;
+pub mod validate;
 
-// ----------------- helpers -----------------
-
-fn write_batches(path: &Path, batches: &[serde_json::Value]) -> std::io::Result<()> {
-    let mut tmp = PathBuf::from(path);
-    if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
-        tmp.set_extension(format!("{}.tmp", ext));
-    } else {
-        tmp.set_extension("tmp");
-    }
-
-    let mut f = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(&tmp)?;
-    for b in batches {
-        writeln!(f, "{}", b)?;
-    }
-    std::fs::rename(&tmp, path)?;
-    Ok(())
-}
-
-fn read_batches(path: &Path) -> Vec<serde_json::Value> {
-    let mut batches = Vec::new();
-    if path.exists() {
-        if let Ok(f) = File::open(path) {
-            for l in BufReader::new(f).lines().flatten() {
-                match serde_json::from_str::<serde_json::Value>(&l) {
-                    Ok(v) => batches.push(v),
-                    Err(_) => batches.push(serde_json::json!({ "raw": l })),
-                }
-            }
-        } else {
-            log::warn!("Could not open NDJSON for read ({})", path.display());
-        }
-    }
-    batches
-}
-
-// Keep the LLM input compact to avoid token bloat.
-#[derive(Serialize)]
-struct LlmMovie {
-    tmdb_id: u32,
-    title: String,
-    year: Option<u16>,
-    genres: Vec<String>,
-    runtime_min: Option<u32>,
-    vote_avg: Option<f64>,
-    overview: Option<String>,
-}
-
-// Output schema we expect back from OpenAI (JSON mode)
-#[derive(serde::Deserialize)]
-struct RecOut {
-    bucket: String,
-    count: usize,
-    recommendations: Vec<RecItem>,
-}
-#[derive(serde::Deserialize, serde::Serialize)]
-struct RecItem {
-    tmdb_id: u32,
-}
+use bucketing::BucketStrategy;
+use events::WatchEvent;
+use recommend::MediaType;
 
-// Helper to pull a year out of "YYYY-MM-DD"
-fn year_from_release_date(d: &Option<String>) -> Option<u16> {
-    d.as_ref()
-        .and_then(|s| s.get(0..4))
-        .and_then(|y| y.parse::<u16>().ok())
-}
+// ----------------- helpers -----------------
 
-fn six_hour_bucket_tag(now_utc: OffsetDateTime) -> String {
-    let hour = now_utc.hour();
-    let bucket_start = (hour / 6) * 6; // 0,6,12,18
-    format!(
-        "{:04}-{:02}-{:02}T{:02}Z",
-        now_utc.year(),
-        u8::from(now_utc.month()) as u8,
-        now_utc.day(),
-        bucket_start
-    )
+fn now_rfc3339() -> Option<String> {
+    OffsetDateTime::now_utc()
+        .to_offset(UtcOffset::UTC)
+        .format(&time::format_description::well_known::Rfc3339)
+        .ok()
 }
 
 /* ---------------------------
 1) EVENT-DRIVEN: append only
 --------------------------- */
 
-/// Append/mutate NDJSON so each 6h window is one line. **No OpenAI here.**
-pub fn append_event_to_ndjson(path: &str, line: &str) {
-    // Parse event
-    let event: serde_json::Value = match serde_json::from_str(line) {
-        Ok(v) => v,
-        Err(e) => {
-            log::error!("event JSON not valid; skipping: {e}");
-            return;
-        }
-    };
-
-    // Bucket values
-    let now_utc = OffsetDateTime::now_utc().to_offset(UtcOffset::UTC);
-    let bucket = six_hour_bucket_tag(now_utc);
-    let now_iso = now_utc
-        .format(&time::format_description::well_known::Rfc3339)
-        .ok();
-
-    let p = Path::new(path);
-    if let Some(parent) = p.parent() {
-        let _ = std::fs::create_dir_all(parent);
-    }
-
-    // Read, upsert, write
-    let mut batches = read_batches(p);
-
-    let mut found = false;
-    for b in &mut batches {
-        if b.get("bucket").and_then(|v| v.as_str()) == Some(bucket.as_str()) {
-            let events = if let Some(ev) = b.get_mut("events").and_then(|ev| ev.as_array_mut()) {
-                ev
-            } else {
-                b["events"] = serde_json::json!([]);
-                b.get_mut("events").unwrap().as_array_mut().unwrap()
+/// Add a completed watch to the user's current bucket and return the bucket id. A watch
+/// already recorded for the same playback (see [`WatchEvent::same_playback`]) in the
+/// user's latest or current bucket is kept for analytics only, so it isn't counted twice.
+/// **No OpenAI here.**
+pub async fn append_event(
+    store: &StoreHandle,
+    buckets: BucketStrategy,
+    user: Option<String>,
+    event: &WatchEvent,
+) -> anyhow::Result<i64> {
+    let now = chrono::Utc::now();
+    let now_iso = now_rfc3339().unwrap_or_default();
+
+    let row = event.to_row();
+    let event = event.clone();
+    // Decided under the write lock so count-based buckets can't overfill.
+    store
+        .write(move |s| {
+            let latest = s.latest_bucket(user.as_deref())?;
+            let tag = buckets.tag_for(now, latest.as_ref().map(|b| (b.tag.as_str(), b.count)));
+            let current = match &latest {
+                Some(b) if b.tag == tag => None,
+                _ => s.bucket(&tag, user.as_deref())?,
             };
-            let event_count = {
-                events.push(event.clone());
-                events.len()
-            };
-            b["updated_at"] = serde_json::json!(now_iso.clone());
-            b["count"] = serde_json::json!(event_count);
-            found = true;
-            break;
-        }
-    }
-    if !found {
-        let batch = serde_json::json!({
-            "bucket": bucket,
-            "started_at": now_iso,
-            "updated_at": now_iso,
-            "count": 1usize,
-            "events": [ event ]
-        });
-        batches.push(batch);
-    }
+            for bucket in latest.iter().chain(&current) {
+                let duplicate = s.bucket_events(bucket.id)?.iter().any(|e| {
+                    serde_json::from_str::<WatchEvent>(&e.row.data)
+                        .is_ok_and(|w| w.same_playback(&event))
+                });
+                if duplicate {
+                    log::info!(
+                        "{:?} was already recorded as watched in bucket {}",
+                        event.title,
+                        bucket.tag
+                    );
+                    s.record_event(&row)?;
+                    return Ok(bucket.id);
+                }
+            }
+            s.append_event(&tag, user.as_deref(), &now_iso, &row)
+        })
+        .await
+}
 
-    if let Err(e) = write_batches(p, &batches) {
-        log::error!("write tmp NDJSON failed: {e}");
+/// Keep an event that doesn't feed recommendations (plays, pauses, abandoned stops…).
+pub async fn record_analytics_event(store: &StoreHandle, event: &WatchEvent) {
+    let row = event.to_row();
+    if let Err(e) = store.write(move |s| s.record_event(&row)).await {
+        log::error!("record analytics event failed: {e:#}");
     }
 }
 
@@ -162,215 +96,193 @@ pub fn append_event_to_ndjson(path: &str, line: &str) {
 2) CRON-DRIVEN: generate recommendations
 --------------------------------------- */
 
-/// Generate/overwrite recommendations for a specific bucket tag.
-/// Example bucket tag: "2025-09-29T12Z".
-pub async fn generate_recommendations_for_bucket(path: &str, bucket_tag: &str, client: &Client) {
-    let p = Path::new(path);
-    let mut batches = read_batches(p);
-
-    // Find target bucket (mutable)
-    let mut_bucket = match batches
-        .iter_mut()
-        .find(|b| b.get("bucket").and_then(|v| v.as_str()) == Some(bucket_tag))
-    {
-        Some(b) => b,
-        None => {
-            log::warn!("No batch found for bucket {}", bucket_tag);
-            return;
-        }
+/// Generate/overwrite recommendations for a specific user's bucket tag and return how
+/// many were written (0 if the bucket has nothing to recommend from).
+/// Example bucket tag: "2025-09-29T12Z". `user` is `None` for the shared bucket.
+pub async fn generate_recommendations_for_bucket(
+    store: &StoreHandle,
+    bucket_tag: &str,
+    user: Option<&str>,
+    client: &Client,
+) -> anyhow::Result<usize> {
+    let (tag, owner) = (bucket_tag.to_string(), user.map(String::from));
+    let loaded = store
+        .read(move |s| {
+            let Some(bucket) = s.bucket(&tag, owner.as_deref())? else {
+                return Ok(None);
+            };
+            let events = s.bucket_events(bucket.id)?;
+            Ok(Some((bucket, events)))
+        })
+        .await?;
+    let Some((bucket, events)) = loaded else {
+        anyhow::bail!("No batch found for bucket {} (user {:?})", bucket_tag, user);
     };
+    let events: Vec<serde_json::Value> = events
+        .iter()
+        .filter_map(|e| serde_json::from_str(&e.row.data).ok())
+        .collect();
+    let watched = recommend::collect_watched(&events);
 
-    // Collect tmdb_ids
-    let mut ids: Vec<u32> = Vec::new();
-    if let Some(events) = mut_bucket.get("events").and_then(|e| e.as_array()) {
-        for ev in events {
-            if let Some(id_val) = ev.get("tmdb_id") {
-                if let Some(id) = id_val.as_u64().map(|n| n as u32) {
-                    ids.push(id);
-                } else if let Some(s) = id_val.as_str() {
-                    if let Ok(n) = s.parse::<u32>() {
-                        ids.push(n);
-                    }
-                }
-            }
-        }
-    }
-    ids.sort_unstable();
-    ids.dedup();
-
-    if ids.is_empty() {
+    if watched.is_empty() {
         log::info!(
-            "No tmdb_ids in bucket {}; skipping OpenAI call.",
-            bucket_tag
+            "No tmdb_ids in bucket {} (user {:?}); skipping OpenAI call.",
+            bucket_tag,
+            user
         );
-        return;
+        return Ok(0);
     }
 
-    // Fetch TMDB details in parallel
-    let (movies, _failures) = batch_movies_request::fetch_movies_batch(client, &ids).await;
+    let now_iso = now_rfc3339().unwrap_or_default();
+
+    // Recent watches set the direction; the long-term profile steers within it.
+    let taste = taste::profile_for(store, client, user, &taste::TasteConfig::from_env())
+        .await
+        .map(|p| p.summary());
+    // Anything anyone has watched, or that's already on the NAS, is never recommended.
+    let exclusion_config = exclude::ExclusionConfig::from_env();
+    let mut exclusions = exclude::load(store, client, &exclusion_config).await;
+    // The user's likes and dislikes steer the prompt; anything they turned down is never
+    // suggested again.
+    let owner = user.map(String::from);
+    let feedback = match store.read(move |s| s.feedback(owner.as_deref())).await {
+        Ok(f) => f,
+        Err(e) => {
+            log::error!("loading feedback for {user:?} failed: {e:#}");
+            Vec::new()
+        }
+    };
+    exclusions.add_feedback(&feedback);
+    let ctx = recommend::Context {
+        taste: taste.as_deref(),
+        exclusions: &exclusions,
+        exclusion_limit: exclusion_config.prompt_limit,
+        feedback: &feedback,
+    };
 
-    // Prepare compact movie set for LLM
-    let llm_movies: Vec<LlmMovie> = movies
-        .iter()
-        .map(|m| LlmMovie {
-            tmdb_id: m.id,
-            title: m.title.clone(),
-            year: year_from_release_date(&m.release_date),
-            genres: m.genres.iter().map(|g| g.name.clone()).collect(),
-            runtime_min: m.runtime,
-            vote_avg: m.vote_average,
-            overview: m.overview.as_ref().map(|o| {
-                let o = o.trim();
-                if o.len() > 320 {
-                    format!("{}…", &o[..320])
-                } else {
-                    o.to_string()
-                }
-            }),
+    // Without an OpenAI key only the TMDB recommender can help (unless it's off).
+    let mode = related::TmdbMode::from_env();
+    let similarity = similarity::SimilarityConfig::from_env();
+    let ask_llm = mode.uses_llm()
+        && (mode == related::TmdbMode::Off
+            || std::env::var("OPENAI_API_KEY").is_ok_and(|k| !k.trim().is_empty()));
+
+    // Movies and shows are separate prompts; keep whichever succeeded.
+    let movies = if watched.movie_ids.is_empty() {
+        Some(Default::default())
+    } else {
+        let ids = &watched.movie_ids;
+        let target = recommend::MOVIE_TARGET;
+        let excluded: HashSet<u32> = ids.iter().chain(&exclusions.movies).copied().collect();
+        let llm = if ask_llm && similarity.mode.uses_llm() {
+            recommend::recommend_movies(client, bucket_tag, ids, &ctx).await
+        } else {
+            None
+        };
+        // The index stands in for, or alternates with, the LLM; TMDB then fills in as usual.
+        let llm = if similarity.mode.picks() {
+            let picks = similarity::recommend(store, client, ids, &excluded, target).await;
+            let picks = validate::check_picks(client, MediaType::Movie, ids, picks).await;
+            similarity::combine(similarity.mode, llm, picks, target)
+        } else {
+            llm
+        };
+        related::combine(mode, llm, target, || async {
+            let picks =
+                related::recommend(store, client, MediaType::Movie, ids, &excluded, target).await;
+            validate::check_picks(client, MediaType::Movie, ids, picks).await
+        })
+        .await
+    };
+    let movies = match movies {
+        Some(mut r) if similarity.mode == similarity::SimilarityMode::Rerank => {
+            r.items = similarity::rerank(store, client, &watched.movie_ids, r.items).await;
+            Some(r)
+        }
+        m => m,
+    };
+    let shows = if watched.shows.is_empty() {
+        Some(Default::default())
+    } else {
+        let llm = if ask_llm {
+            recommend::recommend_shows(client, bucket_tag, &watched.shows, &ctx).await
+        } else {
+            None
+        };
+        related::combine(mode, llm, recommend::SHOW_TARGET, || async {
+            let ids: Vec<u32> = recommend::resolve_show_ids(client, &watched.shows)
+                .await
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect();
+            let excluded = ids.iter().chain(&exclusions.shows).copied().collect();
+            let target = recommend::SHOW_TARGET;
+            let picks =
+                related::recommend(store, client, MediaType::Tv, &ids, &excluded, target).await;
+            validate::check_picks(client, MediaType::Tv, &ids, picks).await
+        })
+        .await
+    };
+    if movies.is_none() && shows.is_none() {
+        anyhow::bail!("OpenAI returned nothing usable for bucket {bucket_tag}");
+    }
+    let split = |r: Option<recommend::Recommended>| match r {
+        Some(r) => (r.items, (r.report.target > 0).then_some(r.report)),
+        None => (Vec::new(), None),
+    };
+    let ((movies, movie_report), (shows, show_report)) = (split(movies), split(shows));
+    let report = serde_json::to_string(&validate::BucketReport {
+        movies: movie_report,
+        shows: show_report,
+    })?;
+    let recs: Vec<Recommendation> = movies
+        .into_iter()
+        .chain(shows)
+        .map(|r| Recommendation {
+            tmdb_id: r.tmdb_id,
+            media_type: r.media_type.as_str().to_string(),
+            confidence: r.confidence,
+            source: Some(r.source.as_str().to_string()),
+            reason: r.reason,
+            because_of: r.because_of,
         })
         .collect();
 
-    let watched_ids_json = serde_json::to_string(&ids).unwrap_or("[]".to_string());
-    let llm_movies_json = serde_json::to_string(&llm_movies).unwrap_or("[]".to_string());
-
-    // Use the batch's updated_at if present (nice to stamp output), else now
-    let now_iso = OffsetDateTime::now_utc()
-        .to_offset(UtcOffset::UTC)
-        .format(&time::format_description::well_known::Rfc3339)
-        .ok();
-
-    let prompt = format!(
-        r#"
-        You are a movie recommendation engine. Return **ONLY** a JSON object (no prose).
-
-        Input:
-        - bucket: "{bucket}"
-        - watched_tmdb_ids: {watched_tmdb_ids}
-        - watched_details: {watched_details}
-
-        Task:
-        Given the user's recently watched movies, return **20** recommended movies that are closely adjacent to what was watched (same franchise/series/spin-off, direct sequels/prequels, or clear thematic/plot-device links like time travel, AI/robots, dystopia, epic fantasy quest). Stay in the same core genres; avoid genre drift.
-
-        Rules:
-        - Output **exactly** this JSON shape (no extra fields):
-        {{ "bucket": "string", "count": 20, "recommendations": [ {{ "tmdb_id": <integer> }}, ... ] }}
-        - All `tmdb_id` values must be integers.
-        - Do **not** include any id in `watched_tmdb_ids` or duplicate any suggestion.
-        - Stay within the watched genres (sci-fi/action/fantasy here); exclude romance/holiday/family drama/war/western unless those genres appear in the watched list. Avoid adult or X-rated content. Avoid broad comedy picks unless they are explicitly in the same franchise.
-        - Prefer well-rated, recognizable titles (vote_avg ≥ 6.5 when possible).
-        - Do not add explanations or claims about franchise/universe/characters. Omit any reason text.
-        - Prefer diversity across years but keep genre/tone alignment; mix obvious franchise-adjacent picks with a few close surprises.
-        - Mix seasonality in as well: for example, if it's September or October, recommend more horror movies, or if it's November or December, recommend more christmas movies, etc.
-
-        Return only the JSON object.
-        "#,
-        bucket = bucket_tag,
-        watched_tmdb_ids = watched_ids_json,
-        watched_details = llm_movies_json
-    );
-
-    let t0 = std::time::Instant::now();
+    // Overwrite recommendations. Only this bucket's recommendation rows are replaced, so
+    // events appended while OpenAI was thinking are kept.
+    let count = recs.len();
+    store
+        .write(move |s| {
+            s.set_recommendations(bucket.id, &recs, &now_iso)?;
+            s.set_recommendation_report(bucket.id, &report)
+        })
+        .await?;
     log::info!(
-        "Calling OpenAI for bucket {} ({} ids)…",
+        "Wrote {} recommendations to bucket {} (user {:?})",
+        count,
         bucket_tag,
-        ids.len()
+        user
     );
-    match get_recommendations(client, &prompt).await {
-        Ok(json_only) => {
-            let parsed: Result<RecOut, _> = serde_json::from_str(&json_only);
-            match parsed {
-                Ok(rec_out) => {
-                    if rec_out.bucket != bucket_tag {
-                        log::warn!(
-                            "OpenAI bucket mismatch: expected {}, got {}",
-                            bucket_tag,
-                            rec_out.bucket
-                        );
-                    }
-                    // Enforce no watched or duplicate ids even if the model errs.
-                    let watched: std::collections::HashSet<u32> = ids.iter().copied().collect();
-                    let mut seen: std::collections::HashSet<u32> = std::collections::HashSet::new();
-                    let orig_len = rec_out.recommendations.len();
-                    let filtered: Vec<RecItem> = rec_out
-                        .recommendations
-                        .into_iter()
-                        .filter(|r| !watched.contains(&r.tmdb_id))
-                        .filter(|r| seen.insert(r.tmdb_id))
-                        .collect();
-                    if filtered.len() < orig_len {
-                        log::info!(
-                            "Filtered out {} watched/duplicate recs (kept {}).",
-                            orig_len - filtered.len(),
-                            filtered.len()
-                        );
-                    }
-                    // Overwrite recommendations
-                    let rec_val = serde_json::to_value(&filtered).unwrap_or(serde_json::json!([]));
-                    mut_bucket["recommendations"] = rec_val;
-                    mut_bucket["recommendations_generated_at"] = serde_json::json!(now_iso);
-
-                    if let Err(e) = write_batches(p, &batches) {
-                        log::error!("Failed to write recommendations to NDJSON: {e}");
-                    } else {
-                        log::info!(
-                            "Wrote {} recommendations to bucket {} in {:?}",
-                            rec_out.count,
-                            bucket_tag,
-                            t0.elapsed()
-                        );
-                    }
-                }
-                Err(e) => {
-                    log::error!("OpenAI JSON parse failed: {e}. Raw: {}", json_only);
-                }
-            }
-        }
-        Err(e) => {
-            log::error!("OpenAI call failed after {:?}: {:#}", t0.elapsed(), e);
-        }
-    }
+    Ok(count)
 }
 
-/// Convenience for cron: operate on the *latest* bucket (by `updated_at` or `started_at`).
-pub async fn generate_recommendations_for_latest_bucket(path: &str, client: &Client) {
-    let p = Path::new(path);
-    let batches = read_batches(p);
-    if batches.is_empty() {
-        log::info!("No batches found.");
-        return;
+/// Convenience for cron: for each user, operate on their *latest* bucket that is closed
+/// under `buckets` and has no recommendations yet.
+pub async fn generate_recommendations_for_latest_bucket(
+    store: &StoreHandle,
+    buckets: &BucketStrategy,
+    client: &Client,
+) -> anyhow::Result<()> {
+    let pending = store.read(|s| s.unprocessed_buckets()).await?;
+    let pending = bucketing::latest_closed_per_user(buckets, pending, chrono::Utc::now());
+    if pending.is_empty() {
+        log::info!("Could not resolve a latest bucket to process.");
     }
-
-    // Pick the latest by `updated_at` (fallback to `started_at` if missing)
-    let mut latest: Option<(&str, OffsetDateTime)> = None;
-
-    for b in &batches {
-        if let Some(bucket_str) = b.get("bucket").and_then(|v| v.as_str()) {
-            // Skip buckets that already have recommendations
-            if b.get("recommendations").is_some() {
-                continue;
-            }
-
-            // choose updated_at > started_at fallback
-            let ts_str = b
-                .get("updated_at")
-                .and_then(|v| v.as_str())
-                .or_else(|| b.get("started_at").and_then(|v| v.as_str()));
-
-            if let Some(ts_str) = ts_str {
-                if let Ok(ts) =
-                    OffsetDateTime::parse(ts_str, &time::format_description::well_known::Rfc3339)
-                {
-                    if latest.map(|(_, t)| ts > t).unwrap_or(true) {
-                        latest = Some((bucket_str, ts));
-                    }
-                }
-            }
+    for b in pending {
+        let res = generate_recommendations_for_bucket(store, &b.tag, b.user.as_deref(), client);
+        if let Err(e) = res.await {
+            log::error!("{e:#}");
         }
     }
-
-    if let Some((bucket, _)) = latest {
-        generate_recommendations_for_bucket(path, bucket, client).await;
-    } else {
-        log::info!("Could not resolve a latest bucket to process.");
-    }
+    Ok(())
 }
@@ -1,26 +1,218 @@
 use clap::Parser;
+use lib::store::retention::RetentionPolicy;
+use lib::store::{Bucket, Store, StoreHandle};
+use movie_recommendation_engine::similarity::SimilarityConfig;
+use movie_recommendation_engine::{bucketing, bucketing::BucketStrategy, commands};
+use std::path::{Path, PathBuf};
 
 #[derive(clap::Parser, Debug)]
 #[command(
     name = "movie_recommendation_engine",
-    about = "Tautulli webhook listener + (future) batch LLM job"
+    about = "Media-server webhook listener + batch LLM recommendations"
 )]
 struct Args {
-    /// Run mode: "serve" (default). We can add "batch" later.
-    #[arg(long, default_value = "serve")]
-    mode: String,
+    /// Defaults to `serve`
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    env_logger::init();
-    let args = Args::parse();
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Run the webhook server and the recommendation scheduler
+    Serve,
+    /// One-shot import of the old NDJSON files into SQLite
+    Import {
+        /// The old bucketed NDJSON store
+        #[arg(long)]
+        ndjson: Option<PathBuf>,
+        /// The old analytics NDJSON log, if any
+        #[arg(long)]
+        analytics: Option<PathBuf>,
+    },
+    /// Generate recommendations once and exit
+    Batch {
+        /// Bucket tag, e.g. "2025-09-29T12Z"
+        #[arg(long, conflicts_with = "latest", required_unless_present = "latest")]
+        bucket: Option<String>,
+        /// Each user's latest closed bucket without recommendations (what the scheduler does)
+        #[arg(long)]
+        latest: bool,
+        /// Only this user's bucket (default: every user's bucket with that tag)
+        #[arg(long, requires = "bucket")]
+        user: Option<String>,
+    },
+    /// Generate recommendations for every closed bucket that has none
+    Backfill,
+    /// Regenerate recommendations for closed buckets that started on or after a date
+    Regenerate {
+        /// YYYY-MM-DD (in BUCKET_TZ) or an RFC 3339 time
+        #[arg(long)]
+        since: String,
+    },
+    /// Print buckets with their event counts and recommendation status
+    List {
+        #[arg(long)]
+        user: Option<String>,
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+        /// Buckets moved to the archive instead
+        #[arg(long)]
+        archived: bool,
+    },
+    /// Apply the RETENTION_* policy now, then rebuild the database and report the space reclaimed
+    Compact,
+    /// Grow the local similarity index's pool and fetch metadata for new or expired films
+    Index {
+        /// Pages of TMDB's popular and top-rated lists (default SIMILARITY_POOL_PAGES)
+        #[arg(long)]
+        pages: Option<u32>,
+    },
+}
 
-    match args.mode.as_str() {
-        "serve" => movie_recommendation_engine::server::run_server().await,
-        other => {
-            eprintln!("Unknown mode: {other}. Try --mode serve");
+/// Load the pre-SQLite NDJSON files into `DB_PATH`.
+async fn import(
+    store: &StoreHandle,
+    ndjson: Option<&Path>,
+    analytics: Option<&Path>,
+) -> anyhow::Result<()> {
+    if ndjson.is_none() && analytics.is_none() {
+        anyhow::bail!("import needs --ndjson and/or --analytics");
+    }
+    if let Some(p) = ndjson {
+        let path = p.to_path_buf();
+        let r = store.write(move |s| s.import_ndjson(&path)).await?;
+        println!(
+            "imported {} buckets, {} events, {} recommendations from {} ({} buckets already present, {} bad lines)",
+            r.buckets,
+            r.events,
+            r.recommendations,
+            p.display(),
+            r.skipped_buckets,
+            r.bad_lines
+        );
+    }
+    if let Some(p) = analytics {
+        let path = p.to_path_buf();
+        let r = store
+            .write(move |s| s.import_analytics_ndjson(&path))
+            .await?;
+        println!(
+            "imported {} analytics events from {} ({} bad lines)",
+            r.events,
+            p.display(),
+            r.bad_lines
+        );
+    }
+    Ok(())
+}
+
+/// Generate for `buckets` and print what happened.
+async fn generate(store: &StoreHandle, buckets: Vec<Bucket>) -> anyhow::Result<()> {
+    if buckets.is_empty() {
+        println!("no buckets to process");
+        return Ok(());
+    }
+    let client = reqwest::Client::new();
+    match commands::generate(store, &client, buckets).await? {
+        Some(report) => println!("{report}"),
+        None => println!("recommendations are already being generated; try again later"),
+    }
+    Ok(())
+}
+
+async fn run(command: Command) -> anyhow::Result<()> {
+    if let Command::Serve = command {
+        return Ok(movie_recommendation_engine::server::run_server().await?);
+    }
+    // Through the storage thread so every change holds the write lock like the server's.
+    let db_path = PathBuf::from(Store::path_from_env()?);
+    let store = StoreHandle::spawn(db_path.clone())?;
+    let strategy = BucketStrategy::from_env();
+    let now = chrono::Utc::now();
+    match command {
+        Command::Serve => Ok(()),
+        Command::Import { ndjson, analytics } => {
+            import(&store, ndjson.as_deref(), analytics.as_deref()).await
+        }
+        Command::Batch { latest: true, .. } => {
+            let pending = store.read(|s| s.unprocessed_buckets()).await?;
+            let buckets = bucketing::latest_closed_per_user(&strategy, pending, now);
+            generate(&store, buckets).await
+        }
+        Command::Batch { bucket, user, .. } => {
+            let tag = bucket.unwrap_or_default();
+            let all = store.read(|s| s.all_buckets()).await?;
+            let buckets = commands::tagged(all, &tag, user.as_deref());
+            if buckets.is_empty() {
+                anyhow::bail!("no bucket {tag:?}");
+            }
+            generate(&store, buckets).await
+        }
+        Command::Backfill => {
+            let pending = store.read(|s| s.unprocessed_buckets()).await?;
+            let buckets = pending
+                .into_iter()
+                .filter(|b| strategy.is_closed(&b.tag, b.count, now))
+                .collect();
+            generate(&store, buckets).await
+        }
+        Command::Regenerate { since } => {
+            let since = commands::parse_since(&since, strategy.tz)?;
+            let all = store.read(|s| s.all_buckets()).await?;
+            generate(&store, commands::closed_since(&strategy, all, since, now)).await
+        }
+        Command::List {
+            user,
+            limit,
+            archived: true,
+        } => {
+            println!("{:<26} {:<12} {:>6}  file", "bucket", "user", "events");
+            let archived = store
+                .read(move |s| s.archived_buckets(user.as_deref(), limit))
+                .await?;
+            for a in archived {
+                println!(
+                    "{:<26} {:<12} {:>6}  {}",
+                    a.tag,
+                    a.user.as_deref().unwrap_or("-"),
+                    a.events,
+                    a.file
+                );
+            }
+            Ok(())
+        }
+        Command::List { user, limit, .. } => {
+            println!("{:<26} {:<12} {:>6}  status", "bucket", "user", "events");
+            for line in commands::list(&store, strategy, user, limit).await? {
+                println!("{line}");
+            }
+            Ok(())
+        }
+        Command::Compact => {
+            let policy = RetentionPolicy::from_env(&db_path);
+            match commands::compact(&store, strategy, policy, &db_path).await? {
+                Some(report) => println!("{report}"),
+                None => println!("a retention pass is already running; try again later"),
+            }
+            Ok(())
+        }
+        Command::Index { pages } => {
+            let pages = pages.unwrap_or(SimilarityConfig::from_env().pool_pages);
+            let client = reqwest::Client::new();
+            match commands::refresh_index(&store, &client, pages).await? {
+                Some(report) => println!("{report}"),
+                None => {
+                    println!("the similarity index is already being refreshed; try again later")
+                }
+            }
             Ok(())
         }
     }
 }
+
+#[actix_web::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    let args = Args::parse();
+    run(args.command.unwrap_or(Command::Serve)).await
+}
@@ -0,0 +1,154 @@
+use serde::Deserialize;
+
+use crate::events::{lenient_string, lenient_u32, EventKind, WatchEvent};
+use crate::tautulli::{extract_tmdb_id, extract_tvdb_id};
+
+/// JSON in the `payload` field of a Plex Pass webhook (Settings → Webhooks).
+/// Plex can't send custom headers, so use `?token=` in the webhook URL for auth.
+#[derive(Debug, Deserialize)]
+pub struct PlexHook {
+    /// `media.play`, `media.pause`, `media.resume`, `media.stop`, `media.scrobble`, `library.new`, …
+    #[serde(default)]
+    pub event: String,
+    #[serde(rename = "Account")]
+    pub account: Option<PlexAccount>,
+    #[serde(rename = "Player")]
+    pub player: Option<PlexPlayer>,
+    #[serde(rename = "Metadata")]
+    pub metadata: Option<PlexMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlexAccount {
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlexPlayer {
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub uuid: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlexGuid {
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlexMetadata {
+    /// "movie", "episode", "track", …
+    #[serde(default, rename = "type", deserialize_with = "lenient_string")]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub grandparent_title: Option<String>,
+    /// The series' guid for episodes; carries its TVDB/TMDB id with the legacy agents
+    /// (`com.plexapp.agents.thetvdb://73244?lang=en`), only a `plex://show/…` otherwise
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub grandparent_guid: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub rating_key: Option<String>,
+    /// Milliseconds
+    #[serde(default, deserialize_with = "lenient_u32")]
+    pub duration: Option<u32>,
+    /// Milliseconds
+    #[serde(default, deserialize_with = "lenient_u32")]
+    pub view_offset: Option<u32>,
+    /// External ids (`tmdb://…`, `tvdb://…`, `imdb://…`) of the item itself
+    #[serde(default, rename = "Guid")]
+    pub guids: Vec<PlexGuid>,
+}
+
+impl PlexHook {
+    pub fn into_event(self, ts: Option<String>, raw: String) -> WatchEvent {
+        let event = match self.event.strip_prefix("media.") {
+            Some(kind) => EventKind::parse(kind),
+            None => EventKind::Other,
+        };
+        let meta = self.metadata;
+        let media_type = meta.as_ref().and_then(|m| m.kind.clone());
+        let episode = media_type.as_deref() == Some("episode");
+        // An episode's Guid list only carries the episode's own ids, so the series is taken
+        // from grandparentGuid; failing that it's looked up by show title later.
+        let series_guid = meta
+            .as_ref()
+            .filter(|_| episode)
+            .and_then(|m| m.grandparent_guid.as_deref());
+        let tmdb_id = match series_guid {
+            Some(guid) => extract_tmdb_id(guid),
+            None => meta
+                .as_ref()
+                .filter(|_| !episode)
+                .and_then(|m| m.guids.iter().find_map(|g| extract_tmdb_id(&g.id))),
+        };
+        let tvdb_id = series_guid.and_then(extract_tvdb_id);
+        // Plex decides scrobbles itself (at 90%); only derive progress for other events.
+        let progress_percent = match (&meta, event) {
+            (_, EventKind::Watched) => None,
+            (Some(m), _) => match (m.view_offset, m.duration) {
+                (Some(off), Some(dur)) if dur > 0 => Some(off as f32 * 100.0 / dur as f32),
+                _ => None,
+            },
+            (None, _) => None,
+        };
+        WatchEvent {
+            ts,
+            source: "plex".to_string(),
+            event,
+            user: self.account.and_then(|a| a.title),
+            title: meta.as_ref().map(|m| m.title.clone()).unwrap_or_default(),
+            media_type,
+            tmdb_id,
+            tvdb_id,
+            show_title: meta
+                .as_ref()
+                .filter(|_| episode)
+                .and_then(|m| m.grandparent_title.clone()),
+            progress_percent,
+            duration_secs: meta.as_ref().and_then(|m| m.duration).map(|ms| ms / 1000),
+            rating_key: meta.and_then(|m| m.rating_key),
+            session_id: self.player.and_then(|p| p.uuid),
+            raw,
+        }
+    }
+}
+
+/// Pull one field's bytes out of a `multipart/form-data` body. Plex sends `payload`
+/// (JSON) plus an optional `thumb` JPEG, so this works on bytes, not text.
+pub fn multipart_field(content_type: &str, body: &[u8], name: &str) -> Option<Vec<u8>> {
+    let boundary = content_type
+        .split(';')
+        .map(str::trim)
+        .find_map(|p| p.strip_prefix("boundary="))?
+        .trim_matches('"');
+    let delim = format!("--{boundary}");
+    let want = format!("name=\"{name}\"");
+
+    let mut rest = body;
+    while let Some(start) = find(rest, delim.as_bytes()) {
+        rest = &rest[start + delim.len()..];
+        if rest.starts_with(b"--") {
+            break;
+        }
+        let Some(header_end) = find(rest, b"\r\n\r\n") else {
+            break;
+        };
+        let headers = String::from_utf8_lossy(&rest[..header_end]);
+        let content = &rest[header_end + 4..];
+        let end = find(content, delim.as_bytes()).unwrap_or(content.len());
+        if headers.contains(&want) {
+            let part = &content[..end];
+            let part = part.strip_suffix(b"\r\n").unwrap_or(part);
+            return Some(part.to_vec());
+        }
+        rest = &content[end..];
+    }
+    None
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
@@ -0,0 +1,902 @@
+use futures::{stream, StreamExt};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use lib::clients::openai::get_recommendations;
+use lib::clients::tmdb::find_by_external_id::tmdb_tv_id_for_tvdb;
+use lib::store::Feedback;
+
+use crate::batch_movies_request;
+use crate::exclude::Exclusions;
+use crate::feedback::{self, PromptFeedback};
+use crate::resolve;
+use crate::validate::{self, ValidationReport};
+
+/// Follow-up calls made to replace ids TMDB validation rejected
+const MAX_FOLLOW_UPS: usize = 2;
+pub const MOVIE_TARGET: usize = 20;
+pub const SHOW_TARGET: usize = 10;
+
+/// What a recommendation points at on TMDB
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaType {
+    #[default]
+    Movie,
+    Tv,
+}
+
+impl MediaType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MediaType::Movie => "movie",
+            MediaType::Tv => "tv",
+        }
+    }
+}
+
+/// Which recommender produced a recommendation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecSource {
+    #[default]
+    Llm,
+    /// TMDB's related-title lists ([`crate::related`])
+    Tmdb,
+    /// The local similarity index ([`crate::similarity`])
+    Index,
+}
+
+impl RecSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RecSource::Llm => "llm",
+            RecSource::Tmdb => "tmdb",
+            RecSource::Index => "index",
+        }
+    }
+}
+
+/// How the LLM names its movie picks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RecommendMode {
+    /// TMDB ids straight from the model
+    #[default]
+    Ids,
+    /// Title and year, resolved through TMDB search
+    Titles,
+}
+
+impl RecommendMode {
+    /// `RECOMMEND_MODE`: `ids` (default) or `titles`. Series are always asked for by id.
+    pub fn from_env() -> Self {
+        match std::env::var("RECOMMEND_MODE")
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "titles" | "title" => RecommendMode::Titles,
+            "" | "ids" | "id" => RecommendMode::Ids,
+            other => {
+                log::error!("Ignoring RECOMMEND_MODE={other:?} (ids or titles)");
+                RecommendMode::Ids
+            }
+        }
+    }
+}
+
+/// One stored recommendation. Buckets written before TV support have no `media_type`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct RecItem {
+    pub tmdb_id: u32,
+    #[serde(default)]
+    pub media_type: MediaType,
+    /// How well a title/year pick matched on TMDB (0-1); `None` when the model gave an id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+    #[serde(default)]
+    pub source: RecSource,
+    /// One short sentence on why it fits
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Tmdb ids of the bucket's watches it follows from
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub because_of: Vec<u32>,
+}
+
+// Keep the LLM input compact to avoid token bloat.
+#[derive(Serialize)]
+struct LlmMovie {
+    tmdb_id: u32,
+    title: String,
+    year: Option<u16>,
+    genres: Vec<String>,
+    runtime_min: Option<u32>,
+    vote_avg: Option<f64>,
+    overview: Option<String>,
+}
+
+#[derive(Serialize)]
+struct LlmShow {
+    tmdb_id: u32,
+    name: String,
+    first_aired: Option<u16>,
+    genres: Vec<String>,
+    episodes_watched: usize,
+    seasons: Option<u32>,
+    vote_avg: Option<f64>,
+    overview: Option<String>,
+}
+
+/// What every prompt for a bucket is given besides its watches
+#[derive(Debug, Clone, Copy)]
+pub struct Context<'a> {
+    /// The user's long-term profile summary, if there is one
+    pub taste: Option<&'a str>,
+    /// Titles never to recommend
+    pub exclusions: &'a Exclusions,
+    /// How many excluded ids of each media type to list in the prompt
+    pub exclusion_limit: usize,
+    /// The user's feedback on earlier recommendations, newest first
+    pub feedback: &'a [Feedback],
+}
+
+impl Context<'_> {
+    fn excluded_json(&self, media_type: MediaType) -> String {
+        let ids = self.exclusions.prompt_ids(media_type, self.exclusion_limit);
+        serde_json::to_string(&ids).unwrap_or("[]".to_string())
+    }
+
+    /// The liked and disliked ids for the prompt, as JSON arrays
+    fn feedback_json(&self, media_type: MediaType) -> (String, String) {
+        let f = PromptFeedback::new(self.feedback, media_type, feedback::PROMPT_LIMIT);
+        let json = |ids: &[u32]| serde_json::to_string(ids).unwrap_or("[]".to_string());
+        (json(&f.liked), json(&f.disliked))
+    }
+}
+
+/// How the prompts are told to use `already_seen_or_owned_tmdb_ids`
+const EXCLUSION_RULE: &str = "- Do **not** include any id in `already_seen_or_owned_tmdb_ids`: the household has already watched or owns those titles (the list is not complete; anything they've seen will be dropped).";
+
+/// How the prompts are told to explain each pick, with the limits `validate` enforces
+fn reason_rule() -> String {
+    format!(
+        "- `reason` is one short sentence (at most {} characters) on why the pick fits, e.g. \"Another Nolan mind-bender about time\". Do not make claims about cast, plot or franchise you are unsure of. `because_of` lists the 1–{} watched tmdb ids from the input the pick follows from most directly.",
+        validate::MAX_REASON_CHARS,
+        validate::MAX_SOURCES
+    )
+}
+
+/// How the prompts are told to use the user's feedback
+const FEEDBACK_RULE: &str = "- `liked_tmdb_ids` and `disliked_tmdb_ids` are earlier recommendations the user rated (newest first). Recommend more like the liked ones and steer away from what the disliked ones have in common. Never recommend any of them again.";
+
+/// How the prompts are told to use `taste_profile`
+const TASTE_RULE: &str = "- `taste_profile` (null if unknown) summarizes the user's whole watch history, weighted towards recent watches: each list is the share of watching time that went to a genre, decade, original language, collection or keyword, and `rewatched` lists favourites seen more than once. The recent watches set the direction; use the profile to choose between candidates and to avoid what the user rarely watches. Do not recommend titles listed in `rewatched`.";
+
+// Output schema we expect back from OpenAI (JSON mode)
+#[derive(serde::Deserialize)]
+struct RecOut {
+    bucket: String,
+    recommendations: Vec<RecIdOut>,
+}
+/// `{ tmdb_id }` in id mode, `{ title, year }` in title mode, each with `reason`,
+/// `because_of` and (movies) `surprise`
+#[derive(serde::Deserialize)]
+struct RecIdOut {
+    tmdb_id: Option<u32>,
+    title: Option<String>,
+    year: Option<serde_json::Value>,
+    reason: Option<String>,
+    because_of: Option<serde_json::Value>,
+    surprise: Option<bool>,
+}
+
+impl RecIdOut {
+    fn year(&self) -> Option<u16> {
+        let y = self.year.as_ref()?;
+        y.as_u64()
+            .and_then(|n| u16::try_from(n).ok())
+            .or_else(|| y.as_str().and_then(|s| s.trim().parse().ok()))
+    }
+
+    /// `because_of` as ids, whether the model gave a list or a single id
+    fn because_of(&self) -> Vec<u32> {
+        let ids = match &self.because_of {
+            Some(serde_json::Value::Array(ids)) => ids.as_slice(),
+            Some(id) => std::slice::from_ref(id),
+            None => &[],
+        };
+        ids.iter()
+            .filter_map(|v| {
+                v.as_u64()
+                    .and_then(|n| u32::try_from(n).ok())
+                    .or_else(|| v.as_str().and_then(|s| s.trim().parse().ok()))
+            })
+            .collect()
+    }
+}
+
+/// A parsed pick: the TMDB id, for a resolved title the match confidence, and why it
+/// was picked
+#[derive(Debug, Clone)]
+struct Pick {
+    tmdb_id: u32,
+    confidence: Option<f64>,
+    reason: Option<String>,
+    because_of: Vec<u32>,
+    /// "Title (year)" as the model gave it, in title mode
+    label: Option<String>,
+    /// Flagged by the model as a close surprise outside the watched genres
+    surprise: bool,
+}
+
+/// A series watched in a bucket, aggregated over its episodes
+#[derive(Debug, Clone, Default)]
+pub struct WatchedShow {
+    pub tmdb_id: Option<u32>,
+    pub tvdb_id: Option<u32>,
+    pub title: Option<String>,
+    pub episodes: usize,
+}
+
+/// What a bucket's completed watches boil down to
+#[derive(Debug, Clone, Default)]
+pub struct WatchedSet {
+    pub movie_ids: Vec<u32>,
+    pub shows: Vec<WatchedShow>,
+}
+
+impl WatchedSet {
+    pub fn is_empty(&self) -> bool {
+        self.movie_ids.is_empty() && self.shows.is_empty()
+    }
+}
+
+fn id_field(ev: &serde_json::Value, key: &str) -> Option<u32> {
+    let v = ev.get(key)?;
+    v.as_u64()
+        .map(|n| n as u32)
+        .or_else(|| v.as_str().and_then(|s| s.parse::<u32>().ok()))
+}
+
+/// Split a bucket's events into movie ids and per-series episode counts.
+pub fn collect_watched(events: &[serde_json::Value]) -> WatchedSet {
+    let mut movie_ids: Vec<u32> = Vec::new();
+    // keyed by tmdb id when known, else tvdb id, else the show's title
+    type ShowKey = (Option<u32>, Option<u32>, Option<String>);
+    let mut shows: BTreeMap<ShowKey, WatchedShow> = BTreeMap::new();
+
+    for ev in events {
+        let is_episode = matches!(
+            ev.get("media_type").and_then(|v| v.as_str()),
+            Some("episode" | "show" | "season")
+        );
+        let tmdb_id = id_field(ev, "tmdb_id");
+        if !is_episode {
+            if let Some(id) = tmdb_id {
+                movie_ids.push(id);
+            }
+            continue;
+        }
+        let tvdb_id = id_field(ev, "tvdb_id");
+        let title = ev
+            .get("show_title")
+            .and_then(|v| v.as_str())
+            .map(str::trim)
+            .filter(|t| !t.is_empty());
+        // Without ids the title is looked up on TMDB later (see `resolve_show_ids`).
+        let key = match (tmdb_id, tvdb_id, title) {
+            (Some(id), ..) => (Some(id), None, None),
+            (None, Some(id), _) => (None, Some(id), None),
+            (None, None, Some(t)) => (None, None, Some(crate::resolve::normalize(t))),
+            (None, None, None) => continue,
+        };
+        let show = shows.entry(key).or_insert_with(|| WatchedShow {
+            tmdb_id,
+            tvdb_id,
+            ..Default::default()
+        });
+        show.episodes += 1;
+        if show.title.is_none() {
+            show.title = title.map(String::from);
+        }
+    }
+    movie_ids.sort_unstable();
+    movie_ids.dedup();
+
+    WatchedSet {
+        movie_ids,
+        shows: shows.into_values().collect(),
+    }
+}
+
+// Helper to pull a year out of "YYYY-MM-DD"
+fn year_from_release_date(d: &Option<String>) -> Option<u16> {
+    d.as_ref()
+        .and_then(|s| s.get(0..4))
+        .and_then(|y| y.parse::<u16>().ok())
+}
+
+fn truncate_overview(o: &Option<String>) -> Option<String> {
+    o.as_ref().map(|o| {
+        let o = o.trim();
+        if o.len() > 320 {
+            let mut end = 320;
+            while !o.is_char_boundary(end) {
+                end -= 1;
+            }
+            format!("{}…", &o[..end])
+        } else {
+            o.to_string()
+        }
+    })
+}
+
+/// Recommendations that passed validation, with what was rejected along the way
+#[derive(Debug, Clone, Default)]
+pub struct Recommended {
+    pub items: Vec<RecItem>,
+    pub report: ValidationReport,
+}
+
+/// Resolve `{ title, year }` picks through TMDB search, a few at a time, keeping the
+/// model's order; unresolvable ones are logged and added to `unresolved`.
+async fn resolve_picks(
+    client: &Client,
+    recs: Vec<RecIdOut>,
+    unresolved: &mut Vec<String>,
+) -> Vec<Pick> {
+    let mut resolved: Vec<(usize, Result<Pick, String>)> =
+        stream::iter(recs.into_iter().enumerate().map(|(i, r)| async move {
+            let because_of = r.because_of();
+            let surprise = r.surprise.unwrap_or(false);
+            if let Some(tmdb_id) = r.tmdb_id {
+                let pick = Pick {
+                    tmdb_id,
+                    confidence: None,
+                    reason: r.reason,
+                    because_of,
+                    label: None,
+                    surprise,
+                };
+                return Some((i, Ok(pick)));
+            }
+            let title = r.title.as_deref().filter(|t| !t.trim().is_empty())?;
+            let year = r.year();
+            let label = match year {
+                Some(y) => format!("{title} ({y})"),
+                None => title.to_string(),
+            };
+            let pick = match resolve::resolve_movie(client, title, year).await {
+                Some((tmdb_id, confidence)) => Ok(Pick {
+                    tmdb_id,
+                    confidence: Some(confidence),
+                    reason: r.reason.clone(),
+                    because_of,
+                    label: Some(label),
+                    surprise,
+                }),
+                None => Err(label),
+            };
+            Some((i, pick))
+        }))
+        .buffer_unordered(8)
+        .filter_map(|r| async move { r })
+        .collect()
+        .await;
+    resolved.sort_by_key(|(i, _)| *i);
+    let mut picks = Vec::with_capacity(resolved.len());
+    for (_, r) in resolved {
+        match r {
+            Ok(pick) => picks.push(pick),
+            Err(label) => {
+                log::warn!("No confident TMDB match for {label:?}; dropping it");
+                unresolved.push(label);
+            }
+        }
+    }
+    picks
+}
+
+/// Ask the LLM, parse `{ bucket, recommendations: [{ tmdb_id } | { title, year }] }`
+/// (resolving titles) and drop excluded, already asked and duplicate ids. Excluded ids
+/// are noted in `report`.
+async fn ask_for_ids(
+    client: &Client,
+    prompt: &str,
+    bucket_tag: &str,
+    excluded: &HashSet<u32>,
+    asked: &HashSet<u32>,
+    what: &str,
+    report: &mut ValidationReport,
+) -> Option<Vec<Pick>> {
+    let t0 = std::time::Instant::now();
+    let json_only = match get_recommendations(client, prompt).await {
+        Ok(j) => j,
+        Err(e) => {
+            log::error!(
+                "OpenAI call for {what} failed after {:?}: {:#}",
+                t0.elapsed(),
+                e
+            );
+            return None;
+        }
+    };
+    let rec_out: RecOut = match serde_json::from_str(&json_only) {
+        Ok(r) => r,
+        Err(e) => {
+            log::error!("OpenAI JSON parse failed: {e}. Raw: {}", json_only);
+            return None;
+        }
+    };
+    if rec_out.bucket != bucket_tag {
+        log::warn!(
+            "OpenAI bucket mismatch: expected {}, got {}",
+            bucket_tag,
+            rec_out.bucket
+        );
+    }
+    let picks = resolve_picks(client, rec_out.recommendations, &mut report.unresolved).await;
+    // Enforce no watched, owned or duplicate ids even if the model errs.
+    let mut seen: HashSet<u32> = HashSet::new();
+    let orig_len = picks.len();
+    let filtered: Vec<Pick> = picks
+        .into_iter()
+        .filter(|p| {
+            let hit = excluded.contains(&p.tmdb_id);
+            if hit && !report.excluded.contains(&p.tmdb_id) {
+                report.excluded.push(p.tmdb_id);
+            }
+            !hit
+        })
+        .filter(|p| !asked.contains(&p.tmdb_id))
+        .filter(|p| seen.insert(p.tmdb_id))
+        .collect();
+    if filtered.len() < orig_len {
+        log::info!(
+            "Filtered out {} watched/owned/duplicate {what} recs (kept {}).",
+            orig_len - filtered.len(),
+            filtered.len()
+        );
+    }
+    log::info!(
+        "Got {} {what} recommendations for bucket {} in {:?}",
+        filtered.len(),
+        bucket_tag,
+        t0.elapsed()
+    );
+    Some(filtered)
+}
+
+/// Keep `picks` by id in `picked` and return their ids.
+fn remember(picked: &mut HashMap<u32, Pick>, picks: Vec<Pick>) -> Vec<u32> {
+    picks
+        .into_iter()
+        .map(|p| {
+            let id = p.tmdb_id;
+            picked.insert(id, p);
+            id
+        })
+        .collect()
+}
+
+/// The flagged surprises among `ids`, while `left` (of [`validate::MAX_SURPRISES`]) lasts
+fn surprises(ids: &[u32], picked: &HashMap<u32, Pick>, left: &mut usize) -> HashSet<u32> {
+    let flagged: HashSet<u32> = ids
+        .iter()
+        .filter(|id| picked.get(id).is_some_and(|p| p.surprise))
+        .take(*left)
+        .copied()
+        .collect();
+    *left -= flagged.len();
+    flagged
+}
+
+/// The follow-up's list of earlier picks: "Title (year)" in title mode, where the model
+/// never sees ids, otherwise the ids.
+fn earlier_picks(mode: RecommendMode, ids: &[u32], picked: &HashMap<u32, Pick>) -> String {
+    match mode {
+        RecommendMode::Ids => serde_json::to_string(ids),
+        RecommendMode::Titles => serde_json::to_string(
+            &ids.iter()
+                .filter_map(|id| picked.get(id)?.label.as_deref())
+                .collect::<Vec<_>>(),
+        ),
+    }
+    .unwrap_or("[]".to_string())
+}
+
+/// What one bucket's validated ask is for.
+struct Ask<'a> {
+    bucket_tag: &'a str,
+    /// The bucket's own watches as well as the global exclusions.
+    excluded: &'a HashSet<u32>,
+    media_type: MediaType,
+    mode: RecommendMode,
+    target: usize,
+    watched_genres: &'a HashSet<String>,
+}
+
+/// Ask with `prompt`, check the ids against TMDB and ask again for replacements (up to
+/// `MAX_FOLLOW_UPS` times) until `target` pass. `None` if the first call failed.
+async fn ask_validated(client: &Client, prompt: &str, ask: Ask<'_>) -> Option<Recommended> {
+    let Ask {
+        bucket_tag,
+        excluded,
+        media_type,
+        mode,
+        target,
+        watched_genres,
+    } = ask;
+    let what = media_type_name(media_type);
+    let mut report = ValidationReport {
+        target,
+        ..Default::default()
+    };
+    let mut picked: HashMap<u32, Pick> = HashMap::new();
+    let first = ask_for_ids(
+        client,
+        prompt,
+        bucket_tag,
+        excluded,
+        &HashSet::new(),
+        what,
+        &mut report,
+    )
+    .await?;
+    let first = remember(&mut picked, first);
+    let mut asked: HashSet<u32> = first.iter().copied().collect();
+    let mut surprises_left = validate::MAX_SURPRISES;
+    let flagged = surprises(&first, &picked, &mut surprises_left);
+    let mut accepted = validate::check(
+        client,
+        media_type,
+        &first,
+        watched_genres,
+        &flagged,
+        &mut report,
+    )
+    .await;
+
+    while accepted.len() < target && report.follow_ups < MAX_FOLLOW_UPS {
+        report.follow_ups += 1;
+        let need = target - accepted.len();
+        let rejected: Vec<u32> = report.rejected.iter().map(|r| r.tmdb_id).collect();
+        let (rejected, accepted_list) = (
+            earlier_picks(mode, &rejected, &picked),
+            earlier_picks(mode, &accepted, &picked),
+        );
+        let unresolved = serde_json::to_string(&report.unresolved).unwrap_or("[]".to_string());
+        let lists = match mode {
+            RecommendMode::Ids => format!(
+                r#"- rejected_tmdb_ids: {rejected}
+        - unmatched_titles: {unresolved}
+        - accepted_tmdb_ids: {accepted_list}
+        Return exactly {need} NEW recommendations in the same JSON shape (with "count": {need}), following the same rules. Do not repeat any rejected, accepted or watched id."#
+            ),
+            RecommendMode::Titles => format!(
+                r#"- rejected_titles: {rejected}
+        - unmatched_titles: {unresolved}
+        - accepted_titles: {accepted_list}
+        Return exactly {need} NEW recommendations in the same JSON shape (with "count": {need}), following the same rules. Do not repeat any of these titles or any watched movie."#
+            ),
+        };
+        let follow_up = format!(
+            r#"{prompt}
+
+        Follow-up: some of your earlier picks were rejected (unknown on TMDB, adult, unreleased, or outside the watched genres).
+        {lists}
+        "#
+        );
+        log::info!(
+            "{} of {target} {what} recs passed validation for bucket {bucket_tag}; asking for {need} more",
+            accepted.len()
+        );
+        let more = ask_for_ids(
+            client,
+            &follow_up,
+            bucket_tag,
+            excluded,
+            &asked,
+            what,
+            &mut report,
+        );
+        let Some(more) = more.await.map(|m| remember(&mut picked, m)) else {
+            break;
+        };
+        if more.is_empty() {
+            break;
+        }
+        asked.extend(more.iter().copied());
+        let flagged = surprises(&more, &picked, &mut surprises_left);
+        accepted.extend(
+            validate::check(
+                client,
+                media_type,
+                &more,
+                watched_genres,
+                &flagged,
+                &mut report,
+            )
+            .await,
+        );
+    }
+    accepted.truncate(target);
+    report.accepted = accepted.len();
+    if !report.rejected.is_empty() {
+        log::info!(
+            "Rejected {} {what} recs for bucket {bucket_tag}: {:?}",
+            report.rejected.len(),
+            report.rejected
+        );
+    }
+    Some(Recommended {
+        items: accepted
+            .into_iter()
+            .map(|tmdb_id| {
+                let pick = picked.remove(&tmdb_id);
+                RecItem {
+                    tmdb_id,
+                    media_type,
+                    confidence: pick.as_ref().and_then(|p| p.confidence),
+                    source: RecSource::Llm,
+                    reason: pick.as_ref().and_then(|p| p.reason.clone()),
+                    because_of: pick.map(|p| p.because_of).unwrap_or_default(),
+                }
+            })
+            .collect(),
+        report,
+    })
+}
+
+fn media_type_name(media_type: MediaType) -> &'static str {
+    match media_type {
+        MediaType::Movie => "movie",
+        MediaType::Tv => "show",
+    }
+}
+
+/// The movie prompt's output shape for `mode`
+fn movie_output_rules(mode: RecommendMode) -> String {
+    match mode {
+        RecommendMode::Ids => format!(
+            r#"- Output **exactly** this JSON shape (no extra fields):
+        {{ "bucket": "string", "count": {MOVIE_TARGET}, "recommendations": [ {{ "tmdb_id": <integer>, "reason": "<string>", "because_of": [<integer>, ...], "surprise": <boolean> }}, ... ] }}
+        - All `tmdb_id` values must be integers.
+        - Do **not** include any id in `watched_tmdb_ids` or duplicate any suggestion."#
+        ),
+        RecommendMode::Titles => format!(
+            r#"- Output **exactly** this JSON shape (no extra fields):
+        {{ "bucket": "string", "count": {MOVIE_TARGET}, "recommendations": [ {{ "title": "<string>", "year": <integer>, "reason": "<string>", "because_of": [<integer>, ...], "surprise": <boolean> }}, ... ] }}
+        - `title` is the film's best-known English title and `year` its original theatrical release year; do not give TMDB ids.
+        - Do **not** include any movie in `watched_details` or duplicate any suggestion."#
+        ),
+    }
+}
+
+/// Movie recommendations for the watched movie ids, or `None` if the LLM call failed.
+pub async fn recommend_movies(
+    client: &Client,
+    bucket_tag: &str,
+    ids: &[u32],
+    ctx: &Context<'_>,
+) -> Option<Recommended> {
+    // Fetch TMDB details in parallel
+    let (movies, _failures) = batch_movies_request::fetch_movies_batch(client, ids).await;
+
+    // Prepare compact movie set for LLM
+    let llm_movies: Vec<LlmMovie> = movies
+        .iter()
+        .map(|m| LlmMovie {
+            tmdb_id: m.id,
+            title: m.title.clone(),
+            year: year_from_release_date(&m.release_date),
+            genres: m.genres.iter().map(|g| g.name.clone()).collect(),
+            runtime_min: m.runtime,
+            vote_avg: m.vote_average,
+            overview: truncate_overview(&m.overview),
+        })
+        .collect();
+
+    let watched_ids_json = serde_json::to_string(&ids).unwrap_or("[]".to_string());
+    let llm_movies_json = serde_json::to_string(&llm_movies).unwrap_or("[]".to_string());
+    let (liked, disliked) = ctx.feedback_json(MediaType::Movie);
+    let mode = RecommendMode::from_env();
+
+    let prompt = format!(
+        r#"
+        You are a movie recommendation engine. Return **ONLY** a JSON object (no prose).
+
+        Input:
+        - bucket: "{bucket}"
+        - watched_tmdb_ids: {watched_tmdb_ids}
+        - watched_details: {watched_details}
+        - taste_profile: {taste_profile}
+        - already_seen_or_owned_tmdb_ids: {excluded}
+        - liked_tmdb_ids: {liked}
+        - disliked_tmdb_ids: {disliked}
+
+        Task:
+        Given the user's recently watched movies, return **{target}** recommended movies that are closely adjacent to what was watched (same franchise/series/spin-off, direct sequels/prequels, or clear thematic/plot-device links like time travel, AI/robots, dystopia, epic fantasy quest). Stay in the same core genres; avoid genre drift.
+
+        Rules:
+        {output_rules}
+        - Stay within the watched genres (sci-fi/action/fantasy here); exclude romance/holiday/family drama/war/western unless those genres appear in the watched list. Avoid adult or X-rated content. Avoid broad comedy picks unless they are explicitly in the same franchise.
+        - Prefer well-rated, recognizable titles (vote_avg ≥ 6.5 when possible).
+        {reason_rule}
+        - Prefer diversity across years but keep genre/tone alignment; mix obvious franchise-adjacent picks with a few close surprises.
+        - Set `surprise` to true on the close surprises and seasonal picks that fall outside the watched genres (at most {max_surprises}), and false on every other pick.
+        - Mix seasonality in as well: for example, if it's September or October, recommend more horror movies, or if it's November or December, recommend more christmas movies, etc.
+        {exclusion_rule}
+        {feedback_rule}
+        {taste_rule}
+
+        Return only the JSON object.
+        "#,
+        bucket = bucket_tag,
+        watched_tmdb_ids = watched_ids_json,
+        watched_details = llm_movies_json,
+        target = MOVIE_TARGET,
+        output_rules = movie_output_rules(mode),
+        taste_profile = ctx.taste.unwrap_or("null"),
+        excluded = ctx.excluded_json(MediaType::Movie),
+        liked = liked,
+        disliked = disliked,
+        feedback_rule = FEEDBACK_RULE,
+        exclusion_rule = EXCLUSION_RULE,
+        reason_rule = reason_rule(),
+        taste_rule = TASTE_RULE,
+        max_surprises = validate::MAX_SURPRISES
+    );
+
+    log::info!(
+        "Calling OpenAI for movies in bucket {} ({} ids)…",
+        bucket_tag,
+        ids.len()
+    );
+    let excluded: HashSet<u32> = ids.iter().chain(&ctx.exclusions.movies).copied().collect();
+    let genres: HashSet<String> = llm_movies.into_iter().flat_map(|m| m.genres).collect();
+    let ask = Ask {
+        bucket_tag,
+        excluded: &excluded,
+        media_type: MediaType::Movie,
+        mode,
+        target: MOVIE_TARGET,
+        watched_genres: &genres,
+    };
+    let rec = ask_validated(client, &prompt, ask).await?;
+    Some(attributed(rec, ids))
+}
+
+/// Keep only reasons' sources that were watched in the bucket, noting the rest in the report.
+fn attributed(mut rec: Recommended, watched: &[u32]) -> Recommended {
+    let watched: HashSet<u32> = watched.iter().copied().collect();
+    rec.report.unknown_sources = validate::check_attribution(&mut rec.items, &watched);
+    rec
+}
+
+/// Resolve TVDB-only and title-only shows to TMDB ids (dropping those TMDB doesn't know).
+pub async fn resolve_show_ids(client: &Client, shows: &[WatchedShow]) -> Vec<(u32, usize)> {
+    let mut by_id: BTreeMap<u32, usize> = BTreeMap::new();
+    for s in shows {
+        let id = match (s.tmdb_id, s.tvdb_id) {
+            (Some(id), _) => Some(id),
+            (None, Some(tvdb)) => match tmdb_tv_id_for_tvdb(client, tvdb).await {
+                Ok(id) => id,
+                Err(e) => {
+                    log::warn!("TMDB find failed for tvdb_id={tvdb}: {e:#}");
+                    None
+                }
+            },
+            (None, None) => match &s.title {
+                Some(title) => crate::resolve::resolve_show(client, title).await,
+                None => None,
+            },
+        };
+        match id {
+            Some(id) => *by_id.entry(id).or_default() += s.episodes,
+            None => log::info!("No TMDB id for show {:?} (tvdb {:?})", s.title, s.tvdb_id),
+        }
+    }
+    by_id.into_iter().collect()
+}
+
+/// TV series recommendations for the watched shows, or `None` if the LLM call failed.
+pub async fn recommend_shows(
+    client: &Client,
+    bucket_tag: &str,
+    shows: &[WatchedShow],
+    ctx: &Context<'_>,
+) -> Option<Recommended> {
+    let series = resolve_show_ids(client, shows).await;
+    if series.is_empty() {
+        return Some(Recommended::default());
+    }
+    let ids: Vec<u32> = series.iter().map(|(id, _)| *id).collect();
+    let (tvs, _failures) = batch_movies_request::fetch_tv_batch(client, &ids).await;
+
+    let llm_shows: Vec<LlmShow> = tvs
+        .iter()
+        .map(|t| LlmShow {
+            tmdb_id: t.id,
+            name: t.name.clone(),
+            first_aired: year_from_release_date(&t.first_air_date),
+            genres: t.genres.iter().map(|g| g.name.clone()).collect(),
+            episodes_watched: series
+                .iter()
+                .find(|(id, _)| *id == t.id)
+                .map(|(_, n)| *n)
+                .unwrap_or(0),
+            seasons: t.number_of_seasons,
+            vote_avg: t.vote_average,
+            overview: truncate_overview(&t.overview),
+        })
+        .collect();
+
+    let watched_ids_json = serde_json::to_string(&ids).unwrap_or("[]".to_string());
+    let llm_shows_json = serde_json::to_string(&llm_shows).unwrap_or("[]".to_string());
+
+    let (liked, disliked) = ctx.feedback_json(MediaType::Tv);
+    let prompt = format!(
+        r#"
+        You are a TV series recommendation engine. Return **ONLY** a JSON object (no prose).
+
+        Input:
+        - bucket: "{bucket}"
+        - watched_series_tmdb_ids: {watched_ids}
+        - watched_series: {watched_series}
+        - taste_profile: {taste_profile}
+        - already_seen_or_owned_tmdb_ids: {excluded}
+        - liked_tmdb_ids: {liked}
+        - disliked_tmdb_ids: {disliked}
+
+        Task:
+        Given the TV series the user has recently been watching (with how many episodes of each), return **{target}** recommended TV series that are closely adjacent in genre, tone and premise. Weight series with more episodes watched more heavily.
+
+        Rules:
+        - Output **exactly** this JSON shape (no extra fields):
+        {{ "bucket": "string", "count": {target}, "recommendations": [ {{ "tmdb_id": <integer>, "reason": "<string>", "because_of": [<integer>, ...] }}, ... ] }}
+        - Every `tmdb_id` must be a TMDB **TV series** id (as used by /tv/{{id}}), not a movie or episode id.
+        - Do **not** include any id in `watched_series_tmdb_ids` or duplicate any suggestion.
+        - Avoid adult or X-rated content. Prefer well-rated, recognizable series (vote_avg ≥ 7 when possible).
+        {reason_rule}
+        {exclusion_rule}
+        {feedback_rule}
+        {taste_rule}
+
+        Return only the JSON object.
+        "#,
+        bucket = bucket_tag,
+        watched_ids = watched_ids_json,
+        watched_series = llm_shows_json,
+        target = SHOW_TARGET,
+        taste_profile = ctx.taste.unwrap_or("null"),
+        excluded = ctx.excluded_json(MediaType::Tv),
+        liked = liked,
+        disliked = disliked,
+        feedback_rule = FEEDBACK_RULE,
+        exclusion_rule = EXCLUSION_RULE,
+        reason_rule = reason_rule(),
+        taste_rule = TASTE_RULE
+    );
+
+    log::info!(
+        "Calling OpenAI for shows in bucket {} ({} series)…",
+        bucket_tag,
+        ids.len()
+    );
+    let excluded: HashSet<u32> = ids.iter().chain(&ctx.exclusions.shows).copied().collect();
+    let genres: HashSet<String> = llm_shows.into_iter().flat_map(|s| s.genres).collect();
+    let ask = Ask {
+        bucket_tag,
+        excluded: &excluded,
+        media_type: MediaType::Tv,
+        mode: RecommendMode::Ids,
+        target: SHOW_TARGET,
+        watched_genres: &genres,
+    };
+    let rec = ask_validated(client, &prompt, ask).await?;
+    Some(attributed(rec, &ids))
+}
@@ -0,0 +1,330 @@
+use chrono::{NaiveDate, Utc};
+use futures::{stream, StreamExt};
+use lib::clients::tmdb::get_related::{get_related, RelatedList, RelatedTitle};
+use lib::store::StoreHandle;
+use reqwest::Client;
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    time::Duration,
+};
+
+use crate::recommend::{MediaType, RecItem, RecSource, Recommended};
+use crate::validate::{ValidationReport, MAX_SOURCES};
+
+const RELATED_CACHE_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+const LISTS: [RelatedList; 2] = [RelatedList::Recommendations, RelatedList::Similar];
+
+/// How much one appearance in each list counts; `/similar` is looser than
+/// `/recommendations`.
+fn list_weight(list: RelatedList) -> f64 {
+    match list {
+        RelatedList::Recommendations => 1.0,
+        RelatedList::Similar => 0.5,
+    }
+}
+
+/// Share of the score from how often a title comes up across the watches, its
+/// (vote-count damped) rating and its popularity
+const FREQUENCY_WEIGHT: f64 = 0.6;
+const RATING_WEIGHT: f64 = 0.25;
+const POPULARITY_WEIGHT: f64 = 0.15;
+/// Ratings are pulled towards `PRIOR_RATING` as if it had this many extra votes.
+const PRIOR_VOTES: f64 = 100.0;
+const PRIOR_RATING: f64 = 6.5;
+
+/// When the TMDB recommender is used instead of, or as well as, the LLM
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TmdbMode {
+    /// LLM only
+    Off,
+    /// Fill in when the LLM fails or returns fewer than asked for
+    #[default]
+    Fallback,
+    /// TMDB only; the LLM isn't called
+    Primary,
+    /// Alternate LLM and TMDB picks
+    Blend,
+}
+
+impl TmdbMode {
+    /// `TMDB_RECOMMENDER`: `fallback` (default), `primary`, `blend` or `off`.
+    pub fn from_env() -> Self {
+        match std::env::var("TMDB_RECOMMENDER")
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "" | "fallback" => TmdbMode::Fallback,
+            "primary" => TmdbMode::Primary,
+            "blend" => TmdbMode::Blend,
+            "off" | "none" => TmdbMode::Off,
+            other => {
+                log::error!(
+                    "Ignoring TMDB_RECOMMENDER={other:?} (fallback, primary, blend or off)"
+                );
+                TmdbMode::Fallback
+            }
+        }
+    }
+
+    /// Whether the LLM should be asked at all
+    pub fn uses_llm(self) -> bool {
+        self != TmdbMode::Primary
+    }
+}
+
+/// A candidate and how strongly the watches point at it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scored {
+    pub tmdb_id: u32,
+    pub score: f64,
+    /// Lists it appeared in
+    pub hits: usize,
+    /// The watches whose lists it appeared in, strongest first
+    pub because_of: Vec<u32>,
+}
+
+/// Rank every title in `lists` (keyed by the watch they were fetched for) that isn't
+/// excluded, adult or unreleased (by `today`), best first; ties go to the lower id.
+pub fn score_candidates(
+    lists: &[(u32, RelatedList, Vec<RelatedTitle>)],
+    excluded: &HashSet<u32>,
+    today: NaiveDate,
+) -> Vec<Scored> {
+    struct Tally<'a> {
+        weight: f64,
+        hits: usize,
+        title: &'a RelatedTitle,
+        seeds: Vec<(u32, f64)>,
+    }
+    let released = |t: &RelatedTitle| {
+        t.released()
+            .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+            .is_some_and(|d| d <= today)
+    };
+    let mut tally: HashMap<u32, Tally> = HashMap::new();
+    for (seed, list, titles) in lists {
+        for t in titles {
+            if excluded.contains(&t.id) || t.adult || !released(t) {
+                continue;
+            }
+            let e = tally.entry(t.id).or_insert(Tally {
+                weight: 0.0,
+                hits: 0,
+                title: t,
+                seeds: Vec::new(),
+            });
+            e.weight += list_weight(*list);
+            e.hits += 1;
+            match e.seeds.iter_mut().find(|(s, _)| s == seed) {
+                Some((_, w)) => *w += list_weight(*list),
+                None => e.seeds.push((*seed, list_weight(*list))),
+            }
+        }
+    }
+
+    let max_weight = tally.values().map(|t| t.weight).fold(0.0, f64::max);
+    let max_pop = tally
+        .values()
+        .map(|t| t.title.popularity.unwrap_or(0.0).max(0.0).ln_1p())
+        .fold(0.0, f64::max);
+    let mut out: Vec<Scored> = tally
+        .into_iter()
+        .map(|(tmdb_id, mut t)| {
+            let votes = t.title.vote_count.unwrap_or(0) as f64;
+            let rating = (votes * t.title.vote_average.unwrap_or(0.0) + PRIOR_VOTES * PRIOR_RATING)
+                / (votes + PRIOR_VOTES);
+            let pop = t.title.popularity.unwrap_or(0.0).max(0.0).ln_1p();
+            let score = FREQUENCY_WEIGHT * t.weight / max_weight
+                + RATING_WEIGHT * rating / 10.0
+                + POPULARITY_WEIGHT * if max_pop > 0.0 { pop / max_pop } else { 0.0 };
+            t.seeds.sort_by(|a, b| b.1.total_cmp(&a.1));
+            Scored {
+                tmdb_id,
+                score,
+                hits: t.hits,
+                because_of: t.seeds.iter().take(MAX_SOURCES).map(|(s, _)| *s).collect(),
+            }
+        })
+        .collect();
+    out.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.tmdb_id.cmp(&b.tmdb_id)));
+    out
+}
+
+/// Both related lists for every seed, from the store's cache or TMDB. Failed lookups are
+/// logged and left out.
+async fn related_lists(
+    store: &StoreHandle,
+    client: &Client,
+    media_type: MediaType,
+    seeds: &[u32],
+) -> Vec<(u32, RelatedList, Vec<RelatedTitle>)> {
+    let kind = format!("related_{}", media_type.as_str());
+    let keys: Vec<(u32, RelatedList)> = seeds
+        .iter()
+        .flat_map(|id| LISTS.map(|l| (*id, l)))
+        .collect();
+    let cache_key = |id: u32, list: RelatedList| format!("{id}:{}", list.as_str());
+
+    let (k, wanted) = (kind.clone(), keys.clone());
+    let cached: HashMap<(u32, RelatedList), Vec<RelatedTitle>> = store
+        .read(move |s| {
+            let mut out = HashMap::new();
+            for (id, list) in wanted {
+                if let Some(v) = s.cache_get(&k, &cache_key(id, list), RELATED_CACHE_TTL)? {
+                    if let Ok(titles) = serde_json::from_str(&v) {
+                        out.insert((id, list), titles);
+                    }
+                }
+            }
+            Ok(out)
+        })
+        .await
+        .unwrap_or_default();
+
+    let missing: Vec<_> = keys
+        .into_iter()
+        .filter(|k| !cached.contains_key(k))
+        .collect();
+    let fetched: Vec<_> = stream::iter(missing.into_iter().map(|(id, list)| {
+        let client = client.clone();
+        async move {
+            match get_related(&client, media_type.as_str(), id, list).await {
+                Ok(titles) => Some(((id, list), titles)),
+                Err(e) => {
+                    log::warn!(
+                        "TMDB {} lookup failed ({} {id}): {e:#}",
+                        list.as_str(),
+                        media_type.as_str()
+                    );
+                    None
+                }
+            }
+        }
+    }))
+    .buffer_unordered(8)
+    .filter_map(|r| async move { r })
+    .collect()
+    .await;
+
+    if !fetched.is_empty() {
+        let rows: Vec<_> = fetched
+            .iter()
+            .map(|((id, list), t)| (cache_key(*id, *list), serde_json::to_string(t)))
+            .collect();
+        let res = store
+            .write(move |s| {
+                for (key, value) in rows {
+                    s.cache_put(&kind, &key, &value?)?;
+                }
+                Ok(())
+            })
+            .await;
+        if let Err(e) = res {
+            log::warn!("related titles cache write failed: {e:#}");
+        }
+    }
+    cached
+        .into_iter()
+        .chain(fetched)
+        .map(|((id, list), titles)| (id, list, titles))
+        .collect()
+}
+
+/// Up to `target` titles related to `seeds` (the bucket's watches), best first.
+pub async fn recommend(
+    store: &StoreHandle,
+    client: &Client,
+    media_type: MediaType,
+    seeds: &[u32],
+    excluded: &HashSet<u32>,
+    target: usize,
+) -> Vec<RecItem> {
+    let lists = related_lists(store, client, media_type, seeds).await;
+    let scored = score_candidates(&lists, excluded, Utc::now().date_naive());
+    log::info!(
+        "TMDB recommender: {} {} candidates from {} seeds",
+        scored.len(),
+        media_type.as_str(),
+        seeds.len()
+    );
+    scored
+        .into_iter()
+        .take(target)
+        .map(|s| RecItem {
+            tmdb_id: s.tmdb_id,
+            media_type,
+            source: RecSource::Tmdb,
+            because_of: s.because_of,
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Alternate `llm` and `tmdb` picks (LLM first), skipping repeats, up to `target`.
+pub fn blend(llm: Vec<RecItem>, tmdb: Vec<RecItem>, target: usize) -> Vec<RecItem> {
+    let mut seen = HashSet::new();
+    let (mut llm, mut tmdb) = (llm.into_iter(), tmdb.into_iter());
+    let mut out = Vec::new();
+    while out.len() < target {
+        let (a, b) = (llm.next(), tmdb.next());
+        if a.is_none() && b.is_none() {
+            break;
+        }
+        for r in a.into_iter().chain(b) {
+            if out.len() < target && seen.insert(r.tmdb_id) {
+                out.push(r);
+            }
+        }
+    }
+    out
+}
+
+/// Combine the LLM's result for one media type with the TMDB recommender's as `mode`
+/// says. `tmdb` is only awaited when its picks are needed; it should return them already
+/// checked (see [`crate::validate::check_picks`]), and its rejections join the report.
+pub async fn combine<F>(
+    mode: TmdbMode,
+    llm: Option<Recommended>,
+    target: usize,
+    tmdb: impl FnOnce() -> F,
+) -> Option<Recommended>
+where
+    F: Future<Output = Recommended>,
+{
+    let empty = || Recommended {
+        items: Vec::new(),
+        report: ValidationReport {
+            target,
+            ..Default::default()
+        },
+    };
+    let mut rec = match mode {
+        TmdbMode::Off => return llm,
+        TmdbMode::Fallback if llm.as_ref().is_some_and(|r| r.items.len() >= target) => return llm,
+        _ => llm.unwrap_or_else(empty),
+    };
+    let picks = tmdb().await;
+    rec.report.absorb(picks.report);
+    let picks = picks.items;
+    rec.items = match mode {
+        TmdbMode::Blend => blend(rec.items, picks, target),
+        // The LLM's picks first, topped up from TMDB
+        _ => {
+            let mut items = rec.items;
+            let have: HashSet<u32> = items.iter().map(|r| r.tmdb_id).collect();
+            let room = target.saturating_sub(items.len());
+            items.extend(
+                picks
+                    .into_iter()
+                    .filter(|r| !have.contains(&r.tmdb_id))
+                    .take(room),
+            );
+            items
+        }
+    };
+    rec.report.tally(&rec.items);
+    Some(rec)
+}
@@ -0,0 +1,144 @@
+use lib::clients::tmdb::search_movie::{search_movie, MovieSearchResult};
+use lib::clients::tmdb::search_tv::{search_tv, TvSearchResult};
+use reqwest::Client;
+
+/// Matches scoring below this are treated as unresolvable.
+pub const MIN_CONFIDENCE: f64 = 0.75;
+
+/// Lowercase, "&" as "and", punctuation and a leading article dropped, spaces collapsed.
+pub fn normalize(title: &str) -> String {
+    let lowered = title.to_lowercase().replace('&', " and ");
+    let cleaned: String = lowered
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+    let words: Vec<&str> = cleaned.split_whitespace().collect();
+    let words = match words.first() {
+        Some(&("the" | "a" | "an")) if words.len() > 1 => &words[1..],
+        _ => &words[..],
+    };
+    words.join(" ")
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != cb);
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// 1.0 for titles that normalize the same, falling towards 0 with edit distance.
+pub fn title_similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (normalize(a), normalize(b));
+    if a == b {
+        return 1.0;
+    }
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 0.0;
+    }
+    1.0 - levenshtein(&a, &b) as f64 / longest as f64
+}
+
+/// Release years drift by one between countries and festival/wide releases; anything
+/// further out is a different film.
+fn year_factor(wanted: Option<u16>, released: Option<&str>) -> f64 {
+    let found = released
+        .and_then(|d| d.get(0..4))
+        .and_then(|y| y.parse::<u16>().ok());
+    match (wanted, found) {
+        (Some(w), Some(f)) => match w.abs_diff(f) {
+            0 => 1.0,
+            1 => 0.9,
+            _ => 0.0,
+        },
+        _ => 0.85,
+    }
+}
+
+/// How well a search hit matches a title/year, from 0 to 1.
+pub fn score(title: &str, year: Option<u16>, hit: &MovieSearchResult) -> f64 {
+    let by_title = title_similarity(title, &hit.title);
+    let by_original = hit
+        .original_title
+        .as_deref()
+        .map_or(0.0, |o| title_similarity(title, o));
+    by_title.max(by_original) * year_factor(year, hit.release_date.as_deref())
+}
+
+/// The best-scoring hit at or above [`MIN_CONFIDENCE`], as (tmdb id, confidence). Ties
+/// go to the more-voted film.
+pub fn best_match(
+    title: &str,
+    year: Option<u16>,
+    hits: &[MovieSearchResult],
+) -> Option<(u32, f64)> {
+    hits.iter()
+        .map(|h| (h, score(title, year, h)))
+        .filter(|(_, s)| *s >= MIN_CONFIDENCE)
+        .max_by(|(a, sa), (b, sb)| {
+            sa.total_cmp(sb)
+                .then(a.vote_count.unwrap_or(0).cmp(&b.vote_count.unwrap_or(0)))
+        })
+        .map(|(h, s)| (h.id, (s * 100.0).round() / 100.0))
+}
+
+/// Find a movie on TMDB by title and year: first narrowed to the year, then, if nothing
+/// matches well enough (the year may be off by one), across all years.
+pub async fn resolve_movie(client: &Client, title: &str, year: Option<u16>) -> Option<(u32, f64)> {
+    let mut searches = vec![year];
+    if year.is_some() {
+        searches.push(None);
+    }
+    for y in searches {
+        match search_movie(client, title, y).await {
+            Ok(hits) => {
+                if let Some(m) = best_match(title, year, &hits) {
+                    return Some(m);
+                }
+            }
+            Err(e) => {
+                log::warn!("TMDB search failed for {title:?} ({year:?}): {e:#}");
+                return None;
+            }
+        }
+    }
+    None
+}
+
+/// The series whose name (or original name) matches `name` best, at or above
+/// [`MIN_CONFIDENCE`]. Ties go to the more-voted series.
+pub fn best_show_match(name: &str, hits: &[TvSearchResult]) -> Option<u32> {
+    hits.iter()
+        .map(|h| {
+            let by_original = h
+                .original_name
+                .as_deref()
+                .map_or(0.0, |o| title_similarity(name, o));
+            (h, title_similarity(name, &h.name).max(by_original))
+        })
+        .filter(|(_, s)| *s >= MIN_CONFIDENCE)
+        .max_by(|(a, sa), (b, sb)| {
+            sa.total_cmp(sb)
+                .then(a.vote_count.unwrap_or(0).cmp(&b.vote_count.unwrap_or(0)))
+        })
+        .map(|(h, _)| h.id)
+}
+
+/// Find a series on TMDB by name, for episodes whose source only sent the show's title.
+pub async fn resolve_show(client: &Client, name: &str) -> Option<u32> {
+    match search_tv(client, name).await {
+        Ok(hits) => best_show_match(name, &hits),
+        Err(e) => {
+            log::warn!("TMDB TV search failed for {name:?}: {e:#}");
+            None
+        }
+    }
+}
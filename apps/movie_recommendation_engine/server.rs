@@ -1,85 +1,247 @@
-use actix_web::{get, post, App, HttpResponse, HttpServer, Responder};
+use actix_web::{
+    get, http::header, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder,
+};
 use log::{info, warn};
-use once_cell::sync::Lazy;
-use regex::Regex;
-use serde::Deserialize;
-use std::env;
+use serde::de::DeserializeOwned;
+use std::{env, path::Path};
 use time::OffsetDateTime;
 
-#[derive(Debug, Deserialize)]
-struct Hook {
-    #[serde(default)]
-    event: String,
-    #[serde(default)]
-    user: String,
-    #[serde(default)]
-    title: String,
-    #[serde(default)]
-    guid: String,
-}
+use lib::scheduler::{Cron, Job, Scheduler};
+use lib::store::retention::RetentionPolicy;
+use lib::store::{Store, StoreHandle};
 
-fn extract_tmdb_id(guid: &str) -> Option<String> {
-    static RE1: Lazy<Regex> = Lazy::new(|| Regex::new(r"tmdb://(?P<id>\d+)").unwrap());
-    static RE2: Lazy<Regex> =
-        Lazy::new(|| Regex::new(r"com\.plexapp\.agents\.themoviedb://(?P<id>\d+)").unwrap());
-    RE1.captures(guid)
-        .or_else(|| RE2.captures(guid))
-        .and_then(|c| c.name("id").map(|m| m.as_str().to_string()))
-}
+use crate::auth::WebhookAuth;
+use crate::events::{IngestConfig, WatchEvent};
+use crate::jellyfin::{EmbyHook, JellyfinHook};
+use crate::plex::{multipart_field, PlexHook};
+use crate::similarity::{SimilarityConfig, SimilarityMode};
+use crate::tautulli::TautulliHook;
 
-#[post("/tautulli")]
-async fn tautulli(body: String) -> impl Responder {
-    let mut tmdb_id: Option<String> = None;
-    let mut title_debug = String::new();
+/// Scheduler job name; `JOB_RECOMMENDATIONS_CRON` etc. override its schedule.
+pub const RECOMMENDATIONS_JOB: &str = "recommendations";
+
+/// Strips and archives old history per `RETENTION_*`; daily at 03:30 unless overridden.
+pub const RETENTION_JOB: &str = "retention";
+const RETENTION_CRON: &str = "30 3 * * *";
 
-    match serde_json::from_str::<Hook>(&body) {
+/// Grows and refreshes the local similarity index when `SIMILARITY_MODE` is on; daily at
+/// 04:15 unless overridden.
+pub const SIMILARITY_JOB: &str = "similarity_index";
+const SIMILARITY_CRON: &str = "15 4 * * *";
+
+/// What the webhook endpoints share: check auth, take the JSON from the multipart `field`
+/// if the source posts multipart (plain JSON is accepted too, which makes manual testing
+/// with curl easier), parse it with `into_event` and ingest the event. A body that
+/// doesn't parse is stored for analytics.
+async fn receive<H: DeserializeOwned>(
+    source: &str,
+    field: Option<&str>,
+    into_event: fn(H, Option<String>, String) -> WatchEvent,
+    req: HttpRequest,
+    body: web::Bytes,
+    auth: web::Data<WebhookAuth>,
+    ingest: web::Data<IngestConfig>,
+) -> HttpResponse {
+    if auth.verify(&req, &body).is_err() {
+        return HttpResponse::Unauthorized().finish();
+    }
+    let ts = OffsetDateTime::now_utc()
+        .format(&time::format_description::well_known::Rfc3339)
+        .ok();
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    let payload = match field {
+        Some(name) if content_type.starts_with("multipart/") => {
+            match multipart_field(content_type, &body, name) {
+                Some(p) => p,
+                None => {
+                    warn!("{source} webhook without a {name} field");
+                    return HttpResponse::BadRequest().finish();
+                }
+            }
+        }
+        _ => body.to_vec(),
+    };
+    let payload = String::from_utf8_lossy(&payload).into_owned();
+
+    let event = match serde_json::from_str::<H>(&payload) {
         Ok(h) => {
-            tmdb_id = extract_tmdb_id(&h.guid);
-            title_debug = h.title.clone();
+            let ev = into_event(h, ts, payload);
             info!(
-                "Webhook: event={} user={} title={} tmdb_id={:?}",
-                h.event, h.user, h.title, tmdb_id
+                "{source} webhook: event={:?} user={:?} title={} tmdb_id={:?} progress={:?}",
+                ev.event, ev.user, ev.title, ev.tmdb_id, ev.progress_percent
             );
+            ev
         }
         Err(e) => {
-            warn!("JSON parse failed ({e}). Raw body will be stored.");
+            warn!("{source} payload parse failed ({e}). Raw body will be stored for analytics.");
+            WatchEvent::unparsed(source, ts, payload)
         }
-    }
-
-    let record = serde_json::json!({
-        "ts": OffsetDateTime::now_utc().format(&time::format_description::well_known::Rfc3339).ok(),
-        "title": title_debug,
-        "tmdb_id": tmdb_id,
-        "raw": body,
-    });
+    };
 
-    let log_path = env::var("NDJSON_PATH").unwrap();
-    crate::append_event_to_ndjson(&log_path, &record.to_string());
+    ingest.ingest(event).await;
 
     HttpResponse::Ok().finish()
 }
 
+#[post("/tautulli")]
+async fn tautulli(
+    req: HttpRequest,
+    body: web::Bytes,
+    auth: web::Data<WebhookAuth>,
+    ingest: web::Data<IngestConfig>,
+) -> impl Responder {
+    let parse = TautulliHook::into_event;
+    receive("tautulli", None, parse, req, body, auth, ingest).await
+}
+
+/// Native Plex Pass webhook: `multipart/form-data` with the event JSON in `payload`.
+#[post("/plex")]
+async fn plex(
+    req: HttpRequest,
+    body: web::Bytes,
+    auth: web::Data<WebhookAuth>,
+    ingest: web::Data<IngestConfig>,
+) -> impl Responder {
+    let parse = PlexHook::into_event;
+    receive("plex", Some("payload"), parse, req, body, auth, ingest).await
+}
+
+/// Jellyfin Webhook plugin ("Generic" destination, JSON body).
+#[post("/jellyfin")]
+async fn jellyfin(
+    req: HttpRequest,
+    body: web::Bytes,
+    auth: web::Data<WebhookAuth>,
+    ingest: web::Data<IngestConfig>,
+) -> impl Responder {
+    let parse = JellyfinHook::into_event;
+    receive("jellyfin", None, parse, req, body, auth, ingest).await
+}
+
+/// Emby webhooks: JSON, or `multipart/form-data` with the JSON in `data`.
+#[post("/emby")]
+async fn emby(
+    req: HttpRequest,
+    body: web::Bytes,
+    auth: web::Data<WebhookAuth>,
+    ingest: web::Data<IngestConfig>,
+) -> impl Responder {
+    let parse = EmbyHook::into_event;
+    receive("emby", Some("data"), parse, req, body, auth, ingest).await
+}
+
 #[get("/healthz")]
 async fn healthz() -> impl Responder {
     HttpResponse::Ok().body("ok")
 }
 
+/// Prometheus-format counters (currently webhook auth rejections).
+#[get("/metrics")]
+async fn metrics() -> impl Responder {
+    let mut out = String::from(
+        "# HELP alfred_webhook_auth_failures_total Webhook requests rejected with 401\n\
+         # TYPE alfred_webhook_auth_failures_total counter\n",
+    );
+    for (reason, n) in crate::auth::failure_counts() {
+        out.push_str(&format!(
+            "alfred_webhook_auth_failures_total{{reason=\"{reason}\"}} {n}\n"
+        ));
+    }
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(out)
+}
+
 /// Launch the Actix server. Reads `BIND_ADDR` (default `0.0.0.0:8088`).
 pub async fn run_server() -> std::io::Result<()> {
-    let ndjson_path = env::var("NDJSON_PATH").unwrap();
+    let auth = web::Data::new(WebhookAuth::from_env().map_err(std::io::Error::other)?);
+    let db_path = Store::path_from_env().map_err(std::io::Error::other)?;
+    let store = StoreHandle::spawn(&db_path).map_err(std::io::Error::other)?;
     let client = reqwest::Client::new();
-    actix_rt::spawn(async move {
-        let mut ticker = tokio::time::interval(std::time::Duration::from_secs(6 * 60 * 60)); // 6hrs
-        loop {
-            ticker.tick().await;
-            crate::generate_recommendations_for_latest_bucket(&ndjson_path, &client).await;
-        }
-    });
+    let admin_client = client.clone();
+    let ingest = web::Data::new(IngestConfig::from_env(store.clone()));
+    let buckets = ingest.buckets;
+    info!("Bucketing watches by {buckets}");
+    let mut scheduler = Scheduler::new(store.clone());
+    let job_store = store.clone();
+    scheduler.register(
+        Job::new(RECOMMENDATIONS_JOB, buckets, move || {
+            let (store, client) = (job_store.clone(), client.clone());
+            async move {
+                crate::generate_recommendations_for_latest_bucket(&store, &buckets, &client).await
+            }
+        })
+        .with_env_overrides(buckets.tz),
+    );
+    let similarity = SimilarityConfig::from_env();
+    if similarity.mode != SimilarityMode::Off {
+        let cron = Cron::parse(SIMILARITY_CRON, buckets.tz).map_err(std::io::Error::other)?;
+        let (store, client) = (store.clone(), admin_client.clone());
+        scheduler.register(
+            Job::new(SIMILARITY_JOB, cron, move || {
+                let (store, client) = (store.clone(), client.clone());
+                async move {
+                    let r =
+                        crate::similarity::refresh(&store, &client, similarity.pool_pages).await?;
+                    info!("Similarity index: {r}");
+                    Ok(())
+                }
+            })
+            .with_env_overrides(buckets.tz),
+        );
+    }
+    let retention = RetentionPolicy::from_env(Path::new(&db_path));
+    if retention.is_enabled() {
+        let cron = Cron::parse(RETENTION_CRON, buckets.tz).map_err(std::io::Error::other)?;
+        let policy = retention.clone();
+        scheduler.register(
+            Job::new(RETENTION_JOB, cron, move || {
+                let (store, policy) = (store.clone(), policy.clone());
+                async move {
+                    let r = store
+                        .write(move |s| {
+                            let now = chrono::Utc::now();
+                            s.apply_retention(&policy, now, |b| {
+                                buckets.is_closed(&b.tag, b.count, now)
+                            })
+                        })
+                        .await?;
+                    info!(
+                        "Retention: stripped {} raw payloads, archived {} buckets ({} events)",
+                        r.raw_stripped, r.buckets_archived, r.events_archived
+                    );
+                    Ok(())
+                }
+            })
+            .with_env_overrides(buckets.tz),
+        );
+    }
+    scheduler.spawn();
 
     let bind = env::var("BIND_ADDR").unwrap_or_else(|_| "0.0.0.0:8088".into());
     println!("movie_recommendation_engine up on http://{bind}");
-    HttpServer::new(|| App::new().service(tautulli).service(healthz))
-        .bind(bind)?
-        .run()
-        .await
+    let admin_client = web::Data::new(admin_client);
+    let retention = web::Data::new(retention);
+    HttpServer::new(move || {
+        App::new()
+            .app_data(auth.clone())
+            .app_data(ingest.clone())
+            .app_data(admin_client.clone())
+            .app_data(retention.clone())
+            .service(tautulli)
+            .service(plex)
+            .service(jellyfin)
+            .service(emby)
+            .service(healthz)
+            .service(metrics)
+            .configure(crate::admin::configure)
+            .configure(crate::feedback::configure)
+    })
+    .bind(bind)?
+    .run()
+    .await
 }
@@ -0,0 +1,628 @@
+use anyhow::Result;
+use chrono::{SecondsFormat, Utc};
+use futures::{stream, StreamExt};
+use lib::clients::tmdb::get_movie_list::{get_movie_list, MovieList};
+use lib::clients::tmdb::get_movie_metadata::{get_movie_metadata, MovieMetadata};
+use lib::store::StoreHandle;
+use once_cell::sync::Lazy;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::recommend::{MediaType, RecItem, RecSource, Recommended};
+use crate::related;
+use crate::validate::ValidationReport;
+
+/// Metadata older than this is fetched again by [`refresh`].
+const DOC_TTL: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+/// ...but is still used to build the index until it's this old.
+const DOC_MAX_AGE: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+const POOL_KIND: &str = "similarity_pool";
+/// Key, next to the pool, of the time it and its documents were last refreshed
+const POOL_VERSION: &str = "version";
+const DOC_KIND: &str = "similarity_doc";
+
+/// Each feature group is normalized on its own and then weighted, so a long cast list or
+/// overview can't drown out the genres.
+const GROUPS: usize = 6;
+const GROUP_WEIGHTS: [f64; GROUPS] = [
+    1.0, // genres
+    1.0, // keywords
+    0.7, // cast
+    1.0, // crew
+    1.5, // collection
+    0.6, // overview words
+];
+const CAST_LIMIT: usize = 6;
+const CREW_JOBS: [&str; 4] = [
+    "Director",
+    "Screenplay",
+    "Writer",
+    "Original Music Composer",
+];
+const STOPWORDS: &[&str] = &[
+    "the", "and", "for", "with", "his", "her", "their", "they", "them", "from", "into", "that",
+    "this", "who", "when", "where", "while", "after", "before", "but", "are", "was", "has", "have",
+    "its", "not", "one", "two", "out", "own", "all", "must", "can", "will", "what", "about",
+    "which", "upon", "only", "than", "then", "also", "becomes", "finds", "find", "takes", "new",
+    "life", "world", "story", "film",
+];
+
+/// How the local index is used for movies, on top of whatever `TMDB_RECOMMENDER` does
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SimilarityMode {
+    #[default]
+    Off,
+    /// Reorder the movie recommendations by similarity to the bucket's watches
+    Rerank,
+    /// The index's picks replace the LLM's; the LLM isn't asked for movies
+    Recommend,
+    /// Alternate LLM and index picks
+    Blend,
+}
+
+impl SimilarityMode {
+    /// Whether the LLM should be asked for movies
+    pub fn uses_llm(self) -> bool {
+        self != SimilarityMode::Recommend
+    }
+
+    /// Whether the index supplies picks of its own
+    pub fn picks(self) -> bool {
+        matches!(self, SimilarityMode::Recommend | SimilarityMode::Blend)
+    }
+}
+
+/// The local index's settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimilarityConfig {
+    pub mode: SimilarityMode,
+    /// Pages (20 titles each) of TMDB's popular and top-rated lists in the pool
+    pub pool_pages: u32,
+}
+
+impl SimilarityConfig {
+    /// `SIMILARITY_MODE`: `off` (default), `rerank`, `recommend` or `blend`, and
+    /// `SIMILARITY_POOL_PAGES` (default 10).
+    pub fn from_env() -> Self {
+        let mode = match std::env::var("SIMILARITY_MODE")
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "" | "off" => SimilarityMode::Off,
+            "rerank" => SimilarityMode::Rerank,
+            "recommend" => SimilarityMode::Recommend,
+            "blend" => SimilarityMode::Blend,
+            other => {
+                log::error!("Ignoring SIMILARITY_MODE={other:?} (off, rerank, recommend or blend)");
+                SimilarityMode::Off
+            }
+        };
+        Self {
+            mode,
+            pool_pages: std::env::var("SIMILARITY_POOL_PAGES")
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .filter(|p| *p > 0)
+                .unwrap_or(10),
+        }
+    }
+}
+
+/// What a film is compared on, cached per title
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MovieDoc {
+    pub tmdb_id: u32,
+    pub title: String,
+    pub genres: Vec<String>,
+    pub keywords: Vec<String>,
+    /// Top-billed cast
+    pub cast: Vec<String>,
+    /// Director, writers and composer
+    pub crew: Vec<String>,
+    pub collection: Option<String>,
+    pub overview: String,
+}
+
+impl From<MovieMetadata> for MovieDoc {
+    fn from(m: MovieMetadata) -> Self {
+        let mut cast = m.credits.cast;
+        cast.sort_by_key(|c| c.order.unwrap_or(u32::MAX));
+        let mut crew: Vec<String> = m
+            .credits
+            .crew
+            .into_iter()
+            .filter(|c| c.job.as_deref().is_some_and(|j| CREW_JOBS.contains(&j)))
+            .map(|c| c.name)
+            .collect();
+        crew.sort();
+        crew.dedup();
+        Self {
+            tmdb_id: m.id,
+            title: m.title,
+            genres: m.genres.into_iter().map(|g| g.name).collect(),
+            keywords: m.keywords.keywords.into_iter().map(|k| k.name).collect(),
+            cast: cast.into_iter().take(CAST_LIMIT).map(|c| c.name).collect(),
+            crew,
+            collection: m.belongs_to_collection.map(|c| c.name),
+            overview: m.overview.unwrap_or_default(),
+        }
+    }
+}
+
+fn overview_terms(text: &str) -> HashMap<String, f64> {
+    let mut tf = HashMap::new();
+    for word in text
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() >= 3 && !w.chars().all(|c| c.is_ascii_digit()))
+        .filter(|w| !STOPWORDS.contains(w))
+    {
+        *tf.entry(format!("w:{word}")).or_insert(0.0) += 1.0;
+    }
+    tf
+}
+
+/// A document's features by group, with their term frequencies
+fn features(doc: &MovieDoc) -> [HashMap<String, f64>; GROUPS] {
+    let tagged = |prefix: &str, names: &[String]| -> HashMap<String, f64> {
+        names
+            .iter()
+            .map(|n| (format!("{prefix}:{}", n.to_lowercase()), 1.0))
+            .collect()
+    };
+    [
+        tagged("g", &doc.genres),
+        tagged("k", &doc.keywords),
+        tagged("c", &doc.cast),
+        tagged("p", &doc.crew),
+        tagged("col", doc.collection.as_slice()),
+        overview_terms(&doc.overview),
+    ]
+}
+
+type SparseVec = Vec<(usize, f64)>;
+
+fn normalize(v: &mut [(usize, f64)]) {
+    let norm = v.iter().map(|(_, x)| x * x).sum::<f64>().sqrt();
+    if norm > 0.0 {
+        v.iter_mut().for_each(|(_, x)| *x /= norm);
+    }
+}
+
+/// Dot product of two vectors sorted by feature
+fn dot(a: &[(usize, f64)], b: &[(usize, f64)]) -> f64 {
+    let (mut i, mut j, mut sum) = (0, 0, 0.0);
+    while i < a.len() && j < b.len() {
+        match a[i].0.cmp(&b[j].0) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                sum += a[i].1 * b[j].1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    sum
+}
+
+/// TF-IDF vectors over a pool of films, compared by cosine similarity. IDF comes from the
+/// pool, so features the pool has never seen don't count.
+#[derive(Debug, Clone, Default)]
+pub struct SimilarityIndex {
+    vocab: HashMap<String, usize>,
+    idf: Vec<f64>,
+    pool: Vec<(u32, SparseVec)>,
+}
+
+impl SimilarityIndex {
+    pub fn build(pool: &[MovieDoc]) -> Self {
+        let mut vocab: HashMap<String, usize> = HashMap::new();
+        let mut df: Vec<f64> = Vec::new();
+        for doc in pool {
+            for group in features(doc) {
+                for name in group.into_keys() {
+                    let next = vocab.len();
+                    let i = *vocab.entry(name).or_insert(next);
+                    if i == df.len() {
+                        df.push(0.0);
+                    }
+                    df[i] += 1.0;
+                }
+            }
+        }
+        let n = pool.len() as f64;
+        let mut index = Self {
+            vocab,
+            idf: df
+                .iter()
+                .map(|d| ((1.0 + n) / (1.0 + d)).ln() + 1.0)
+                .collect(),
+            pool: Vec::new(),
+        };
+        index.pool = pool
+            .iter()
+            .map(|d| (d.tmdb_id, index.vectorize(d)))
+            .collect();
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.pool.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pool.is_empty()
+    }
+
+    fn vectorize(&self, doc: &MovieDoc) -> SparseVec {
+        let mut out = SparseVec::new();
+        for (group, weight) in features(doc).into_iter().zip(GROUP_WEIGHTS) {
+            let mut v: SparseVec = group
+                .into_iter()
+                .filter_map(|(name, tf)| {
+                    let i = *self.vocab.get(&name)?;
+                    Some((i, tf * self.idf[i]))
+                })
+                .collect();
+            normalize(&mut v);
+            out.extend(v.into_iter().map(|(i, x)| (i, x * weight)));
+        }
+        out.sort_unstable_by_key(|(i, _)| *i);
+        normalize(&mut out);
+        out
+    }
+
+    /// The normalized mean of the seeds' vectors
+    fn centroid(&self, seeds: &[MovieDoc]) -> SparseVec {
+        let mut sum: HashMap<usize, f64> = HashMap::new();
+        for doc in seeds {
+            for (i, x) in self.vectorize(doc) {
+                *sum.entry(i).or_default() += x;
+            }
+        }
+        let mut v: SparseVec = sum.into_iter().collect();
+        v.sort_unstable_by_key(|(i, _)| *i);
+        normalize(&mut v);
+        v
+    }
+
+    /// The `n` pool films most like `seeds` as a whole, best first, leaving out the seeds
+    /// and `excluded`.
+    pub fn most_similar(
+        &self,
+        seeds: &[MovieDoc],
+        excluded: &HashSet<u32>,
+        n: usize,
+    ) -> Vec<(u32, f64)> {
+        let centroid = self.centroid(seeds);
+        let seed_ids: HashSet<u32> = seeds.iter().map(|d| d.tmdb_id).collect();
+        let mut scored: Vec<(u32, f64)> = self
+            .pool
+            .iter()
+            .filter(|(id, _)| !seed_ids.contains(id) && !excluded.contains(id))
+            .map(|(id, v)| (*id, dot(&centroid, v)))
+            .filter(|(_, s)| *s > 0.0)
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        scored.truncate(n);
+        scored
+    }
+
+    /// `candidates` ordered by similarity to `seeds`, best first; ties keep their order.
+    pub fn rerank(&self, seeds: &[MovieDoc], candidates: &[MovieDoc]) -> Vec<(u32, f64)> {
+        let centroid = self.centroid(seeds);
+        let mut scored: Vec<(u32, f64)> = candidates
+            .iter()
+            .map(|d| (d.tmdb_id, dot(&centroid, &self.vectorize(d))))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored
+    }
+}
+
+/// Documents for `ids` from the store's cache (up to `max_age` old) or TMDB, with how many
+/// were fetched and how many TMDB couldn't return.
+async fn docs(
+    store: &StoreHandle,
+    client: &Client,
+    ids: &[u32],
+    max_age: Duration,
+) -> (HashMap<u32, MovieDoc>, usize, usize) {
+    let wanted = ids.to_vec();
+    let cached: HashMap<u32, MovieDoc> = store
+        .read(move |s| {
+            let mut out = HashMap::new();
+            for id in wanted {
+                if let Some(v) = s.cache_get(DOC_KIND, &id.to_string(), max_age)? {
+                    if let Ok(doc) = serde_json::from_str(&v) {
+                        out.insert(id, doc);
+                    }
+                }
+            }
+            Ok(out)
+        })
+        .await
+        .unwrap_or_default();
+
+    let missing: Vec<u32> = ids
+        .iter()
+        .copied()
+        .filter(|id| !cached.contains_key(id))
+        .collect();
+    let results: Vec<Option<MovieDoc>> = stream::iter(missing.into_iter().map(|id| {
+        let client = client.clone();
+        async move {
+            match get_movie_metadata(&client, id).await {
+                Ok(m) => Some(MovieDoc::from(m)),
+                Err(e) => {
+                    log::warn!("TMDB metadata for the similarity index failed ({id}): {e:#}");
+                    None
+                }
+            }
+        }
+    }))
+    .buffer_unordered(8)
+    .collect()
+    .await;
+    let failed = results.iter().filter(|r| r.is_none()).count();
+    let fetched: Vec<MovieDoc> = results.into_iter().flatten().collect();
+
+    if !fetched.is_empty() {
+        let rows: Vec<_> = fetched
+            .iter()
+            .map(|d| (d.tmdb_id.to_string(), serde_json::to_string(d)))
+            .collect();
+        let res = store
+            .write(move |s| {
+                for (key, value) in rows {
+                    s.cache_put(DOC_KIND, &key, &value?)?;
+                }
+                Ok(())
+            })
+            .await;
+        if let Err(e) = res {
+            log::warn!("similarity document cache write failed: {e:#}");
+        }
+    }
+    let count = fetched.len();
+    let all = cached
+        .into_iter()
+        .chain(fetched.into_iter().map(|d| (d.tmdb_id, d)))
+        .collect();
+    (all, count, failed)
+}
+
+/// When [`refresh`] last finished, in any process; the built index is keyed on it.
+async fn pool_version(store: &StoreHandle) -> Result<Option<String>> {
+    store
+        .read(|s| s.cache_get(POOL_KIND, POOL_VERSION, DOC_MAX_AGE))
+        .await
+}
+
+async fn load_pool(store: &StoreHandle) -> Result<Vec<u32>> {
+    store
+        .read(|s| {
+            Ok(s.cache_get(POOL_KIND, "movie", DOC_MAX_AGE)?
+                .and_then(|v| serde_json::from_str(&v).ok())
+                .unwrap_or_default())
+        })
+        .await
+}
+
+/// What a [`refresh`] did
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RefreshReport {
+    /// Films in the pool afterwards
+    pub pool: usize,
+    /// Films new to the pool
+    pub added: usize,
+    /// Documents fetched from TMDB (new films and expired metadata)
+    pub fetched: usize,
+    pub failed: usize,
+}
+
+impl fmt::Display for RefreshReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} films in the similarity pool ({} new); fetched {} documents, {} failed",
+            self.pool, self.added, self.fetched, self.failed
+        )
+    }
+}
+
+/// Grow the pool with the current popular and top-rated lists and fetch metadata only for
+/// films that are new or whose metadata has expired. Films stay in the pool once added.
+pub async fn refresh(
+    store: &StoreHandle,
+    client: &Client,
+    pool_pages: u32,
+) -> Result<RefreshReport> {
+    let before: BTreeSet<u32> = load_pool(store).await?.into_iter().collect();
+    let mut pool = before.clone();
+    let mut errors = 0;
+    for list in [MovieList::Popular, MovieList::TopRated] {
+        for page in 1..=pool_pages {
+            match get_movie_list(client, list, page).await {
+                Ok(found) => pool.extend(found.into_iter().filter(|m| !m.adult).map(|m| m.id)),
+                Err(e) => {
+                    log::warn!("TMDB {} page {page} failed: {e:#}", list.as_str());
+                    errors += 1;
+                }
+            }
+        }
+    }
+    if errors == 2 * pool_pages as usize && before.is_empty() {
+        anyhow::bail!("could not read any TMDB list to build the similarity pool");
+    }
+    let ids: Vec<u32> = pool.iter().copied().collect();
+    let (_, fetched, failed) = docs(store, client, &ids, DOC_TTL).await;
+    let json = serde_json::to_string(&ids)?;
+    let version = Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, true);
+    store
+        .write(move |s| {
+            s.cache_put(POOL_KIND, "movie", &json)?;
+            s.cache_put(POOL_KIND, POOL_VERSION, &version)
+        })
+        .await?;
+    if let Ok(mut built) = LOADED.lock() {
+        *built = None;
+    }
+    Ok(RefreshReport {
+        pool: ids.len(),
+        added: ids.len() - before.len(),
+        fetched,
+        failed,
+    })
+}
+
+/// The index over the pool's cached documents; empty until [`refresh`] has run.
+pub async fn load_index(store: &StoreHandle) -> Result<SimilarityIndex> {
+    let ids = load_pool(store).await?;
+    let docs: Vec<MovieDoc> = store
+        .read(move |s| {
+            let mut out = Vec::with_capacity(ids.len());
+            for id in ids {
+                if let Some(v) = s.cache_get(DOC_KIND, &id.to_string(), DOC_MAX_AGE)? {
+                    if let Ok(doc) = serde_json::from_str(&v) {
+                        out.push(doc);
+                    }
+                }
+            }
+            Ok(out)
+        })
+        .await?;
+    Ok(SimilarityIndex::build(&docs))
+}
+
+/// The index last built by [`loaded`] and the pool version it was built from. [`refresh`]
+/// drops it; a refresh by another process (the `index` command) is noticed by the version.
+static LOADED: Lazy<Mutex<Option<BuiltIndex>>> = Lazy::new(|| Mutex::new(None));
+type BuiltIndex = (Option<String>, Arc<SimilarityIndex>);
+
+/// The index, built only when the pool has been refreshed since it was last built
+async fn cached_index(store: &StoreHandle) -> Result<Arc<SimilarityIndex>> {
+    let version = pool_version(store).await?;
+    if let Ok(built) = LOADED.lock() {
+        if let Some((_, index)) = built.as_ref().filter(|(v, _)| *v == version) {
+            return Ok(index.clone());
+        }
+    }
+    let index = Arc::new(load_index(store).await?);
+    if let Ok(mut built) = LOADED.lock() {
+        *built = Some((version, index.clone()));
+    }
+    Ok(index)
+}
+
+async fn loaded(store: &StoreHandle) -> Option<Arc<SimilarityIndex>> {
+    match cached_index(store).await {
+        Ok(index) if !index.is_empty() => Some(index),
+        Ok(_) => {
+            log::warn!("The similarity index is empty; run `index` or wait for its job");
+            None
+        }
+        Err(e) => {
+            log::error!("loading the similarity index failed: {e:#}");
+            None
+        }
+    }
+}
+
+/// Up to `target` pool films most like the watched `seeds`, best first.
+pub async fn recommend(
+    store: &StoreHandle,
+    client: &Client,
+    seeds: &[u32],
+    excluded: &HashSet<u32>,
+    target: usize,
+) -> Vec<RecItem> {
+    let Some(index) = loaded(store).await else {
+        return Vec::new();
+    };
+    let (seed_docs, ..) = docs(store, client, seeds, DOC_MAX_AGE).await;
+    let seed_docs: Vec<MovieDoc> = seed_docs.into_values().collect();
+    index
+        .most_similar(&seed_docs, excluded, target)
+        .into_iter()
+        .map(|(tmdb_id, _)| RecItem {
+            tmdb_id,
+            media_type: MediaType::Movie,
+            source: RecSource::Index,
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// The LLM's movie result with the index's `picks` in its place (`Recommend`) or alternated
+/// with its own (`Blend`). `None` when neither has anything.
+pub fn combine(
+    mode: SimilarityMode,
+    llm: Option<Recommended>,
+    picks: Recommended,
+    target: usize,
+) -> Option<Recommended> {
+    if llm.is_none() && picks.items.is_empty() {
+        return None;
+    }
+    let mut rec = llm.unwrap_or_else(|| Recommended {
+        items: Vec::new(),
+        report: ValidationReport {
+            target,
+            ..Default::default()
+        },
+    });
+    rec.report.absorb(picks.report);
+    let picks = picks.items;
+    rec.items = match mode {
+        SimilarityMode::Blend => related::blend(rec.items, picks, target),
+        _ => picks.into_iter().take(target).collect(),
+    };
+    rec.report.tally(&rec.items);
+    Some(rec)
+}
+
+/// `items` reordered by similarity to the watched `seeds`. Left as they are if the index
+/// is empty; films without metadata go last.
+pub async fn rerank(
+    store: &StoreHandle,
+    client: &Client,
+    seeds: &[u32],
+    items: Vec<RecItem>,
+) -> Vec<RecItem> {
+    let Some(index) = loaded(store).await else {
+        return items;
+    };
+    let ids: Vec<u32> = seeds
+        .iter()
+        .chain(items.iter().map(|r| &r.tmdb_id))
+        .copied()
+        .collect();
+    let (found, ..) = docs(store, client, &ids, DOC_MAX_AGE).await;
+    let seed_docs: Vec<MovieDoc> = seeds
+        .iter()
+        .filter_map(|id| found.get(id).cloned())
+        .collect();
+    let candidates: Vec<MovieDoc> = items
+        .iter()
+        .filter_map(|r| found.get(&r.tmdb_id).cloned())
+        .collect();
+    let rank: HashMap<u32, usize> = index
+        .rerank(&seed_docs, &candidates)
+        .into_iter()
+        .enumerate()
+        .map(|(i, (id, _))| (id, i))
+        .collect();
+    let mut items = items;
+    items.sort_by_key(|r| rank.get(&r.tmdb_id).copied().unwrap_or(usize::MAX));
+    items
+}
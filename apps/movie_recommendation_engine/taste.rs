@@ -0,0 +1,411 @@
+use chrono::{DateTime, Utc};
+use futures::{stream, StreamExt};
+use lib::clients::tmdb::get_keywords::{get_movie_keywords, get_tv_keywords};
+use lib::clients::tmdb::get_movie_by_id::get_movie_by_id;
+use lib::clients::tmdb::get_tv_by_id::get_tv_by_id;
+use lib::store::{StoreHandle, StoredEvent};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{collections::HashMap, time::Duration};
+
+use crate::recommend::MediaType;
+
+/// An episode counts for this much of a film, so a binged season doesn't swamp the profile.
+const EPISODE_WEIGHT: f64 = 0.2;
+/// Each watch of a title after the first counts this much more: a rewatch is a stronger
+/// endorsement than a first watch.
+const REWATCH_BONUS: f64 = 1.5;
+/// Affinities below this share are left out of the summary.
+const MIN_SHARE: f64 = 0.02;
+const FEATURES_CACHE_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// How the long-term profile is built
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TasteConfig {
+    /// A watch this many days old counts half as much as one today
+    pub half_life_days: f64,
+    /// Only the most recently watched titles are looked up on TMDB
+    pub max_titles: usize,
+    /// Entries kept per dimension in the summary
+    pub top_n: usize,
+}
+
+impl Default for TasteConfig {
+    fn default() -> Self {
+        Self {
+            half_life_days: 90.0,
+            max_titles: 300,
+            top_n: 8,
+        }
+    }
+}
+
+impl TasteConfig {
+    /// `TASTE_HALF_LIFE_DAYS` (default 90) and `TASTE_MAX_TITLES` (default 300; 0 turns
+    /// the profile off).
+    pub fn from_env() -> Self {
+        let d = Self::default();
+        let var = |k: &str| std::env::var(k).ok().filter(|v| !v.trim().is_empty());
+        Self {
+            half_life_days: var("TASTE_HALF_LIFE_DAYS")
+                .and_then(|v| v.trim().parse().ok())
+                .filter(|h: &f64| *h > 0.0)
+                .unwrap_or(d.half_life_days),
+            max_titles: var("TASTE_MAX_TITLES")
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(d.max_titles),
+            ..d
+        }
+    }
+}
+
+/// One completed watch from the history
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watch {
+    pub tmdb_id: u32,
+    pub media_type: MediaType,
+    pub at: DateTime<Utc>,
+}
+
+/// The TMDB facts a profile is built from, cached per title
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TitleFeatures {
+    pub title: String,
+    pub year: Option<u16>,
+    pub genres: Vec<String>,
+    /// ISO 639-1 original language
+    pub language: Option<String>,
+    pub collection: Option<String>,
+    pub keywords: Vec<String>,
+}
+
+/// Something the user leans towards, as a share of all (decayed) watch weight
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Affinity {
+    pub name: String,
+    pub share: f64,
+}
+
+/// A film watched more than once
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Rewatch {
+    pub tmdb_id: u32,
+    pub title: String,
+    pub times: usize,
+}
+
+/// Recency-weighted affinities over a user's whole watch history
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TasteProfile {
+    pub watches: usize,
+    pub titles: usize,
+    pub genres: Vec<Affinity>,
+    pub decades: Vec<Affinity>,
+    pub languages: Vec<Affinity>,
+    pub collections: Vec<Affinity>,
+    pub keywords: Vec<Affinity>,
+    pub rewatched: Vec<Rewatch>,
+}
+
+fn id_field(ev: &serde_json::Value, key: &str) -> Option<u32> {
+    let v = ev.get(key)?;
+    v.as_u64()
+        .map(|n| n as u32)
+        .or_else(|| v.as_str().and_then(|s| s.parse::<u32>().ok()))
+}
+
+/// Watches with a tmdb id and a readable time. Episodes count towards their series.
+pub fn watches_from_events(events: &[StoredEvent]) -> Vec<Watch> {
+    events
+        .iter()
+        .filter_map(|e| {
+            let data: serde_json::Value = serde_json::from_str(&e.row.data).ok()?;
+            let tmdb_id = e.row.tmdb_id.or_else(|| id_field(&data, "tmdb_id"))?;
+            let media_type = match e.row.media_type.as_deref() {
+                Some("episode" | "show" | "season") => MediaType::Tv,
+                _ => MediaType::Movie,
+            };
+            let at = DateTime::parse_from_rfc3339(e.row.ts.as_deref()?)
+                .ok()?
+                .with_timezone(&Utc);
+            Some(Watch {
+                tmdb_id,
+                media_type,
+                at,
+            })
+        })
+        .collect()
+}
+
+fn top(weights: HashMap<String, f64>, total: f64, n: usize) -> Vec<Affinity> {
+    let mut out: Vec<Affinity> = weights
+        .into_iter()
+        .map(|(name, w)| Affinity {
+            name,
+            share: (w / total * 100.0).round() / 100.0,
+        })
+        .filter(|a| a.share >= MIN_SHARE)
+        .collect();
+    out.sort_by(|a, b| {
+        b.share
+            .total_cmp(&a.share)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    out.truncate(n);
+    out
+}
+
+/// Build the profile. Each watch weighs `0.5^(age / half-life)`, scaled down for episodes
+/// and up for rewatches; titles without features are skipped.
+pub fn build_profile(
+    watches: &[Watch],
+    features: &HashMap<(MediaType, u32), TitleFeatures>,
+    now: DateTime<Utc>,
+    config: &TasteConfig,
+) -> TasteProfile {
+    let mut seen: HashMap<(MediaType, u32), usize> = HashMap::new();
+    let mut dims: [HashMap<String, f64>; 5] = Default::default();
+    let mut total = 0.0;
+    let mut used = 0;
+    let mut ordered = watches.to_vec();
+    ordered.sort_by_key(|w| w.at);
+    for w in &ordered {
+        let key = (w.media_type, w.tmdb_id);
+        let Some(f) = features.get(&key) else {
+            continue;
+        };
+        let times = seen.entry(key).or_default();
+        *times += 1;
+        used += 1;
+
+        let age_days = (now - w.at).num_seconds().max(0) as f64 / 86_400.0;
+        let mut weight = 0.5f64.powf(age_days / config.half_life_days);
+        if w.media_type == MediaType::Tv {
+            weight *= EPISODE_WEIGHT;
+        } else if *times > 1 {
+            weight *= REWATCH_BONUS;
+        }
+        total += weight;
+
+        let [genres, decades, languages, collections, keywords] = &mut dims;
+        for g in &f.genres {
+            *genres.entry(g.clone()).or_default() += weight;
+        }
+        if let Some(y) = f.year {
+            *decades.entry(format!("{}s", y / 10 * 10)).or_default() += weight;
+        }
+        if let Some(l) = &f.language {
+            *languages.entry(l.clone()).or_default() += weight;
+        }
+        if let Some(c) = &f.collection {
+            *collections.entry(c.clone()).or_default() += weight;
+        }
+        for k in &f.keywords {
+            *keywords.entry(k.clone()).or_default() += weight;
+        }
+    }
+
+    let mut rewatched: Vec<Rewatch> = seen
+        .iter()
+        .filter(|((media_type, _), times)| *media_type == MediaType::Movie && **times > 1)
+        .map(|(key, times)| Rewatch {
+            tmdb_id: key.1,
+            title: features[key].title.clone(),
+            times: *times,
+        })
+        .collect();
+    rewatched.sort_by(|a, b| b.times.cmp(&a.times).then(a.tmdb_id.cmp(&b.tmdb_id)));
+    rewatched.truncate(config.top_n);
+
+    if total <= 0.0 {
+        return TasteProfile::default();
+    }
+    let [genres, decades, languages, collections, keywords] = dims;
+    TasteProfile {
+        watches: used,
+        titles: seen.len(),
+        genres: top(genres, total, config.top_n),
+        decades: top(decades, total, config.top_n),
+        languages: top(languages, total, config.top_n),
+        collections: top(collections, total, config.top_n),
+        keywords: top(keywords, total, config.top_n * 2),
+        rewatched,
+    }
+}
+
+impl TasteProfile {
+    pub fn is_empty(&self) -> bool {
+        self.watches == 0
+    }
+
+    /// Compact JSON for the prompt, e.g. `{"genres": ["Science Fiction 31%", …], …}`.
+    pub fn summary(&self) -> String {
+        let list = |a: &[Affinity]| -> Vec<String> {
+            a.iter()
+                .map(|a| format!("{} {:.0}%", a.name, a.share * 100.0))
+                .collect()
+        };
+        json!({
+            "watches": self.watches,
+            "titles": self.titles,
+            "genres": list(&self.genres),
+            "decades": list(&self.decades),
+            "languages": list(&self.languages),
+            "collections": list(&self.collections),
+            "keywords": list(&self.keywords),
+            "rewatched": self
+                .rewatched
+                .iter()
+                .map(|r| format!("{} (tmdb {}) x{}", r.title, r.tmdb_id, r.times))
+                .collect::<Vec<_>>(),
+        })
+        .to_string()
+    }
+}
+
+fn year(d: &Option<String>) -> Option<u16> {
+    d.as_ref()
+        .and_then(|s| s.get(0..4))
+        .and_then(|y| y.parse::<u16>().ok())
+}
+
+async fn fetch_features(
+    client: &Client,
+    media_type: MediaType,
+    id: u32,
+) -> anyhow::Result<TitleFeatures> {
+    Ok(match media_type {
+        MediaType::Movie => {
+            let (m, kw) =
+                futures::join!(get_movie_by_id(client, id), get_movie_keywords(client, id));
+            let m = m?;
+            TitleFeatures {
+                year: year(&m.release_date),
+                title: m.title,
+                genres: m.genres.into_iter().map(|g| g.name).collect(),
+                language: m.original_language,
+                collection: m.belongs_to_collection.map(|c| c.name),
+                keywords: kw.unwrap_or_default().into_iter().map(|k| k.name).collect(),
+            }
+        }
+        MediaType::Tv => {
+            let (t, kw) = futures::join!(get_tv_by_id(client, id), get_tv_keywords(client, id));
+            let t = t?;
+            TitleFeatures {
+                year: year(&t.first_air_date),
+                title: t.name,
+                genres: t.genres.into_iter().map(|g| g.name).collect(),
+                language: t.original_language,
+                collection: None,
+                keywords: kw.unwrap_or_default().into_iter().map(|k| k.name).collect(),
+            }
+        }
+    })
+}
+
+/// Features for each title, from the store's cache or TMDB. Titles TMDB can't find are left out.
+async fn load_features(
+    store: &StoreHandle,
+    client: &Client,
+    titles: Vec<(MediaType, u32)>,
+) -> HashMap<(MediaType, u32), TitleFeatures> {
+    let cache_kind = |m: MediaType| format!("taste_{}", m.as_str());
+    let keys = titles.clone();
+    let cached: HashMap<(MediaType, u32), TitleFeatures> = store
+        .read(move |s| {
+            let mut out = HashMap::new();
+            for (m, id) in keys {
+                if let Some(v) = s.cache_get(&cache_kind(m), &id.to_string(), FEATURES_CACHE_TTL)? {
+                    if let Ok(f) = serde_json::from_str(&v) {
+                        out.insert((m, id), f);
+                    }
+                }
+            }
+            Ok(out)
+        })
+        .await
+        .unwrap_or_default();
+
+    let missing: Vec<_> = titles
+        .into_iter()
+        .filter(|k| !cached.contains_key(k))
+        .collect();
+    let fetched: Vec<_> = stream::iter(missing.into_iter().map(|(m, id)| {
+        let client = client.clone();
+        async move {
+            match fetch_features(&client, m, id).await {
+                Ok(f) => Some(((m, id), f)),
+                Err(e) => {
+                    log::warn!(
+                        "TMDB lookup for taste profile failed ({} {id}): {e:#}",
+                        m.as_str()
+                    );
+                    None
+                }
+            }
+        }
+    }))
+    .buffer_unordered(8)
+    .filter_map(|r| async move { r })
+    .collect()
+    .await;
+
+    if !fetched.is_empty() {
+        let rows: Vec<_> = fetched
+            .iter()
+            .map(|((m, id), f)| (cache_kind(*m), id.to_string(), serde_json::to_string(f)))
+            .collect();
+        let res = store
+            .write(move |s| {
+                for (kind, key, value) in rows {
+                    s.cache_put(&kind, &key, &value?)?;
+                }
+                Ok(())
+            })
+            .await;
+        if let Err(e) = res {
+            log::warn!("taste feature cache write failed: {e:#}");
+        }
+    }
+    cached.into_iter().chain(fetched).collect()
+}
+
+/// The user's profile over their whole history in the database, or `None` if it's off,
+/// empty or couldn't be loaded.
+pub async fn profile_for(
+    store: &StoreHandle,
+    client: &Client,
+    user: Option<&str>,
+    config: &TasteConfig,
+) -> Option<TasteProfile> {
+    if config.max_titles == 0 {
+        return None;
+    }
+    let owner = user.map(String::from);
+    let events = match store.read(move |s| s.watch_history(owner.as_deref())).await {
+        Ok(e) => e,
+        Err(e) => {
+            log::error!("loading watch history for the taste profile failed: {e:#}");
+            return None;
+        }
+    };
+    let watches = watches_from_events(&events);
+
+    // Most recently watched first, so the cap drops the titles that count least.
+    let mut last_seen: HashMap<(MediaType, u32), DateTime<Utc>> = HashMap::new();
+    for w in &watches {
+        last_seen.insert((w.media_type, w.tmdb_id), w.at);
+    }
+    let mut titles: Vec<_> = last_seen.into_iter().collect();
+    titles.sort_by(|a, b| b.1.cmp(&a.1).then(a.0 .1.cmp(&b.0 .1)));
+    let titles = titles
+        .into_iter()
+        .take(config.max_titles)
+        .map(|(k, _)| k)
+        .collect();
+
+    let features = load_features(store, client, titles).await;
+    let profile = build_profile(&watches, &features, Utc::now(), config);
+    (!profile.is_empty()).then_some(profile)
+}
@@ -0,0 +1,117 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::events::{lenient_f32, lenient_string, lenient_u32, EventKind, WatchEvent};
+
+/// Body of the Tautulli webhook agent. Configure the JSON data template for each trigger as:
+///
+/// ```json
+/// { "event": "watched", "user": "{user}", "title": "{title}", "guid": "{guid}",
+///   "media_type": "{media_type}", "progress_percent": "{progress_percent}",
+///   "duration_sec": "{duration_sec}", "rating_key": "{rating_key}", "session_id": "{session_id}" }
+/// ```
+///
+/// with `event` set to `play`, `pause`, `resume`, `stop` or `watched` per trigger.
+/// For TV, also add `"show_name": "{show_name}", "grandparent_guid": "{grandparent_guid}",
+/// "themoviedb_id": "{themoviedb_id}", "thetvdb_id": "{thetvdb_id}"` so episodes map
+/// to their series.
+#[derive(Debug, Deserialize)]
+pub struct TautulliHook {
+    #[serde(default)]
+    pub event: String,
+    #[serde(default)]
+    pub user: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub guid: String,
+    /// The series' guid when the item is an episode
+    #[serde(default)]
+    pub grandparent_guid: String,
+    #[serde(
+        default,
+        alias = "grandparent_title",
+        deserialize_with = "lenient_string"
+    )]
+    pub show_name: Option<String>,
+    /// Tautulli's resolved TMDB id (the series' id for episodes)
+    #[serde(default, deserialize_with = "lenient_u32")]
+    pub themoviedb_id: Option<u32>,
+    #[serde(default, deserialize_with = "lenient_u32")]
+    pub thetvdb_id: Option<u32>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub media_type: Option<String>,
+    #[serde(default, deserialize_with = "lenient_f32")]
+    pub progress_percent: Option<f32>,
+    /// Seconds (`{duration_sec}`)
+    #[serde(default, deserialize_with = "lenient_u32")]
+    pub duration_sec: Option<u32>,
+    /// Minutes (`{duration}`), used when `duration_sec` is absent
+    #[serde(default, deserialize_with = "lenient_u32")]
+    pub duration: Option<u32>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub rating_key: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    pub session_id: Option<String>,
+}
+
+pub fn extract_tmdb_id(guid: &str) -> Option<u32> {
+    static RE1: Lazy<Regex> = Lazy::new(|| Regex::new(r"tmdb://(?P<id>\d+)").unwrap());
+    static RE2: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"com\.plexapp\.agents\.themoviedb://(?P<id>\d+)").unwrap());
+    RE1.captures(guid)
+        .or_else(|| RE2.captures(guid))
+        .and_then(|c| c.name("id").and_then(|m| m.as_str().parse().ok()))
+}
+
+pub fn extract_tvdb_id(guid: &str) -> Option<u32> {
+    static RE1: Lazy<Regex> = Lazy::new(|| Regex::new(r"tvdb://(?P<id>\d+)").unwrap());
+    static RE2: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"com\.plexapp\.agents\.thetvdb://(?P<id>\d+)").unwrap());
+    RE1.captures(guid)
+        .or_else(|| RE2.captures(guid))
+        .and_then(|c| c.name("id").and_then(|m| m.as_str().parse().ok()))
+}
+
+impl TautulliHook {
+    fn is_episode(&self) -> bool {
+        matches!(
+            self.media_type.as_deref(),
+            Some("episode" | "season" | "show")
+        )
+    }
+
+    pub fn into_event(self, ts: Option<String>, raw: String) -> WatchEvent {
+        let episode = self.is_episode();
+        // Episodes are tracked by their series: the episode guid only carries episode ids.
+        let (tmdb_id, tvdb_id) = if episode {
+            (
+                self.themoviedb_id
+                    .or_else(|| extract_tmdb_id(&self.grandparent_guid)),
+                self.thetvdb_id
+                    .or_else(|| extract_tvdb_id(&self.grandparent_guid)),
+            )
+        } else {
+            (extract_tmdb_id(&self.guid).or(self.themoviedb_id), None)
+        };
+        WatchEvent {
+            ts,
+            source: "tautulli".to_string(),
+            event: EventKind::parse(&self.event),
+            user: Some(self.user).filter(|u| !u.is_empty()),
+            tmdb_id,
+            tvdb_id,
+            show_title: if episode { self.show_name } else { None },
+            title: self.title,
+            media_type: self.media_type,
+            progress_percent: self.progress_percent,
+            duration_secs: self
+                .duration_sec
+                .or(self.duration.map(|m| m.saturating_mul(60))),
+            rating_key: self.rating_key,
+            session_id: self.session_id,
+            raw,
+        }
+    }
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use actix_web::{http::StatusCode, test, web, App};
+use common::temp_db;
+use lib::store::retention::RetentionPolicy;
+use movie_recommendation_engine::auth::WebhookAuth;
+use movie_recommendation_engine::bucketing::BucketStrategy;
+use movie_recommendation_engine::events::IngestConfig;
+use movie_recommendation_engine::users::UserPolicy;
+use serde_json::{json, Value};
+
+const TOKEN: (&str, &str) = ("X-Alfred-Token", "s3cret");
+
+#[actix_rt::test]
+async fn admin_endpoints() {
+    let db = temp_db();
+    let store = db.spawn();
+    let ingest = IngestConfig {
+        store,
+        buckets: BucketStrategy::default(),
+        watched_threshold_percent: 85.0,
+        users: UserPolicy::new(&[("pmclennan", "patrick")], &["guest"]),
+    };
+    let retention = RetentionPolicy {
+        raw_days: None,
+        archive_months: Some(1),
+        archive_dir: db.dir().join("archive"),
+    };
+    let archive_store = ingest.store.clone();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(WebhookAuth::new(
+                Some("s3cret".into()),
+                None,
+                vec![],
+            )))
+            .app_data(web::Data::new(ingest))
+            .app_data(web::Data::new(reqwest::Client::new()))
+            .app_data(web::Data::new(retention.clone()))
+            .configure(movie_recommendation_engine::admin::configure),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/buckets").to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+
+    // Into the user's current bucket, with the alias resolved
+    let req = test::TestRequest::post()
+        .uri("/watched")
+        .insert_header(TOKEN)
+        .set_json(json!({ "tmdb_id": 603, "user": "PMcLennan" }))
+        .to_request();
+    let res: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(res["bucket"]["user"], "patrick");
+    assert_eq!(res["bucket"]["count"], 1);
+    let current = res["bucket"]["tag"].as_str().unwrap().to_string();
+
+    let req = test::TestRequest::post()
+        .uri("/watched")
+        .insert_header(TOKEN)
+        .set_json(json!({ "tmdb_id": 1396, "media_type": "tv", "user": "sam", "bucket": "2025-09-29T12Z" }))
+        .to_request();
+    let res: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(res["bucket"]["tag"], "2025-09-29T12Z");
+
+    let req = test::TestRequest::post()
+        .uri("/watched")
+        .insert_header(TOKEN)
+        .set_json(json!({ "tmdb_id": 1, "user": "guest" }))
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::UNPROCESSABLE_ENTITY
+    );
+
+    let req = test::TestRequest::get()
+        .uri("/buckets?limit=1&offset=1&token=s3cret")
+        .to_request();
+    let page: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page["total"], 2);
+    assert_eq!(page["buckets"].as_array().unwrap().len(), 1);
+    // Both started just now; the later one comes first.
+    assert_eq!(page["buckets"][0]["user"], "patrick");
+
+    let req = test::TestRequest::get()
+        .uri("/buckets/2025-09-29T12Z?user=sam")
+        .insert_header(TOKEN)
+        .to_request();
+    let detail: Value = test::call_and_read_body_json(&app, req).await;
+    let event = &detail["events"][0];
+    assert_eq!(event["tmdb_id"], 1396);
+    assert_eq!(event["media_type"], "show");
+    assert_eq!(event["data"]["source"], "manual");
+    let event_id = event["id"].as_i64().unwrap();
+
+    for expected in [StatusCode::NO_CONTENT, StatusCode::NOT_FOUND] {
+        let req = test::TestRequest::delete()
+            .uri(&format!("/events/{event_id}"))
+            .insert_header(TOKEN)
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), expected);
+    }
+
+    // The bucket is empty now, so regenerating doesn't reach OpenAI.
+    let req = test::TestRequest::post()
+        .uri("/buckets/2025-09-29T12Z/regenerate?user=sam")
+        .insert_header(TOKEN)
+        .to_request();
+    let report: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(report["buckets"], 1);
+    assert_eq!(report["empty"], 1);
+
+    let req = test::TestRequest::delete()
+        .uri("/buckets/2025-09-29T12Z?user=sam")
+        .insert_header(TOKEN)
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::NO_CONTENT
+    );
+    let req = test::TestRequest::get()
+        .uri("/buckets/2025-09-29T12Z?user=sam")
+        .insert_header(TOKEN)
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::NOT_FOUND
+    );
+
+    // Archived buckets are still served, from the monthly file.
+    let cutoff = chrono::Utc::now() + chrono::Duration::hours(1);
+    let dir = retention.archive_dir.clone();
+    archive_store
+        .write(move |s| s.archive_before(cutoff, &dir, |_| true))
+        .await
+        .unwrap();
+    let req = test::TestRequest::get()
+        .uri(&format!("/buckets/{current}?user=patrick"))
+        .insert_header(TOKEN)
+        .to_request();
+    let detail: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(detail["archived"]["events"], 1);
+    assert_eq!(detail["bucket"]["events"][0]["tmdb_id"], 603);
+}
@@ -0,0 +1,98 @@
+use actix_web::test::TestRequest;
+use hmac::{Hmac, Mac};
+use movie_recommendation_engine::auth::{AuthError, IpRange, WebhookAuth};
+use sha2::Sha256;
+
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[test]
+fn token_in_header_or_query() {
+    let auth = WebhookAuth::new(Some("s3cret".into()), None, vec![]);
+
+    let ok_header = TestRequest::post()
+        .insert_header(("X-Alfred-Token", "s3cret"))
+        .to_http_request();
+    assert_eq!(auth.verify(&ok_header, b"{}"), Ok(()));
+
+    let ok_query = TestRequest::post()
+        .uri("/tautulli?token=s3cret")
+        .to_http_request();
+    assert_eq!(auth.verify(&ok_query, b"{}"), Ok(()));
+
+    // Tokens with reserved characters arrive percent-encoded.
+    let auth_plus = WebhookAuth::new(Some("a+b/c=d".into()), None, vec![]);
+    let encoded = TestRequest::post()
+        .uri("/plex?event=1&token=a%2Bb%2Fc%3Dd")
+        .to_http_request();
+    assert_eq!(auth_plus.verify(&encoded, b"{}"), Ok(()));
+
+    let bad = TestRequest::post()
+        .insert_header(("X-Alfred-Token", "nope"))
+        .to_http_request();
+    assert_eq!(auth.verify(&bad, b"{}"), Err(AuthError::BadToken));
+
+    let missing = TestRequest::post().to_http_request();
+    assert_eq!(
+        auth.verify(&missing, b"{}"),
+        Err(AuthError::MissingCredentials)
+    );
+}
+
+#[test]
+fn hmac_signature_covers_body() {
+    let auth = WebhookAuth::new(None, Some("key".into()), vec![]);
+    let body = br#"{"event":"watched"}"#;
+
+    let ok = TestRequest::post()
+        .insert_header(("X-Alfred-Signature", sign("key", body)))
+        .to_http_request();
+    assert_eq!(auth.verify(&ok, body), Ok(()));
+
+    let tampered = TestRequest::post()
+        .insert_header(("X-Alfred-Signature", sign("key", body)))
+        .to_http_request();
+    assert_eq!(
+        auth.verify(&tampered, br#"{"event":"play"}"#),
+        Err(AuthError::BadSignature)
+    );
+}
+
+#[test]
+fn ip_allowlist() {
+    let auth = WebhookAuth::new(None, None, vec![IpRange::parse("192.168.1.0/24").unwrap()]);
+
+    let lan = TestRequest::post()
+        .peer_addr("192.168.1.40:5000".parse().unwrap())
+        .to_http_request();
+    assert_eq!(auth.verify(&lan, b""), Ok(()));
+
+    let outside = TestRequest::post()
+        .peer_addr("10.0.0.2:5000".parse().unwrap())
+        .to_http_request();
+    assert_eq!(auth.verify(&outside, b""), Err(AuthError::IpNotAllowed));
+}
+
+#[test]
+fn from_env_requires_a_secret_or_explicit_opt_out() {
+    // The only test in this binary that touches the WEBHOOK_* variables
+    for var in [
+        "WEBHOOK_TOKEN",
+        "WEBHOOK_HMAC_SECRET",
+        "WEBHOOK_ALLOWED_IPS",
+    ] {
+        std::env::remove_var(var);
+    }
+    std::env::remove_var("WEBHOOK_AUTH");
+    assert!(WebhookAuth::from_env().is_err());
+
+    std::env::set_var("WEBHOOK_AUTH", "off");
+    assert!(!WebhookAuth::from_env().unwrap().requires_secret());
+
+    std::env::remove_var("WEBHOOK_AUTH");
+    std::env::set_var("WEBHOOK_TOKEN", "s3cret");
+    assert!(WebhookAuth::from_env().unwrap().requires_secret());
+}
@@ -0,0 +1,142 @@
+use chrono::{TimeZone, Utc};
+use lib::store::Bucket;
+use movie_recommendation_engine::bucketing::{BucketStrategy, Window};
+
+fn utc(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> chrono::DateTime<Utc> {
+    Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+}
+
+fn bucket(id: i64, tag: &str, user: Option<&str>, count: usize) -> Bucket {
+    Bucket {
+        id,
+        tag: tag.into(),
+        user: user.map(String::from),
+        started_at: None,
+        updated_at: None,
+        count,
+        recommendations_generated_at: None,
+    }
+}
+
+#[test]
+fn default_keeps_legacy_six_hour_tags() {
+    let s = BucketStrategy::default();
+    assert_eq!(s, BucketStrategy::parse("fixed:6h", "UTC").unwrap());
+    assert_eq!(s.tag_for(utc(2025, 9, 29, 13, 45), None), "2025-09-29T12Z");
+    assert_eq!(s.tag_for(utc(2025, 9, 29, 0, 0), None), "2025-09-29T00Z");
+    assert_eq!(s.tag_start("2025-09-29T12Z"), Some(utc(2025, 9, 29, 12, 0)));
+    assert_eq!(
+        s.next_boundary(utc(2025, 9, 29, 13, 45)),
+        Some(utc(2025, 9, 29, 18, 0))
+    );
+    assert!(!s.is_closed("2025-09-29T12Z", 3, utc(2025, 9, 29, 17, 59)));
+    assert!(s.is_closed("2025-09-29T12Z", 3, utc(2025, 9, 29, 18, 0)));
+}
+
+#[test]
+fn fixed_windows_align_to_local_midnight() {
+    let s = BucketStrategy::parse("fixed:90m", "UTC").unwrap();
+    assert_eq!(s.window, Window::Fixed(90));
+    assert_eq!(s.tag_for(utc(2025, 9, 29, 2, 0), None), "2025-09-29T01:30Z");
+    // 16 windows fit in a day; the last one is cut short at midnight.
+    assert_eq!(
+        s.next_boundary(utc(2025, 9, 29, 23, 0)),
+        Some(utc(2025, 9, 30, 0, 0))
+    );
+
+    let ny = BucketStrategy::parse("fixed:8h", "America/New_York").unwrap();
+    // 03:00 UTC is 23:00 EDT the previous evening.
+    let tag = ny.tag_for(utc(2025, 9, 30, 3, 0), None);
+    assert_eq!(tag, "2025-09-29T16:00-04:00");
+    assert_eq!(ny.tag_start(&tag), Some(utc(2025, 9, 29, 20, 0)));
+    assert_eq!(
+        ny.next_boundary(utc(2025, 9, 30, 3, 0)),
+        Some(utc(2025, 9, 30, 4, 0))
+    );
+}
+
+#[test]
+fn calendar_day_and_week() {
+    let day = BucketStrategy::parse("day", "Europe/Berlin").unwrap();
+    // 23:30 UTC is already the next day in Berlin.
+    assert_eq!(day.tag_for(utc(2025, 9, 29, 23, 30), None), "2025-09-30");
+    assert_eq!(day.tag_start("2025-09-30"), Some(utc(2025, 9, 29, 22, 0)));
+    assert!(day.is_closed("2025-09-30", 1, utc(2025, 9, 30, 22, 0)));
+
+    let week = BucketStrategy::parse("week", "UTC").unwrap();
+    assert_eq!(week.tag_for(utc(2025, 10, 1, 12, 0), None), "2025-W40");
+    assert_eq!(week.tag_start("2025-W40"), Some(utc(2025, 9, 29, 0, 0)));
+    assert_eq!(
+        week.next_boundary(utc(2025, 10, 1, 12, 0)),
+        Some(utc(2025, 10, 6, 0, 0))
+    );
+}
+
+#[test]
+fn count_buckets_fill_then_roll_over() {
+    let s = BucketStrategy::parse("count:3", "UTC").unwrap();
+    let now = utc(2025, 9, 29, 12, 5);
+    let first = s.tag_for(now, None);
+    assert_eq!(first, "2025-09-29T12:05:00Z");
+    assert_eq!(s.tag_for(utc(2025, 10, 2, 9, 0), Some((&first, 2))), first);
+    assert_eq!(
+        s.tag_for(utc(2025, 10, 2, 9, 0), Some((&first, 3))),
+        "2025-10-02T09:00:00Z"
+    );
+    assert!(!s.is_closed(&first, 2, utc(2030, 1, 1, 0, 0)));
+    assert!(s.is_closed(&first, 3, now));
+    assert_eq!(s.next_boundary(now), None);
+    assert_eq!(s.tag_start(&first), Some(now));
+}
+
+#[test]
+fn rejects_bad_config() {
+    assert!(BucketStrategy::parse("fixed:0h", "UTC").is_err());
+    assert!(BucketStrategy::parse("fixed:25h", "UTC").is_err());
+    assert!(BucketStrategy::parse("count:0", "UTC").is_err());
+    assert!(BucketStrategy::parse("fortnight", "UTC").is_err());
+    assert!(BucketStrategy::parse("day", "Mars/Olympus").is_err());
+}
+
+#[test]
+fn latest_closed_bucket_per_user() {
+    let s = BucketStrategy::default();
+    let now = utc(2025, 9, 29, 19, 0);
+    let picked = movie_recommendation_engine::bucketing::latest_closed_per_user(
+        &s,
+        vec![
+            bucket(1, "2025-09-29T06Z", Some("patrick"), 2),
+            bucket(2, "2025-09-29T12Z", Some("patrick"), 1),
+            // still open
+            bucket(3, "2025-09-29T18Z", Some("patrick"), 4),
+            bucket(4, "2025-09-28T18Z", None, 1),
+        ],
+        now,
+    );
+    let tags: Vec<_> = picked.iter().map(|b| b.tag.as_str()).collect();
+    assert_eq!(tags, vec!["2025-09-28T18Z", "2025-09-29T12Z"]);
+}
+
+#[test]
+fn recommendations_run_just_after_a_bucket_closes() {
+    use lib::scheduler::Schedule;
+
+    let s = BucketStrategy::default();
+    let closed = Some(utc(2025, 9, 29, 18, 1));
+    assert_eq!(s.next_after(utc(2025, 9, 29, 13, 45)), closed);
+    // Inside the grace minute the closing window's run is still ahead.
+    assert_eq!(
+        s.next_after(Utc.with_ymd_and_hms(2025, 9, 29, 18, 0, 30).unwrap()),
+        closed
+    );
+    assert_eq!(
+        s.next_after(utc(2025, 9, 29, 18, 1)),
+        Some(utc(2025, 9, 30, 0, 1))
+    );
+
+    let count = BucketStrategy::parse("count:5", "UTC").unwrap();
+    assert_eq!(
+        count.next_after(utc(2025, 9, 29, 13, 45)),
+        Some(utc(2025, 9, 29, 14, 0))
+    );
+}
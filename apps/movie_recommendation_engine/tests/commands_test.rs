@@ -0,0 +1,100 @@
+mod common;
+
+use chrono::{TimeZone, Utc};
+use common::{temp_db, watched};
+use movie_recommendation_engine::bucketing::BucketStrategy;
+use movie_recommendation_engine::commands::{self, BatchReport, Status};
+use movie_recommendation_engine::server::RECOMMENDATIONS_JOB;
+
+#[test]
+fn since_accepts_dates_and_timestamps() {
+    let ny: chrono_tz::Tz = "America/New_York".parse().unwrap();
+    assert_eq!(
+        commands::parse_since("2025-09-29", ny).unwrap(),
+        Utc.with_ymd_and_hms(2025, 9, 29, 4, 0, 0).unwrap()
+    );
+    assert_eq!(
+        commands::parse_since("2025-09-29T12:00:00+02:00", ny).unwrap(),
+        Utc.with_ymd_and_hms(2025, 9, 29, 10, 0, 0).unwrap()
+    );
+    assert!(commands::parse_since("last tuesday", ny).is_err());
+}
+
+#[actix_rt::test]
+async fn list_regenerate_and_job_lock() {
+    let db = temp_db();
+    let store = db.spawn();
+    let strategy = BucketStrategy::default();
+    store
+        .write(|s| {
+            s.append_event(
+                "2025-09-28T18Z",
+                None,
+                "2025-09-28T18:05:00Z",
+                &watched(None),
+            )?;
+            s.append_event(
+                "2025-09-29T06Z",
+                Some("sam"),
+                "2025-09-29T06:05:00Z",
+                &watched(None),
+            )?;
+            s.append_event(
+                "2999-01-01T00Z",
+                Some("sam"),
+                "2999-01-01T00:05:00Z",
+                &watched(603),
+            )
+        })
+        .await
+        .unwrap();
+
+    let lines = commands::list(&store, strategy, None, 10).await.unwrap();
+    let statuses: Vec<_> = lines
+        .iter()
+        .map(|l| (l.bucket.tag.as_str(), l.status.clone()))
+        .collect();
+    assert_eq!(
+        statuses,
+        vec![
+            ("2999-01-01T00Z", Status::Open),
+            ("2025-09-29T06Z", Status::Pending),
+            ("2025-09-28T18Z", Status::Pending),
+        ]
+    );
+
+    let since = commands::parse_since("2025-09-29", strategy.tz).unwrap();
+    let all = store.read(|s| s.all_buckets()).await.unwrap();
+    let picked = commands::closed_since(&strategy, all, since, Utc::now());
+    let tags: Vec<_> = picked.iter().map(|b| b.tag.as_str()).collect();
+    assert_eq!(tags, vec!["2025-09-29T06Z"]);
+
+    // Neither closed bucket has a tmdb id, so no OpenAI call is made.
+    let client = reqwest::Client::new();
+    let report = commands::generate(&store, &client, picked).await.unwrap();
+    assert_eq!(
+        report,
+        Some(BatchReport {
+            buckets: 1,
+            empty: 1,
+            ..Default::default()
+        })
+    );
+
+    // A scheduled run in progress (here or in the server) blocks a manual one.
+    store
+        .write(|s| {
+            s.try_start_job(
+                RECOMMENDATIONS_JOB,
+                Utc::now(),
+                std::time::Duration::from_secs(60),
+            )
+        })
+        .await
+        .unwrap();
+    let all = store.read(|s| s.all_buckets()).await.unwrap();
+    assert_eq!(
+        commands::generate(&store, &client, all).await.unwrap(),
+        None
+    );
+}
@@ -0,0 +1,45 @@
+// Each test binary uses its own subset of these.
+#![allow(dead_code)]
+
+use lib::store::{EventRow, StoreHandle};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// A database file in a fresh temporary directory, deleted with it
+pub struct TestDb {
+    dir: TempDir,
+    pub path: PathBuf,
+}
+
+pub fn temp_db() -> TestDb {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("alfred.sqlite3");
+    TestDb { dir, path }
+}
+
+impl TestDb {
+    /// The directory holding the database, for files kept next to it
+    pub fn dir(&self) -> &Path {
+        self.dir.path()
+    }
+
+    pub fn spawn(&self) -> StoreHandle {
+        StoreHandle::spawn(&self.path).unwrap()
+    }
+}
+
+/// A movie watched through Tautulli, with or without a TMDB id, as ingest stores it.
+pub fn watched(tmdb_id: impl Into<Option<u32>>) -> EventRow {
+    let tmdb_id = tmdb_id.into();
+    EventRow {
+        source: "tautulli".into(),
+        event: "watched".into(),
+        media_type: Some("movie".into()),
+        tmdb_id,
+        data: match tmdb_id {
+            Some(id) => format!(r#"{{"tmdb_id":{id}}}"#),
+            None => "{}".into(),
+        },
+        ..Default::default()
+    }
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use common::{temp_db, watched};
+use lib::clients::plex::get_watched::PlexWatched;
+use lib::clients::radarr::get_movies::RadarrMovie;
+use movie_recommendation_engine::exclude::{self, ExclusionConfig, Exclusions};
+use movie_recommendation_engine::recommend::MediaType;
+use reqwest::Client;
+
+fn radarr(tmdb_id: Option<u32>, has_file: bool) -> RadarrMovie {
+    RadarrMovie {
+        tmdb_id,
+        has_file,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn combines_history_radarr_and_plex() {
+    let history = vec![("movie".to_string(), 603), ("tv".to_string(), 1396)];
+    let library = vec![
+        radarr(Some(27205), true),
+        radarr(Some(157336), false),
+        radarr(None, true),
+    ];
+    let plex = PlexWatched {
+        movies: vec![78, 603],
+        shows: vec![95396],
+    };
+    let ex = Exclusions::from_sources(&history, &library, &plex);
+    assert_eq!(ex.movies, [78, 603, 27205, 157336].into());
+    assert_eq!(ex.shows, [1396, 95396].into());
+    assert!(ex.for_type(MediaType::Tv).contains(&1396));
+
+    // Lowest ids first, capped.
+    assert_eq!(ex.prompt_ids(MediaType::Movie, 2), vec![78, 603]);
+    assert_eq!(ex.prompt_ids(MediaType::Tv, 10), vec![1396, 95396]);
+}
+
+#[actix_rt::test]
+async fn load_uses_the_store_and_cached_sources() {
+    let db = temp_db();
+    let store = db.spawn();
+    let cached = serde_json::to_string(&vec![radarr(Some(27205), true)]).unwrap();
+    store
+        .write(move |s| {
+            let ev = watched(603);
+            s.append_event("2025-09-29T12Z", Some("sam"), "2025-09-29T12:00:00Z", &ev)?;
+            s.cache_put("exclude_radarr", "all", &cached)
+        })
+        .await
+        .unwrap();
+
+    // Only the history when nothing else is configured.
+    let ex = exclude::load(&store, &Client::new(), &ExclusionConfig::default()).await;
+    assert_eq!(ex.movies, [603].into());
+
+    // A fresh cached library is used without asking Radarr.
+    let config = ExclusionConfig {
+        radarr: Some(("http://127.0.0.1:9".into(), "key".into())),
+        ..Default::default()
+    };
+    let ex = exclude::load(&store, &Client::new(), &config).await;
+    assert_eq!(ex.movies, [603, 27205].into());
+    assert!(ex.shows.is_empty());
+}
@@ -0,0 +1,189 @@
+mod common;
+
+use actix_web::{http::StatusCode, test, web, App};
+use common::{temp_db, watched};
+use lib::store::{EventRow, Feedback, Recommendation};
+use movie_recommendation_engine::auth::WebhookAuth;
+use movie_recommendation_engine::bucketing::BucketStrategy;
+use movie_recommendation_engine::events::IngestConfig;
+use movie_recommendation_engine::exclude::Exclusions;
+use movie_recommendation_engine::feedback::{FeedbackStats, PromptFeedback};
+use movie_recommendation_engine::recommend::MediaType;
+use movie_recommendation_engine::users::UserPolicy;
+use serde_json::{json, Value};
+
+const TOKEN: (&str, &str) = ("X-Alfred-Token", "s3cret");
+
+fn fb(media_type: &str, tmdb_id: u32, kind: &str, source: Option<&str>, at: &str) -> Feedback {
+    Feedback {
+        user: Some("patrick".into()),
+        media_type: media_type.into(),
+        tmdb_id,
+        kind: kind.into(),
+        source: source.map(String::from),
+        created_at: at.into(),
+    }
+}
+
+#[actix_rt::test]
+async fn summarizes_feedback_for_prompts_exclusions_and_stats() {
+    // Newest first, as the store returns it
+    let feedback = vec![
+        fb("movie", 3, "like", Some("llm"), "2025-10-04T00:00:00Z"),
+        fb(
+            "movie",
+            2,
+            "not_interested",
+            Some("tmdb"),
+            "2025-10-03T00:00:00Z",
+        ),
+        fb("tv", 1396, "dislike", Some("llm"), "2025-10-02T00:00:00Z"),
+        fb("movie", 1, "like", Some("llm"), "2025-10-01T00:00:00Z"),
+        fb("movie", 603, "seen", None, "2025-09-01T00:00:00Z"),
+    ];
+
+    let movies = PromptFeedback::new(&feedback, MediaType::Movie, 1);
+    assert_eq!((movies.liked, movies.disliked), (vec![3], vec![2]));
+    let shows = PromptFeedback::new(&feedback, MediaType::Tv, 20);
+    assert_eq!((shows.liked, shows.disliked), (vec![], vec![1396]));
+
+    // Everything but a like is kept out of later recommendations.
+    let mut ex = Exclusions::default();
+    ex.add_feedback(&feedback);
+    assert_eq!(ex.movies, [2, 603].into());
+    assert_eq!(ex.shows, [1396].into());
+
+    // Compared as times, not strings: this is midnight UTC.
+    let since = "2025-10-01T02:00:00+02:00".parse().ok();
+    let stats = FeedbackStats::new(&feedback, since);
+    assert_eq!(stats.all.total, 4);
+    assert_eq!(stats.all.like_rate, Some(0.5));
+    assert_eq!(stats.by_source["llm"].like_rate, Some(2.0 / 3.0));
+    assert_eq!(stats.by_source["tmdb"].not_interested, 1);
+    assert_eq!(stats.by_media_type["tv"].dislike, 1);
+    assert!(!stats.by_source.contains_key("none"));
+    assert_eq!(
+        FeedbackStats::new(&feedback, None).by_source["none"].seen,
+        1
+    );
+}
+
+#[actix_rt::test]
+async fn feedback_endpoints() {
+    let db = temp_db();
+    let store = db.spawn();
+    store
+        .write(|s| {
+            let row = EventRow {
+                source: "manual".into(),
+                ..watched(603)
+            };
+            let id = s.append_event("2025-09-29T12Z", Some("patrick"), "", &row)?;
+            let rec = Recommendation {
+                tmdb_id: 604,
+                media_type: "movie".into(),
+                source: Some("tmdb".into()),
+                ..Default::default()
+            };
+            s.set_recommendations(id, &[rec], "2025-09-29T18:00:00Z")?;
+            Ok(())
+        })
+        .await
+        .unwrap();
+    let ingest = IngestConfig {
+        store,
+        buckets: BucketStrategy::default(),
+        watched_threshold_percent: 85.0,
+        users: UserPolicy::new(&[("pmclennan", "patrick")], &["guest"]),
+    };
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(WebhookAuth::new(
+                Some("s3cret".into()),
+                None,
+                vec![],
+            )))
+            .app_data(web::Data::new(ingest))
+            .configure(movie_recommendation_engine::feedback::configure),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/feedback")
+        .set_json(json!({ "tmdb_id": 604, "kind": "like" }))
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+
+    // The alias resolves, and the recommender that suggested it is noted.
+    let req = test::TestRequest::post()
+        .uri("/feedback")
+        .insert_header(TOKEN)
+        .set_json(json!({ "tmdb_id": 604, "kind": "dislike", "user": "PMcLennan" }))
+        .to_request();
+    let res: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(res["user"], "patrick");
+    assert_eq!(res["source"], "tmdb");
+
+    let req = test::TestRequest::post()
+        .uri("/feedback")
+        .insert_header(TOKEN)
+        .set_json(json!({ "tmdb_id": 1396, "media_type": "tv", "kind": "like", "user": "patrick" }))
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::CREATED
+    );
+
+    for (body, expected) in [
+        (
+            json!({ "tmdb_id": 1, "kind": "meh" }),
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            json!({ "tmdb_id": 1, "kind": "like", "user": "guest" }),
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ),
+    ] {
+        let req = test::TestRequest::post()
+            .uri("/feedback")
+            .insert_header(TOKEN)
+            .set_json(body)
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), expected);
+    }
+
+    let req = test::TestRequest::get()
+        .uri("/feedback?user=patrick")
+        .insert_header(TOKEN)
+        .to_request();
+    let list: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(list.as_array().unwrap().len(), 2);
+
+    let req = test::TestRequest::get()
+        .uri("/feedback/stats")
+        .insert_header(TOKEN)
+        .to_request();
+    let stats: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(stats["all"]["total"], 2);
+    assert_eq!(stats["all"]["like_rate"], 0.5);
+    assert_eq!(stats["by_source"]["tmdb"]["dislike"], 1);
+    assert_eq!(stats["by_source"]["none"]["like"], 1);
+
+    let req = test::TestRequest::get()
+        .uri("/feedback/stats?since=2999-01-01")
+        .insert_header(TOKEN)
+        .to_request();
+    let stats: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(stats["all"]["total"], 0);
+    let req = test::TestRequest::get()
+        .uri("/feedback/stats?since=last+week")
+        .insert_header(TOKEN)
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::BAD_REQUEST
+    );
+}
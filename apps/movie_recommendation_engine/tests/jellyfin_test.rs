@@ -0,0 +1,64 @@
+use movie_recommendation_engine::events::EventKind;
+use movie_recommendation_engine::jellyfin::{EmbyHook, JellyfinHook};
+use movie_recommendation_engine::recommend::collect_watched;
+
+#[test]
+fn jellyfin_flat_provider_ids_and_completion() {
+    let body = r#"{"NotificationType":"PlaybackStop","NotificationUsername":"patrick","Name":"Arrival",
+        "ItemType":"Movie","ItemId":"abc","Provider_tmdb":"329865","Provider_imdb":"tt2543164",
+        "PlayedToCompletion":"True","PlaybackPositionTicks":"67000000000","RunTimeTicks":"69600000000"}"#;
+    let ev = serde_json::from_str::<JellyfinHook>(body)
+        .unwrap()
+        .into_event(None, body.to_string());
+    assert_eq!(ev.source, "jellyfin");
+    assert_eq!(ev.event, EventKind::Watched);
+    assert_eq!(ev.user.as_deref(), Some("patrick"));
+    assert_eq!(ev.tmdb_id, Some(329865));
+    assert_eq!(ev.media_type.as_deref(), Some("movie"));
+    assert_eq!(ev.duration_secs, Some(6960));
+    assert!(ev.is_completed_watch(85.0));
+}
+
+#[test]
+fn jellyfin_abandoned_stop_is_not_a_watch() {
+    let body = r#"{"NotificationType":"PlaybackStop","NotificationUsername":"patrick","Name":"Heat",
+        "ItemType":"Movie","ProviderIds":{"Tmdb":"949"},"PlayedToCompletion":false,
+        "PlaybackPositionTicks":6000000000,"RunTimeTicks":102000000000}"#;
+    let ev = serde_json::from_str::<JellyfinHook>(body)
+        .unwrap()
+        .into_event(None, body.to_string());
+    assert_eq!(ev.event, EventKind::Stop);
+    assert_eq!(ev.tmdb_id, Some(949));
+    assert!(!ev.is_completed_watch(85.0));
+}
+
+#[test]
+fn emby_nested_payload() {
+    let body = r#"{"Event":"playback.stop","User":{"Name":"sam"},
+        "Item":{"Name":"Dune","Type":"Movie","Id":"42","ProviderIds":{"Tmdb":"438631","Imdb":"tt1160419"},"RunTimeTicks":93600000000},
+        "PlaybackInfo":{"PlayedToCompletion":true,"PositionTicks":93000000000},"Session":{"Id":"s1"}}"#;
+    let ev = serde_json::from_str::<EmbyHook>(body)
+        .unwrap()
+        .into_event(None, body.to_string());
+    assert_eq!(ev.source, "emby");
+    assert_eq!(ev.event, EventKind::Watched);
+    assert_eq!(ev.user.as_deref(), Some("sam"));
+    assert_eq!(ev.tmdb_id, Some(438631));
+    assert_eq!(ev.session_id.as_deref(), Some("s1"));
+}
+
+#[test]
+fn jellyfin_episode_is_kept_by_series_name() {
+    let body = r#"{"NotificationType":"PlaybackStop","NotificationUsername":"patrick","Name":"Pilot",
+        "ItemType":"Episode","ItemId":"e1","SeriesName":"Severance","Provider_tmdb":"1959351",
+        "PlayedToCompletion":true}"#;
+    let ev = serde_json::from_str::<JellyfinHook>(body)
+        .unwrap()
+        .into_event(None, body.to_string());
+    // The provider id is the episode's, not the series'.
+    assert_eq!(ev.tmdb_id, None);
+    let watched = collect_watched(&[serde_json::to_value(ev).unwrap()]);
+    assert_eq!(watched.shows.len(), 1);
+    assert_eq!(watched.shows[0].title.as_deref(), Some("Severance"));
+    assert_eq!(watched.shows[0].episodes, 1);
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use common::temp_db;
+use movie_recommendation_engine::bucketing::BucketStrategy;
+use movie_recommendation_engine::events::{EventKind, IngestConfig};
+use movie_recommendation_engine::plex::{multipart_field, PlexHook};
+use movie_recommendation_engine::recommend::collect_watched;
+use movie_recommendation_engine::users::UserPolicy;
+
+const SCROBBLE: &str = r#"{"event":"media.scrobble","user":true,"owner":true,
+    "Account":{"id":1,"title":"patrick"},"Player":{"uuid":"p1"},
+    "Metadata":{"type":"movie","title":"Arrival","ratingKey":"1936","duration":6960000,"viewOffset":6500000,
+        "guid":"plex://movie/5d77","Guid":[{"id":"imdb://tt2543164"},{"id":"tmdb://329865"}]}}"#;
+
+#[test]
+fn payload_is_pulled_from_multipart() {
+    let ct = "multipart/form-data; boundary=------------------------abc123";
+    let body = [
+        b"--------------------------abc123\r\nContent-Disposition: form-data; name=\"payload\"\r\nContent-Type: application/json\r\n\r\n".as_slice(),
+        SCROBBLE.as_bytes(),
+        b"\r\n--------------------------abc123\r\nContent-Disposition: form-data; name=\"thumb\"; filename=\"t.jpg\"\r\nContent-Type: image/jpeg\r\n\r\n\xff\xd8\xff\x00\r\n--------------------------abc123--\r\n".as_slice(),
+    ]
+    .concat();
+    let payload = multipart_field(ct, &body, "payload").unwrap();
+    assert_eq!(payload, SCROBBLE.as_bytes());
+    assert_eq!(
+        multipart_field(ct, &body, "thumb").unwrap(),
+        b"\xff\xd8\xff\x00"
+    );
+    assert!(multipart_field(ct, &body, "missing").is_none());
+}
+
+#[test]
+fn scrobble_becomes_completed_watch() {
+    let hook: PlexHook = serde_json::from_str(SCROBBLE).unwrap();
+    let ev = hook.into_event(None, SCROBBLE.to_string());
+    assert_eq!(ev.source, "plex");
+    assert_eq!(ev.event, EventKind::Watched);
+    assert_eq!(ev.user.as_deref(), Some("patrick"));
+    assert_eq!(ev.tmdb_id, Some(329865));
+    assert_eq!(ev.duration_secs, Some(6960));
+    assert!(ev.is_completed_watch(85.0));
+}
+
+#[test]
+fn library_new_is_not_a_watch() {
+    let body = r#"{"event":"library.new","Metadata":{"type":"movie","title":"Dune","Guid":[{"id":"tmdb://438631"}]}}"#;
+    let ev = serde_json::from_str::<PlexHook>(body)
+        .unwrap()
+        .into_event(None, body.to_string());
+    assert_eq!(ev.event, EventKind::Other);
+    assert_eq!(ev.tmdb_id, Some(438631));
+    assert!(!ev.is_completed_watch(85.0));
+}
+
+#[actix_rt::test]
+async fn scrobble_and_its_stop_count_as_one_watch() {
+    let db = temp_db();
+    let store = db.spawn();
+    let ingest = IngestConfig {
+        store: store.clone(),
+        buckets: BucketStrategy::default(),
+        watched_threshold_percent: 85.0,
+        users: UserPolicy::default(),
+    };
+    let stop = SCROBBLE.replace("media.scrobble", "media.stop");
+    let other_player = SCROBBLE.replace("\"p1\"", "\"p2\"");
+    for body in [SCROBBLE, &stop, &other_player] {
+        let hook: PlexHook = serde_json::from_str(body).unwrap();
+        let ev = hook.into_event(None, body.to_string());
+        assert!(ev.is_completed_watch(85.0));
+        ingest.ingest(ev).await;
+    }
+
+    // The stop repeats the scrobble; watching again on another player is a rewatch.
+    let events = store
+        .read(|s| {
+            let bucket = s.latest_bucket(Some("patrick"))?.unwrap();
+            s.bucket_events(bucket.id)
+        })
+        .await
+        .unwrap();
+    let kinds: Vec<&str> = events.iter().map(|e| e.row.event.as_str()).collect();
+    assert_eq!(kinds, ["watched", "watched"]);
+}
+
+#[test]
+fn episodes_reach_the_watched_shows() {
+    let episode = |grandparent_guid: &str| {
+        format!(
+            r#"{{"event":"media.scrobble","Account":{{"title":"patrick"}},
+            "Metadata":{{"type":"episode","title":"Pilot","grandparentTitle":"Breaking Bad",
+                "grandparentGuid":"{grandparent_guid}","ratingKey":"77",
+                "Guid":[{{"id":"tmdb://62085"}},{{"id":"tvdb://349232"}}]}}}}"#
+        )
+    };
+    let events: Vec<serde_json::Value> = [
+        // The legacy TVDB agent names the series; the episode's own ids are ignored.
+        episode("com.plexapp.agents.thetvdb://81189?lang=en"),
+        // The new agent doesn't, so the show's title is kept for a TMDB search.
+        episode("plex://show/5d9c086c46115600200aa2fe"),
+    ]
+    .iter()
+    .map(|body| {
+        let ev = serde_json::from_str::<PlexHook>(body)
+            .unwrap()
+            .into_event(None, body.clone());
+        assert_eq!(ev.show_title.as_deref(), Some("Breaking Bad"));
+        serde_json::to_value(ev).unwrap()
+    })
+    .collect();
+
+    let watched = collect_watched(&events);
+    assert!(watched.movie_ids.is_empty());
+    let shows: Vec<_> = watched
+        .shows
+        .iter()
+        .map(|s| (s.tmdb_id, s.tvdb_id, s.title.as_deref(), s.episodes))
+        .collect();
+    assert_eq!(
+        shows,
+        [
+            (None, None, Some("Breaking Bad"), 1),
+            (None, Some(81189), Some("Breaking Bad"), 1),
+        ]
+    );
+}
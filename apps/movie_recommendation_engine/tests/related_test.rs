@@ -0,0 +1,142 @@
+use chrono::NaiveDate;
+use lib::clients::tmdb::get_related::{RelatedList, RelatedTitle};
+use movie_recommendation_engine::recommend::{MediaType, RecItem, RecSource, Recommended};
+use movie_recommendation_engine::related::{blend, combine, score_candidates, TmdbMode};
+use movie_recommendation_engine::validate::{RejectReason, Rejection, ValidationReport};
+use std::collections::HashSet;
+
+fn title(id: u32, rating: f64, votes: u32, popularity: f64) -> RelatedTitle {
+    RelatedTitle {
+        id,
+        release_date: Some("2010-07-15".into()),
+        vote_average: Some(rating),
+        vote_count: Some(votes),
+        popularity: Some(popularity),
+        ..Default::default()
+    }
+}
+
+fn item(tmdb_id: u32, source: RecSource) -> RecItem {
+    RecItem {
+        tmdb_id,
+        media_type: MediaType::Movie,
+        source,
+        ..Default::default()
+    }
+}
+
+fn ids(items: &[RecItem]) -> Vec<u32> {
+    items.iter().map(|r| r.tmdb_id).collect()
+}
+
+#[test]
+fn scores_by_frequency_rating_and_popularity() {
+    let today = NaiveDate::from_ymd_opt(2025, 10, 1).unwrap();
+    let lists = vec![
+        (
+            27205,
+            RelatedList::Recommendations,
+            vec![title(1, 7.0, 1000, 50.0), title(2, 8.5, 5000, 80.0)],
+        ),
+        (
+            157336,
+            RelatedList::Recommendations,
+            vec![title(1, 7.0, 1000, 50.0), title(3, 9.0, 10, 1.0)],
+        ),
+        (
+            157336,
+            RelatedList::Similar,
+            vec![
+                title(2, 8.5, 5000, 80.0),
+                title(4, 8.0, 2000, 40.0),
+                RelatedTitle {
+                    adult: true,
+                    ..title(5, 9.0, 9000, 99.0)
+                },
+                RelatedTitle {
+                    release_date: Some("2026-01-01".into()),
+                    ..title(6, 9.0, 9000, 99.0)
+                },
+                title(7, 9.0, 9000, 99.0),
+            ],
+        ),
+    ];
+    let excluded: HashSet<u32> = [7].into();
+    let scored = score_candidates(&lists, &excluded, today);
+    // Two recommendation hits beat one of each kind, even against a better-rated,
+    // more popular film; how often a title comes up matters most. Excluded, adult and
+    // unreleased titles are dropped.
+    assert_eq!(
+        scored.iter().map(|s| s.tmdb_id).collect::<Vec<_>>(),
+        vec![1, 2, 3, 4]
+    );
+    assert_eq!((scored[0].hits, scored[1].hits), (2, 2));
+    // Each is put down to the watches it came from, the stronger link first.
+    assert_eq!(scored[0].because_of, vec![27205, 157336]);
+    assert_eq!(scored[1].because_of, vec![27205, 157336]);
+    assert_eq!(scored[3].because_of, vec![157336]);
+    assert!(scored.windows(2).all(|w| w[0].score >= w[1].score));
+}
+
+#[test]
+fn blend_alternates_without_repeats() {
+    let llm = vec![item(1, RecSource::Llm), item(2, RecSource::Llm)];
+    let tmdb = vec![
+        item(2, RecSource::Tmdb),
+        item(3, RecSource::Tmdb),
+        item(4, RecSource::Tmdb),
+    ];
+    assert_eq!(ids(&blend(llm.clone(), tmdb.clone(), 10)), vec![1, 2, 3, 4]);
+    assert_eq!(ids(&blend(llm, tmdb, 3)), vec![1, 2, 3]);
+}
+
+#[actix_rt::test]
+async fn combine_follows_the_mode() {
+    let llm = |n: u32| {
+        Some(Recommended {
+            items: (1..=n).map(|i| item(i, RecSource::Llm)).collect(),
+            ..Default::default()
+        })
+    };
+    // Picks come in checked; what the check dropped joins the report.
+    let tmdb = || async {
+        Recommended {
+            items: vec![item(2, RecSource::Tmdb), item(10, RecSource::Tmdb)],
+            report: ValidationReport {
+                rejected: vec![Rejection {
+                    tmdb_id: 11,
+                    title: None,
+                    reason: RejectReason::OffGenre,
+                }],
+                ..Default::default()
+            },
+        }
+    };
+
+    // Off and a full LLM answer leave it alone.
+    let r = combine(TmdbMode::Off, None, 3, tmdb).await;
+    assert!(r.is_none());
+    let r = combine(TmdbMode::Fallback, llm(3), 3, tmdb).await.unwrap();
+    assert_eq!(ids(&r.items), vec![1, 2, 3]);
+    assert_eq!(r.report.from_tmdb, 0);
+    assert!(r.report.rejected.is_empty());
+
+    // A short or failed LLM answer is topped up.
+    let r = combine(TmdbMode::Fallback, llm(2), 3, tmdb).await.unwrap();
+    assert_eq!(ids(&r.items), vec![1, 2, 10]);
+    assert_eq!(r.items[2].source, RecSource::Tmdb);
+    assert_eq!(r.report.from_tmdb, 1);
+    assert_eq!(r.report.rejected[0].tmdb_id, 11);
+    let r = combine(TmdbMode::Fallback, None, 3, tmdb).await.unwrap();
+    assert_eq!(ids(&r.items), vec![2, 10]);
+    let report = &r.report;
+    assert_eq!(
+        (report.target, report.accepted, report.from_tmdb),
+        (3, 0, 2)
+    );
+
+    let r = combine(TmdbMode::Primary, None, 3, tmdb).await.unwrap();
+    assert_eq!(r.report.from_tmdb, 2);
+    let r = combine(TmdbMode::Blend, llm(3), 3, tmdb).await.unwrap();
+    assert_eq!(ids(&r.items), vec![1, 2, 10]);
+}
@@ -0,0 +1,83 @@
+use lib::clients::tmdb::search_movie::MovieSearchResult;
+use lib::clients::tmdb::search_tv::TvSearchResult;
+use movie_recommendation_engine::resolve::{
+    best_match, best_show_match, normalize, title_similarity,
+};
+
+fn hit(id: u32, title: &str, date: &str, votes: u32) -> MovieSearchResult {
+    MovieSearchResult {
+        id,
+        title: title.into(),
+        release_date: Some(date.into()),
+        vote_count: Some(votes),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn normalizes_articles_punctuation_and_ampersands() {
+    assert_eq!(
+        normalize("The Lord of the Rings: The Two Towers"),
+        "lord of the rings the two towers"
+    );
+    assert_eq!(normalize("Fast & Furious"), "fast and furious");
+    assert_eq!(normalize("  Amélie!  "), "amélie");
+    assert_eq!(normalize("The"), "the");
+    assert_eq!(title_similarity("Se7en", "Se7en"), 1.0);
+    assert!(title_similarity("Arrival", "Arival") > 0.8);
+    assert!(title_similarity("Arrival", "Survival") < 0.75);
+}
+
+#[test]
+fn picks_the_closest_title_within_a_year() {
+    let hits = vec![
+        hit(329865, "Arrival", "2016-11-10", 17000),
+        hit(10292, "The Arrival", "1996-05-31", 400),
+        hit(1, "Arrival of the Dead", "2016-01-01", 3),
+    ];
+    assert_eq!(
+        best_match("Arrival", Some(2016), &hits),
+        Some((329865, 1.0))
+    );
+    // A year out still matches, with less confidence.
+    assert_eq!(
+        best_match("Arrival", Some(2017), &hits),
+        Some((329865, 0.9))
+    );
+    // "The Arrival" (1996) once the article is ignored
+    assert_eq!(
+        best_match("The Arrival", Some(1996), &hits),
+        Some((10292, 1.0))
+    );
+    // Too far out in years, or too different a title
+    assert_eq!(best_match("Arrival", Some(2005), &hits), None);
+    assert_eq!(best_match("Interstellar", Some(2014), &hits), None);
+
+    // Same title, no year: the better-known film wins.
+    let remakes = vec![
+        hit(9426, "The Fly", "1986-08-15", 3800),
+        hit(11815, "The Fly", "1958-07-16", 500),
+    ];
+    assert_eq!(best_match("The Fly", None, &remakes), Some((9426, 0.85)));
+    assert_eq!(
+        best_match("The Fly", Some(1958), &remakes),
+        Some((11815, 1.0))
+    );
+}
+
+#[test]
+fn picks_the_series_by_name() {
+    let show = |id, name: &str, votes| TvSearchResult {
+        id,
+        name: name.into(),
+        vote_count: Some(votes),
+        ..Default::default()
+    };
+    let hits = [
+        show(1, "The Office", 800),
+        show(2526, "The Office", 3500),
+        show(9, "Office Space Stories", 5000),
+    ];
+    assert_eq!(best_show_match("The Office", &hits), Some(2526));
+    assert_eq!(best_show_match("Severance", &hits), None);
+}
@@ -0,0 +1,166 @@
+use lib::clients::tmdb::get_movie_metadata::MovieMetadata;
+use movie_recommendation_engine::recommend::{RecItem, RecSource, Recommended};
+use movie_recommendation_engine::similarity::{combine, MovieDoc, SimilarityIndex, SimilarityMode};
+use std::collections::HashSet;
+
+fn doc(
+    id: u32,
+    genres: &[&str],
+    crew: &[&str],
+    collection: Option<&str>,
+    overview: &str,
+) -> MovieDoc {
+    let strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect();
+    MovieDoc {
+        tmdb_id: id,
+        title: format!("movie {id}"),
+        genres: strings(genres),
+        crew: strings(crew),
+        collection: collection.map(String::from),
+        overview: overview.into(),
+        ..Default::default()
+    }
+}
+
+fn pool() -> Vec<MovieDoc> {
+    vec![
+        doc(
+            603,
+            &["Action", "Science Fiction"],
+            &["Lana Wachowski"],
+            Some("The Matrix Collection"),
+            "A hacker learns reality is a simulation run by machines.",
+        ),
+        doc(
+            604,
+            &["Action", "Science Fiction"],
+            &["Lana Wachowski"],
+            Some("The Matrix Collection"),
+            "Neo fights the machines as they close in on Zion.",
+        ),
+        doc(
+            27205,
+            &["Action", "Science Fiction"],
+            &["Christopher Nolan"],
+            None,
+            "A thief enters dreams to plant an idea.",
+        ),
+        doc(
+            157336,
+            &["Science Fiction", "Drama"],
+            &["Christopher Nolan"],
+            None,
+            "Explorers travel through a wormhole to save humanity.",
+        ),
+        doc(
+            10681,
+            &["Animation", "Family"],
+            &["Andrew Stanton"],
+            None,
+            "A lonely robot cleans up an abandoned planet.",
+        ),
+        doc(
+            19404,
+            &["Romance", "Drama"],
+            &["Aditya Chopra"],
+            None,
+            "Two young people fall in love on a trip across Europe.",
+        ),
+    ]
+}
+
+#[test]
+fn parses_metadata_with_appended_keywords_and_credits() {
+    let m: MovieMetadata = serde_json::from_str(
+        r#"{"id": 603, "title": "The Matrix", "overview": "Neo.",
+            "genres": [{"id": 28, "name": "Action"}],
+            "belongs_to_collection": {"id": 2344, "name": "The Matrix Collection", "poster_path": null, "backdrop_path": null},
+            "keywords": {"keywords": [{"id": 310, "name": "artificial intelligence"}]},
+            "credits": {
+                "cast": [{"id": 2, "name": "Laurence Fishburne", "order": 1}, {"id": 1, "name": "Keanu Reeves", "order": 0}],
+                "crew": [{"id": 9, "name": "Lana Wachowski", "job": "Director"}, {"id": 9, "name": "Lana Wachowski", "job": "Writer"}, {"id": 8, "name": "Grip", "job": "Key Grip"}]
+            }}"#,
+    )
+    .unwrap();
+    let d = MovieDoc::from(m);
+    assert_eq!(d.cast, vec!["Keanu Reeves", "Laurence Fishburne"]);
+    assert_eq!(d.crew, vec!["Lana Wachowski"]);
+    assert_eq!(d.keywords, vec!["artificial intelligence"]);
+    assert_eq!(d.collection.as_deref(), Some("The Matrix Collection"));
+}
+
+#[test]
+fn finds_the_closest_films_to_a_set() {
+    let pool = pool();
+    let index = SimilarityIndex::build(&pool);
+    assert_eq!(index.len(), 6);
+
+    // The sequel shares the collection, director, genres and overview words.
+    let matrix = &pool[0];
+    let best = index.most_similar(std::slice::from_ref(matrix), &HashSet::new(), 3);
+    assert_eq!(best[0].0, 604);
+    assert!(best.iter().all(|(id, _)| *id != 603));
+    assert!(best.windows(2).all(|w| w[0].1 >= w[1].1));
+
+    // Two Nolan films point at each other; excluded and unrelated films are left out.
+    let seeds = vec![pool[2].clone()];
+    let best = index.most_similar(&seeds, &[604].into(), 10);
+    assert_eq!(best[0].0, 157336);
+    assert!(best.iter().all(|(id, _)| *id != 604 && *id != 19404));
+}
+
+#[test]
+fn reranks_candidates_towards_the_seeds() {
+    let pool = pool();
+    let index = SimilarityIndex::build(&pool);
+    let seeds = vec![pool[0].clone(), pool[2].clone()];
+    // A candidate outside the pool is still compared on the features the pool knows.
+    let outsider = doc(
+        1,
+        &["Science Fiction"],
+        &["Christopher Nolan"],
+        None,
+        "A simulation of dreams.",
+    );
+    let candidates = vec![pool[5].clone(), pool[4].clone(), outsider, pool[1].clone()];
+    let order: Vec<u32> = index
+        .rerank(&seeds, &candidates)
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect();
+    assert_eq!(order[..2], [604, 1]);
+    // Nothing in common with either: they keep their order at the end.
+    assert_eq!(order[2..], [19404, 10681]);
+}
+
+#[test]
+fn index_picks_replace_or_blend_with_the_llm() {
+    let item = |id, source| RecItem {
+        tmdb_id: id,
+        source,
+        ..Default::default()
+    };
+    let llm = Some(Recommended {
+        items: vec![item(1, RecSource::Llm), item(2, RecSource::Llm)],
+        ..Default::default()
+    });
+    let picks = Recommended {
+        items: vec![item(2, RecSource::Index), item(30, RecSource::Index)],
+        ..Default::default()
+    };
+    let ids = |r: &Recommended| r.items.iter().map(|i| i.tmdb_id).collect::<Vec<_>>();
+
+    assert!(!SimilarityMode::Recommend.uses_llm());
+    let r = combine(SimilarityMode::Recommend, None, picks.clone(), 3).unwrap();
+    assert_eq!(ids(&r), vec![2, 30]);
+    assert_eq!((r.report.target, r.report.from_index), (3, 2));
+    assert_eq!(r.report.from_tmdb, 0);
+
+    let r = combine(SimilarityMode::Blend, llm, picks.clone(), 3).unwrap();
+    assert_eq!(ids(&r), vec![1, 2, 30]);
+    // Only the LLM's own pick counts as accepted.
+    assert_eq!((r.report.accepted, r.report.from_index), (1, 2));
+
+    // Without either there's nothing to fall back from.
+    assert!(combine(SimilarityMode::Blend, None, Default::default(), 3).is_none());
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use chrono::{Duration, TimeZone, Utc};
+use common::{temp_db, watched};
+use lib::store::EventRow;
+use movie_recommendation_engine::recommend::MediaType;
+use movie_recommendation_engine::taste::{self, TasteConfig, TitleFeatures, Watch};
+use std::collections::HashMap;
+
+fn features(title: &str, year: u16, genres: &[&str], collection: Option<&str>) -> TitleFeatures {
+    TitleFeatures {
+        title: title.into(),
+        year: Some(year),
+        genres: genres.iter().map(|g| g.to_string()).collect(),
+        language: Some("en".into()),
+        collection: collection.map(String::from),
+        keywords: vec!["dystopia".into()],
+    }
+}
+
+#[test]
+fn recent_and_rewatched_titles_dominate() {
+    let now = Utc.with_ymd_and_hms(2025, 10, 1, 0, 0, 0).unwrap();
+    let watch = |tmdb_id, media_type, days_ago| Watch {
+        tmdb_id,
+        media_type,
+        at: now - Duration::days(days_ago),
+    };
+    let watches = vec![
+        // A year ago: two westerns
+        watch(1, MediaType::Movie, 365),
+        watch(2, MediaType::Movie, 360),
+        // Lately: The Matrix twice, and a season of a sitcom
+        watch(603, MediaType::Movie, 30),
+        watch(603, MediaType::Movie, 2),
+        watch(1668, MediaType::Tv, 5),
+        watch(1668, MediaType::Tv, 5),
+        watch(1668, MediaType::Tv, 4),
+        // No features: ignored
+        watch(9999, MediaType::Movie, 1),
+    ];
+    let features = HashMap::from([
+        (
+            (MediaType::Movie, 1),
+            features("Unforgiven", 1992, &["Western"], None),
+        ),
+        (
+            (MediaType::Movie, 2),
+            features("Tombstone", 1993, &["Western"], None),
+        ),
+        (
+            (MediaType::Movie, 603),
+            features(
+                "The Matrix",
+                1999,
+                &["Action", "Science Fiction"],
+                Some("The Matrix Collection"),
+            ),
+        ),
+        (
+            (MediaType::Tv, 1668),
+            features("Friends", 1994, &["Comedy"], None),
+        ),
+    ]);
+
+    let profile = taste::build_profile(&watches, &features, now, &TasteConfig::default());
+    assert_eq!(profile.watches, 7);
+    assert_eq!(profile.titles, 4);
+    let genres: Vec<_> = profile.genres.iter().map(|a| a.name.as_str()).collect();
+    assert_eq!(
+        genres,
+        vec!["Action", "Science Fiction", "Comedy", "Western"]
+    );
+    assert_eq!(profile.decades[0].name, "1990s");
+    assert_eq!(profile.decades[0].share, 1.0);
+    assert_eq!(profile.collections[0].name, "The Matrix Collection");
+    assert_eq!(profile.rewatched.len(), 1);
+    assert_eq!(profile.rewatched[0].tmdb_id, 603);
+    assert_eq!(profile.rewatched[0].times, 2);
+
+    let summary: serde_json::Value = serde_json::from_str(&profile.summary()).unwrap();
+    assert!(summary["genres"][0]
+        .as_str()
+        .unwrap()
+        .starts_with("Action "));
+    assert_eq!(summary["rewatched"][0], "The Matrix (tmdb 603) x2");
+}
+
+#[actix_rt::test]
+async fn history_spans_buckets_per_user() {
+    let db = temp_db();
+    let store = db.spawn();
+    let row = |tmdb_id: u32, media_type: &str, ts: &str| EventRow {
+        ts: Some(ts.into()),
+        media_type: Some(media_type.into()),
+        ..watched(tmdb_id)
+    };
+    store
+        .write(move |s| {
+            let t1 = "2025-08-01T20:00:00Z";
+            let t2 = "2025-09-29T20:00:00Z";
+            s.append_event("2025-08-01T18Z", Some("sam"), t1, &row(603, "movie", t1))?;
+            s.append_event("2025-09-29T18Z", Some("sam"), t2, &row(1668, "episode", t2))?;
+            s.append_event("2025-09-29T18Z", None, t2, &row(1, "movie", t2))
+        })
+        .await
+        .unwrap();
+
+    let events = store.read(|s| s.watch_history(Some("sam"))).await.unwrap();
+    let watches = taste::watches_from_events(&events);
+    let got: Vec<_> = watches.iter().map(|w| (w.tmdb_id, w.media_type)).collect();
+    assert_eq!(got, vec![(603, MediaType::Movie), (1668, MediaType::Tv)]);
+}
@@ -0,0 +1,97 @@
+use movie_recommendation_engine::events::EventKind;
+use movie_recommendation_engine::recommend::collect_watched;
+use movie_recommendation_engine::tautulli::{extract_tmdb_id, TautulliHook};
+
+fn parse(body: &str) -> movie_recommendation_engine::events::WatchEvent {
+    let hook: TautulliHook = serde_json::from_str(body).unwrap();
+    hook.into_event(None, body.to_string())
+}
+
+#[test]
+fn parses_templated_strings() {
+    let ev = parse(
+        r#"{"event":"stop","user":"patrick","title":"Arrival","guid":"plex://movie/5d77?x tmdb://329865",
+            "media_type":"movie","progress_percent":"93","duration_sec":"6960",
+            "rating_key":12345,"session_id":"abc"}"#,
+    );
+    assert_eq!(ev.event, EventKind::Stop);
+    assert_eq!(ev.user.as_deref(), Some("patrick"));
+    assert_eq!(ev.tmdb_id, Some(329865));
+    assert_eq!(ev.progress_percent, Some(93.0));
+    assert_eq!(ev.duration_secs, Some(6960));
+    assert_eq!(ev.rating_key.as_deref(), Some("12345"));
+    assert_eq!(ev.session_id.as_deref(), Some("abc"));
+}
+
+#[test]
+fn legacy_minimal_payload_still_parses() {
+    let ev = parse(
+        r#"{"event":"watched","user":"","title":"Heat","guid":"com.plexapp.agents.themoviedb://949?lang=en"}"#,
+    );
+    assert_eq!(ev.event, EventKind::Watched);
+    assert_eq!(ev.user, None);
+    assert_eq!(ev.tmdb_id, Some(949));
+    assert_eq!(ev.progress_percent, None);
+}
+
+#[test]
+fn only_completed_watches_count() {
+    let watched = parse(r#"{"event":"watched","progress_percent":"90"}"#);
+    let abandoned = parse(r#"{"event":"stop","progress_percent":"4"}"#);
+    let finished_stop = parse(r#"{"event":"stop","progress_percent":"97"}"#);
+    let play = parse(r#"{"event":"play","progress_percent":"100"}"#);
+    let early_watched = parse(r#"{"event":"watched","progress_percent":"60"}"#);
+
+    assert!(watched.is_completed_watch(85.0));
+    assert!(!abandoned.is_completed_watch(85.0));
+    assert!(finished_stop.is_completed_watch(85.0));
+    assert!(!play.is_completed_watch(85.0));
+    assert!(!early_watched.is_completed_watch(85.0));
+    assert!(early_watched.is_completed_watch(50.0));
+}
+
+#[test]
+fn tmdb_id_from_guids() {
+    assert_eq!(extract_tmdb_id("tmdb://603"), Some(603));
+    assert_eq!(extract_tmdb_id("imdb://tt0133093"), None);
+}
+
+#[test]
+fn episodes_map_to_their_series() {
+    let ev = parse(
+        r#"{"event":"watched","title":"Ozymandias","media_type":"episode","show_name":"Breaking Bad",
+            "guid":"plex://episode/5d9c tmdb://62161","grandparent_guid":"plex://show/5d9c tvdb://81189",
+            "themoviedb_id":"","thetvdb_id":""}"#,
+    );
+    assert_eq!(ev.tmdb_id, None);
+    assert_eq!(ev.tvdb_id, Some(81189));
+    assert_eq!(ev.show_title.as_deref(), Some("Breaking Bad"));
+
+    let ev = parse(
+        r#"{"event":"watched","media_type":"episode","themoviedb_id":"1396","guid":"tmdb://62161"}"#,
+    );
+    assert_eq!(ev.tmdb_id, Some(1396));
+}
+
+#[test]
+fn watched_set_aggregates_episodes_per_series() {
+    let events: Vec<serde_json::Value> = serde_json::from_str(
+        r#"[{"media_type":"movie","tmdb_id":603},
+            {"media_type":"episode","tmdb_id":1396,"show_title":"Breaking Bad"},
+            {"media_type":"episode","tmdb_id":1396},
+            {"media_type":"episode","tvdb_id":81189},
+            {"tmdb_id":"949"},
+            {"media_type":"episode"}]"#,
+    )
+    .unwrap();
+    let watched = collect_watched(&events);
+    assert_eq!(watched.movie_ids, vec![603, 949]);
+    assert_eq!(watched.shows.len(), 2);
+    let bb = watched
+        .shows
+        .iter()
+        .find(|s| s.tmdb_id == Some(1396))
+        .unwrap();
+    assert_eq!(bb.episodes, 2);
+    assert_eq!(bb.title.as_deref(), Some("Breaking Bad"));
+}
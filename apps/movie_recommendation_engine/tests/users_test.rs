@@ -0,0 +1,16 @@
+use movie_recommendation_engine::users::UserPolicy;
+
+#[test]
+fn aliases_and_ignored_accounts() {
+    let users = UserPolicy::new(&[("pmclennan", "patrick"), ("Pat", "patrick")], &["guest"]);
+
+    assert_eq!(
+        users.resolve(Some("PMcLennan")),
+        Some(Some("patrick".into()))
+    );
+    assert_eq!(users.resolve(Some("pat")), Some(Some("patrick".into())));
+    assert_eq!(users.resolve(Some("sam")), Some(Some("sam".into())));
+    assert_eq!(users.resolve(Some("Guest")), None);
+    assert_eq!(users.resolve(None), Some(None));
+    assert_eq!(users.resolve(Some("  ")), Some(None));
+}
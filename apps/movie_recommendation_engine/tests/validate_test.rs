@@ -0,0 +1,143 @@
+mod common;
+
+use chrono::NaiveDate;
+use common::temp_db;
+use lib::store::EventRow;
+use movie_recommendation_engine::recommend::{MediaType, RecItem};
+use movie_recommendation_engine::validate::{
+    check_attribution, clean_reason, judge, BucketReport, Candidate, RejectReason, Rejection,
+    ValidationReport, MAX_REASON_CHARS,
+};
+use std::collections::HashSet;
+
+fn candidate(genres: &[&str], released: Option<&str>, status: &str) -> Candidate {
+    Candidate {
+        title: "x".into(),
+        adult: false,
+        status: Some(status.into()),
+        released: released.map(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap()),
+        genres: genres.iter().map(|g| g.to_string()).collect(),
+    }
+}
+
+#[test]
+fn rejects_adult_unreleased_and_off_genre() {
+    let today = NaiveDate::from_ymd_opt(2025, 10, 1).unwrap();
+    let watched: HashSet<String> = ["Science Fiction", "Action"].map(String::from).into();
+    let ok = candidate(
+        &["Science Fiction", "Drama"],
+        Some("1999-03-31"),
+        "Released",
+    );
+    assert_eq!(judge(&ok, &watched, false, today), None);
+
+    let adult = Candidate {
+        adult: true,
+        ..ok.clone()
+    };
+    assert_eq!(
+        judge(&adult, &watched, false, today),
+        Some(RejectReason::Adult)
+    );
+    for (released, status) in [
+        (Some("2026-05-01"), "Released"),
+        (Some("2025-09-01"), "Post Production"),
+        (None, "Released"),
+    ] {
+        let c = candidate(&["Action"], released, status);
+        assert_eq!(
+            judge(&c, &watched, false, today),
+            Some(RejectReason::Unreleased),
+            "{released:?} {status}"
+        );
+    }
+    let romance = candidate(&["Romance"], Some("2001-01-01"), "Released");
+    assert_eq!(
+        judge(&romance, &watched, false, today),
+        Some(RejectReason::OffGenre)
+    );
+    // Nothing to compare against: any genre goes.
+    assert_eq!(judge(&romance, &HashSet::new(), false, today), None);
+    // A flagged surprise may leave the watched genres, but nothing else is waived.
+    assert_eq!(judge(&romance, &watched, true, today), None);
+    assert_eq!(
+        judge(&adult, &watched, true, today),
+        Some(RejectReason::Adult)
+    );
+
+    let series = candidate(&["Action"], Some("2019-01-01"), "Returning Series");
+    assert_eq!(judge(&series, &watched, false, today), None);
+}
+
+#[test]
+fn keeps_only_watched_sources_and_short_reasons() {
+    assert_eq!(
+        clean_reason("  Another   Nolan\nmind-bender  ").as_deref(),
+        Some("Another Nolan mind-bender")
+    );
+    assert_eq!(clean_reason(" \n "), None);
+    let long = clean_reason(&"slow burn, ".repeat(30)).unwrap();
+    assert!(long.chars().count() <= MAX_REASON_CHARS);
+    // Cut between words, without a dangling comma
+    assert!(long.ends_with('…') && !long.contains(",…") && !long.contains(" …"));
+
+    let watched: HashSet<u32> = [157336, 27205].into();
+    let mut items = vec![
+        RecItem {
+            tmdb_id: 329865,
+            media_type: MediaType::Movie,
+            reason: Some("Cerebral sci-fi about time".into()),
+            because_of: vec![157336, 999, 157336, 27205],
+            ..Default::default()
+        },
+        RecItem {
+            tmdb_id: 335984,
+            media_type: MediaType::Movie,
+            because_of: vec![1, 2],
+            ..Default::default()
+        },
+    ];
+    // Ids not watched in the bucket are dropped and counted; repeats go quietly.
+    assert_eq!(check_attribution(&mut items, &watched), 3);
+    assert_eq!(items[0].because_of, vec![157336, 27205]);
+    assert_eq!(
+        items[0].reason.as_deref(),
+        Some("Cerebral sci-fi about time")
+    );
+    assert!(items[1].because_of.is_empty());
+}
+
+#[actix_rt::test]
+async fn report_is_kept_with_the_bucket() {
+    let db = temp_db();
+    let store = db.spawn();
+    let report = BucketReport {
+        movies: Some(ValidationReport {
+            target: 20,
+            returned: 22,
+            accepted: 20,
+            rejected: vec![Rejection {
+                tmdb_id: 1,
+                title: None,
+                reason: RejectReason::NotFound,
+            }],
+            follow_ups: 1,
+            ..Default::default()
+        }),
+        shows: None,
+    };
+    let json = serde_json::to_string(&report).unwrap();
+    let stored = store
+        .write(move |s| {
+            let id = s.append_event("2025-09-29T12Z", None, "", &EventRow::default())?;
+            assert_eq!(s.recommendation_report(id)?, None);
+            s.set_recommendation_report(id, &json)?;
+            s.recommendation_report(id)
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    let v: serde_json::Value = serde_json::from_str(&stored).unwrap();
+    assert_eq!(v["movies"]["rejected"][0]["reason"], "not_found");
+    assert_eq!(v["shows"], serde_json::Value::Null);
+}
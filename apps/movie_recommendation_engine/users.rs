@@ -0,0 +1,62 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+};
+
+/// Maps raw Plex/Tautulli usernames onto the people we bucket by.
+#[derive(Debug, Clone, Default)]
+pub struct UserPolicy {
+    /// lowercase raw name → canonical name
+    aliases: HashMap<String, String>,
+    /// lowercase names (raw or canonical) whose watches never feed recommendations
+    ignored: HashSet<String>,
+}
+
+impl UserPolicy {
+    pub fn new(aliases: &[(&str, &str)], ignored: &[&str]) -> Self {
+        Self {
+            aliases: aliases
+                .iter()
+                .map(|(raw, canonical)| (raw.trim().to_lowercase(), canonical.trim().to_string()))
+                .collect(),
+            ignored: ignored.iter().map(|u| u.trim().to_lowercase()).collect(),
+        }
+    }
+
+    /// Reads `USER_ALIASES` (`raw=canonical,…`, e.g. `pmclennan=patrick,Patrick's iPad=patrick`)
+    /// and `IGNORED_USERS` (comma-separated, e.g. `guest,Plex Guest`).
+    pub fn from_env() -> Self {
+        let aliases_raw = env::var("USER_ALIASES").unwrap_or_default();
+        let aliases: Vec<(&str, &str)> = aliases_raw
+            .split(',')
+            .filter_map(|pair| pair.split_once('='))
+            .filter(|(raw, canonical)| !raw.trim().is_empty() && !canonical.trim().is_empty())
+            .collect();
+        let ignored_raw = env::var("IGNORED_USERS").unwrap_or_default();
+        let ignored: Vec<&str> = ignored_raw
+            .split(',')
+            .filter(|u| !u.trim().is_empty())
+            .collect();
+        Self::new(&aliases, &ignored)
+    }
+
+    /// Canonical name for `raw`, or `None` if the account is ignored.
+    /// Events without a user keep `Some(None)` and land in the shared bucket.
+    pub fn resolve(&self, raw: Option<&str>) -> Option<Option<String>> {
+        let Some(raw) = raw.map(str::trim).filter(|s| !s.is_empty()) else {
+            return Some(None);
+        };
+        let canonical = self
+            .aliases
+            .get(&raw.to_lowercase())
+            .cloned()
+            .unwrap_or_else(|| raw.to_string());
+        if self.ignored.contains(&raw.to_lowercase())
+            || self.ignored.contains(&canonical.to_lowercase())
+        {
+            None
+        } else {
+            Some(Some(canonical))
+        }
+    }
+}
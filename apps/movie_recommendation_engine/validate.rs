@@ -0,0 +1,329 @@
+use chrono::{NaiveDate, Utc};
+use futures::{stream, StreamExt};
+use lib::clients::tmdb::get_movie_by_id::{get_movie_by_id, TmdbMovie};
+use lib::clients::tmdb::get_tv_by_id::{get_tv_by_id, TmdbTv};
+use reqwest::{Client, StatusCode};
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::recommend::{MediaType, RecItem, RecSource, Recommended};
+
+/// Reasons longer than this are cut at a word boundary.
+pub const MAX_REASON_CHARS: usize = 140;
+/// A recommendation is attributed to at most this many watches.
+pub const MAX_SOURCES: usize = 3;
+/// Picks the model flags as a close surprise skip the genre check, up to this many per
+/// media type.
+pub const MAX_SURPRISES: usize = 3;
+
+/// Why a recommended id was dropped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectReason {
+    /// TMDB has no such movie/series
+    NotFound,
+    Adult,
+    /// Not out yet, or no release date at all
+    Unreleased,
+    /// Shares no genre with what was watched
+    OffGenre,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Rejection {
+    pub tmdb_id: u32,
+    pub title: Option<String>,
+    pub reason: RejectReason,
+}
+
+/// What happened to one media type's recommendations for a bucket
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ValidationReport {
+    /// How many were asked for
+    pub target: usize,
+    /// Ids the LLM returned, over every call
+    pub returned: usize,
+    /// LLM ids kept, after trimming to `target`
+    pub accepted: usize,
+    pub rejected: Vec<Rejection>,
+    /// Kept without being checked because the TMDB lookup failed
+    pub unverified: Vec<u32>,
+    /// "Title (year)" picks TMDB search couldn't match confidently (title mode)
+    pub unresolved: Vec<String>,
+    /// Ids the LLM returned anyway that were already watched or owned
+    pub excluded: Vec<u32>,
+    /// Extra calls made to replace rejected ids
+    pub follow_ups: usize,
+    /// Recommendations filled in from TMDB's related lists, after the same checks as the LLM's
+    pub from_tmdb: usize,
+    /// Recommendations filled in from the local similarity index, after the same checks
+    pub from_index: usize,
+    /// `because_of` ids the LLM gave that weren't watched in the bucket
+    pub unknown_sources: usize,
+}
+
+impl ValidationReport {
+    /// Add the rejections from checking non-LLM picks.
+    pub fn absorb(&mut self, picks: ValidationReport) {
+        self.rejected.extend(picks.rejected);
+        self.unverified.extend(picks.unverified);
+    }
+
+    /// Count the final `items` by where they came from.
+    pub fn tally(&mut self, items: &[RecItem]) {
+        let count = |source| items.iter().filter(|r| r.source == source).count();
+        self.from_tmdb = count(RecSource::Tmdb);
+        self.from_index = count(RecSource::Index);
+        self.accepted = count(RecSource::Llm);
+    }
+}
+
+/// Stored with a bucket's recommendations
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct BucketReport {
+    pub movies: Option<ValidationReport>,
+    pub shows: Option<ValidationReport>,
+}
+
+/// The TMDB facts a recommendation is judged on
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Candidate {
+    pub title: String,
+    pub adult: bool,
+    /// "Released", "In Production", "Returning Series"… if TMDB says
+    pub status: Option<String>,
+    pub released: Option<NaiveDate>,
+    pub genres: Vec<String>,
+}
+
+fn date(d: &Option<String>) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(d.as_deref()?, "%Y-%m-%d").ok()
+}
+
+impl From<TmdbMovie> for Candidate {
+    fn from(m: TmdbMovie) -> Self {
+        Candidate {
+            released: date(&m.release_date),
+            title: m.title,
+            adult: m.adult,
+            status: m.status,
+            genres: m.genres.into_iter().map(|g| g.name).collect(),
+        }
+    }
+}
+
+impl From<TmdbTv> for Candidate {
+    fn from(t: TmdbTv) -> Self {
+        Candidate {
+            released: date(&t.first_air_date),
+            title: t.name,
+            adult: t.adult.unwrap_or(false),
+            status: t.status,
+            genres: t.genres.into_iter().map(|g| g.name).collect(),
+        }
+    }
+}
+
+/// `None` if the candidate is fine. An empty `watched_genres` accepts any genre, and so
+/// does a `surprise`.
+pub fn judge(
+    c: &Candidate,
+    watched_genres: &HashSet<String>,
+    surprise: bool,
+    today: NaiveDate,
+) -> Option<RejectReason> {
+    if c.adult {
+        return Some(RejectReason::Adult);
+    }
+    let out_by_status = !matches!(
+        c.status.as_deref(),
+        None | Some("Released" | "Returning Series" | "Ended" | "Canceled")
+    );
+    if out_by_status || c.released.is_none_or(|d| d > today) {
+        return Some(RejectReason::Unreleased);
+    }
+    if !surprise
+        && !watched_genres.is_empty()
+        && !c.genres.iter().any(|g| watched_genres.contains(g))
+    {
+        return Some(RejectReason::OffGenre);
+    }
+    None
+}
+
+enum Lookup {
+    Found(Candidate),
+    NotFound,
+    Failed,
+}
+
+fn is_not_found(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<reqwest::Error>()
+        .and_then(|e| e.status())
+        .is_some_and(|s| s == StatusCode::NOT_FOUND)
+}
+
+async fn lookup(client: &Client, media_type: MediaType, id: u32) -> Lookup {
+    let res = match media_type {
+        MediaType::Movie => get_movie_by_id(client, id).await.map(Candidate::from),
+        MediaType::Tv => get_tv_by_id(client, id).await.map(Candidate::from),
+    };
+    match res {
+        Ok(c) => Lookup::Found(c),
+        Err(e) if is_not_found(&e) => Lookup::NotFound,
+        Err(e) => {
+            log::warn!("TMDB check failed for {} {id}: {e:#}", media_type.as_str());
+            Lookup::Failed
+        }
+    }
+}
+
+/// Check `ids` against TMDB, in order. Returns the ids that pass and adds the rest to
+/// `report`. Ids TMDB couldn't be asked about are kept, so an outage doesn't empty a bucket.
+/// Ids in `surprises` may fall outside `watched_genres`.
+pub async fn check(
+    client: &Client,
+    media_type: MediaType,
+    ids: &[u32],
+    watched_genres: &HashSet<String>,
+    surprises: &HashSet<u32>,
+    report: &mut ValidationReport,
+) -> Vec<u32> {
+    let today = Utc::now().date_naive();
+    let looked_up: Vec<(u32, Lookup)> = stream::iter(ids.iter().copied().map(|id| {
+        let client = client.clone();
+        async move { (id, lookup(&client, media_type, id).await) }
+    }))
+    .buffered(12)
+    .collect()
+    .await;
+
+    report.returned += ids.len();
+    let mut ok = Vec::new();
+    for (tmdb_id, l) in looked_up {
+        let (title, reason) = match l {
+            Lookup::Found(c) => {
+                let surprise = surprises.contains(&tmdb_id);
+                (
+                    Some(c.title.clone()),
+                    judge(&c, watched_genres, surprise, today),
+                )
+            }
+            Lookup::NotFound => (None, Some(RejectReason::NotFound)),
+            Lookup::Failed => {
+                report.unverified.push(tmdb_id);
+                (None, None)
+            }
+        };
+        match reason {
+            Some(reason) => report.rejected.push(Rejection {
+                tmdb_id,
+                title,
+                reason,
+            }),
+            None => ok.push(tmdb_id),
+        }
+    }
+    ok
+}
+
+/// The genres of the watched `ids`; ones TMDB can't be asked about add none.
+pub async fn watched_genres(
+    client: &Client,
+    media_type: MediaType,
+    ids: &[u32],
+) -> HashSet<String> {
+    stream::iter(ids.iter().copied().map(|id| {
+        let client = client.clone();
+        async move { lookup(&client, media_type, id).await }
+    }))
+    .buffer_unordered(12)
+    .flat_map(|l| {
+        stream::iter(match l {
+            Lookup::Found(c) => c.genres,
+            _ => Vec::new(),
+        })
+    })
+    .collect()
+    .await
+}
+
+/// Run picks made without the LLM (TMDB's related lists, the local index) through
+/// [`check`] against the genres of the `watched` ids, keeping their order.
+pub async fn check_picks(
+    client: &Client,
+    media_type: MediaType,
+    watched: &[u32],
+    picks: Vec<RecItem>,
+) -> Recommended {
+    let mut report = ValidationReport::default();
+    if picks.is_empty() {
+        return Recommended {
+            items: picks,
+            report,
+        };
+    }
+    let genres = watched_genres(client, media_type, watched).await;
+    let ids: Vec<u32> = picks.iter().map(|r| r.tmdb_id).collect();
+    let ok: HashSet<u32> = check(
+        client,
+        media_type,
+        &ids,
+        &genres,
+        &HashSet::new(),
+        &mut report,
+    )
+    .await
+    .into_iter()
+    .collect();
+    let items = picks
+        .into_iter()
+        .filter(|r| ok.contains(&r.tmdb_id))
+        .collect();
+    Recommended { items, report }
+}
+
+/// Whitespace collapsed and cut to [`MAX_REASON_CHARS`]; `None` if nothing is left.
+pub fn clean_reason(reason: &str) -> Option<String> {
+    let words: Vec<&str> = reason.split_whitespace().collect();
+    let full = words.join(" ");
+    if full.chars().count() <= MAX_REASON_CHARS {
+        return (!full.is_empty()).then_some(full);
+    }
+    let mut out = String::new();
+    for w in words {
+        // room for the space and the ellipsis
+        if out.chars().count() + w.chars().count() + 2 > MAX_REASON_CHARS {
+            break;
+        }
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        out.push_str(w);
+    }
+    if out.is_empty() {
+        // one enormous word
+        out = full.chars().take(MAX_REASON_CHARS - 1).collect();
+    }
+    let out = out.trim_end_matches([',', ';', ':', '.', ' ']).to_string();
+    Some(format!("{out}…"))
+}
+
+/// Keep only `because_of` ids that were watched in the bucket (at most [`MAX_SOURCES`],
+/// no repeats) and clean each reason. Returns how many ids were dropped as unknown.
+pub fn check_attribution(items: &mut [RecItem], watched: &HashSet<u32>) -> usize {
+    let mut unknown = 0;
+    for item in items {
+        unknown += item
+            .because_of
+            .iter()
+            .filter(|id| !watched.contains(id))
+            .count();
+        let mut seen = HashSet::new();
+        item.because_of
+            .retain(|id| watched.contains(id) && seen.insert(*id));
+        item.because_of.truncate(MAX_SOURCES);
+        item.reason = item.reason.as_deref().and_then(clean_reason);
+    }
+    unknown
+}
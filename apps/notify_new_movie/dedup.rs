@@ -0,0 +1,276 @@
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// What to do when a movie we already texted about shows up again
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RepeatPolicy {
+    /// Never notify twice for the same movie
+    Suppress,
+    /// Notify again only when the new file is a higher resolution
+    Upgrade,
+}
+
+/// Vertical resolution parsed from a release name (e.g. 1080 for "1080p")
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Resolution(pub u16);
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}p", self.0)
+    }
+}
+
+/// Everything we can tell about a movie from its folder/file name
+#[derive(Debug, Clone)]
+pub struct Release {
+    pub title: String,
+    pub year: Option<u16>,
+    pub tmdb_id: Option<u32>,
+    pub resolution: Option<Resolution>,
+}
+
+impl Release {
+    /// Parse names like `Arrival (2016) {tmdb-329865}` or `Arrival.2016.2160p.UHD.BluRay.mkv`
+    pub fn parse(name: &str) -> Self {
+        static TMDB: Lazy<Regex> =
+            Lazy::new(|| Regex::new(r"(?i)[\{\[]tmdb(?:id)?[-=](?P<id>\d+)[\}\]]").unwrap());
+        static RES: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r"(?i)\b(?P<res>2160p|4k|uhd|1080p|1080i|720p|576p|480p)\b").unwrap()
+        });
+        static YEAR: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b(?:19|20)\d{2}\b").unwrap());
+        static EXT: Lazy<Regex> =
+            Lazy::new(|| Regex::new(r"(?i)\.(mkv|mp4|m4v|avi|mov|ts|wmv)$").unwrap());
+
+        let stem = EXT.replace(name, "");
+        let tmdb_id = TMDB
+            .captures(&stem)
+            .and_then(|c| c["id"].parse::<u32>().ok());
+        let resolution =
+            RES.captures(&stem)
+                .map(|c| match c["res"].to_ascii_lowercase().as_str() {
+                    "2160p" | "4k" | "uhd" => Resolution(2160),
+                    "1080p" | "1080i" => Resolution(1080),
+                    "720p" => Resolution(720),
+                    "576p" => Resolution(576),
+                    _ => Resolution(480),
+                });
+        // Prefer "(2017)"; otherwise the last year-like token that isn't the start of the
+        // title, so "Blade.Runner.2049.2017" and "2001 A Space Odyssey" both come out right.
+        let years: Vec<_> = YEAR.find_iter(&stem).filter(|m| m.start() > 0).collect();
+        let year_match = years
+            .iter()
+            .find(|m| stem[..m.start()].ends_with('(') && stem[m.end()..].starts_with(')'))
+            .or(years.last())
+            .copied();
+        let year = year_match.and_then(|m| m.as_str().parse::<u16>().ok());
+
+        // The title is whatever comes before the first year/resolution/id marker.
+        let mut cut = stem.len();
+        if let Some(m) = year_match {
+            cut = cut.min(m.start());
+        }
+        for re in [&*RES, &*TMDB] {
+            if let Some(m) = re.find(&stem) {
+                cut = cut.min(m.start());
+            }
+        }
+        let title = stem[..cut]
+            .replace(['.', '_'], " ")
+            .trim_matches(|c: char| c.is_whitespace() || "([{-".contains(c))
+            .to_string();
+        let title = if title.is_empty() {
+            stem.to_string()
+        } else {
+            title
+        };
+
+        Self {
+            title,
+            year,
+            tmdb_id,
+            resolution,
+        }
+    }
+
+    /// Lowercased, punctuation-free `title|year` used when no tmdb id is available
+    pub fn title_key(&self) -> String {
+        let words: Vec<String> = self
+            .title
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(|w| w.to_lowercase())
+            .collect();
+        format!(
+            "{}|{}",
+            words.join(" "),
+            self.year.map(|y| y.to_string()).unwrap_or_default()
+        )
+    }
+
+    fn key(&self) -> String {
+        match self.tmdb_id {
+            Some(id) => format!("tmdb:{id}"),
+            None => format!("title:{}", self.title_key()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct NotifiedEntry {
+    title: String,
+    year: Option<u16>,
+    title_key: String,
+    resolution: Option<Resolution>,
+    first_notified_at: String,
+    last_seen_at: String,
+}
+
+/// How a newly detected release relates to what we've already notified about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    New,
+    Upgraded { from: Resolution, to: Resolution },
+    Repeat,
+}
+
+/// Persistent record of notified movies, stored as a JSON object on disk
+pub struct NotifiedStore {
+    path: PathBuf,
+    entries: HashMap<String, NotifiedEntry>,
+    persist: bool,
+}
+
+impl NotifiedStore {
+    pub fn open(path: &Path) -> Result<Self> {
+        let entries = if path.exists() {
+            let raw = std::fs::read_to_string(path)?;
+            if raw.trim().is_empty() {
+                HashMap::new()
+            } else {
+                serde_json::from_str(&raw)?
+            }
+        } else {
+            HashMap::new()
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            entries,
+            persist: true,
+        })
+    }
+
+    /// Keep deciding against the existing state, but never write it back (dry runs).
+    pub fn read_only(mut self) -> Self {
+        self.persist = false;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The key of what we already know about `release`, if anything
+    fn existing_key(&self, release: &Release) -> Option<String> {
+        let key = release.key();
+        if self.entries.contains_key(&key) {
+            return Some(key);
+        }
+        // Fall back to title/year so a later `{tmdb-…}` rename still matches.
+        let title_key = release.title_key();
+        self.entries
+            .iter()
+            .find(|(_, e)| e.title_key == title_key)
+            .map(|(k, _)| k.clone())
+    }
+
+    /// Whether `release` is new, an upgrade or a repeat, without recording it.
+    pub fn classify(&self, release: &Release) -> Outcome {
+        let Some(entry) = self
+            .existing_key(release)
+            .and_then(|k| self.entries.get(&k))
+        else {
+            return Outcome::New;
+        };
+        match (entry.resolution, release.resolution) {
+            (Some(from), Some(to)) if to > from => Outcome::Upgraded { from, to },
+            _ => Outcome::Repeat,
+        }
+    }
+
+    /// Record a sighting and report whether it was new, an upgrade or a repeat. Call it
+    /// once the notification went out (or was suppressed), so a failed publish is retried
+    /// the next time the movie shows up.
+    pub fn record(&mut self, release: &Release, seen_at: &str) -> Result<Outcome> {
+        let title_key = release.title_key();
+        let outcome = match self.existing_key(release) {
+            None => {
+                self.entries.insert(
+                    release.key(),
+                    NotifiedEntry {
+                        title: release.title.clone(),
+                        year: release.year,
+                        title_key,
+                        resolution: release.resolution,
+                        first_notified_at: seen_at.to_string(),
+                        last_seen_at: seen_at.to_string(),
+                    },
+                );
+                Outcome::New
+            }
+            Some(key) => {
+                let mut entry = self.entries.remove(&key).expect("key was just found");
+                entry.last_seen_at = seen_at.to_string();
+                let outcome = match (entry.resolution, release.resolution) {
+                    (Some(from), Some(to)) if to > from => Outcome::Upgraded { from, to },
+                    _ => Outcome::Repeat,
+                };
+                if let Some(r) = release.resolution {
+                    entry.resolution = Some(entry.resolution.map_or(r, |old| old.max(r)));
+                }
+                // Prefer the tmdb key once we learn it.
+                let key = if release.tmdb_id.is_some() {
+                    release.key()
+                } else {
+                    key
+                };
+                self.entries.insert(key, entry);
+                outcome
+            }
+        };
+
+        self.save()?;
+        Ok(outcome)
+    }
+
+    fn save(&self) -> Result<()> {
+        if !self.persist {
+            return Ok(());
+        }
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut tmp = self.path.clone();
+        tmp.set_extension("json.tmp");
+        let mut f = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tmp)?;
+        f.write_all(serde_json::to_string_pretty(&self.entries)?.as_bytes())?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+use actix_web::{get, web, App, HttpResponse, HttpServer, Responder};
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write as _,
+    sync::{Arc, Mutex},
+};
+
+/// How many processed entries `/status` keeps around
+const RECENT_LIMIT: usize = 50;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecentEvent {
+    pub at: DateTime<Local>,
+    pub root: String,
+    pub name: String,
+    /// "new", "upgraded" or "suppressed"
+    pub outcome: String,
+    /// "ok", "dry-run", "failed: …" or `None` when nothing was published
+    pub publish: Option<String>,
+}
+
+#[derive(Default)]
+struct State {
+    watchers: BTreeMap<String, bool>,
+    events_seen: u64,
+    outcomes: BTreeMap<String, u64>,
+    publish_ok: u64,
+    publish_failed: u64,
+    last_event_at: Option<DateTime<Local>>,
+    last_publish_at: Option<DateTime<Local>>,
+    recent: VecDeque<RecentEvent>,
+}
+
+/// Liveness and counters shared between the watch loop and the HTTP endpoints
+pub struct Health {
+    started_at: DateTime<Local>,
+    state: Mutex<State>,
+}
+
+impl Health {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            started_at: Local::now(),
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    pub fn set_watcher_alive(&self, root: &str, alive: bool) {
+        if let Ok(mut s) = self.state.lock() {
+            s.watchers.insert(root.to_string(), alive);
+        }
+    }
+
+    pub fn record_event(&self) {
+        if let Ok(mut s) = self.state.lock() {
+            s.events_seen += 1;
+            s.last_event_at = Some(Local::now());
+        }
+    }
+
+    pub fn record_result(&self, event: RecentEvent) {
+        if let Ok(mut s) = self.state.lock() {
+            *s.outcomes.entry(event.outcome.clone()).or_default() += 1;
+            match event.publish.as_deref() {
+                Some(p) if p.starts_with("failed") => s.publish_failed += 1,
+                Some("dry-run") | None => {}
+                Some(_) => {
+                    s.publish_ok += 1;
+                    s.last_publish_at = Some(event.at);
+                }
+            }
+            s.recent.push_front(event);
+            s.recent.truncate(RECENT_LIMIT);
+        }
+    }
+
+    fn healthy(s: &State) -> bool {
+        !s.watchers.is_empty() && s.watchers.values().all(|alive| *alive)
+    }
+}
+
+/// Escape a Prometheus label value: backslash, double quote and newline.
+fn label_value(v: &str) -> String {
+    v.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn age_secs(at: Option<DateTime<Local>>) -> Option<i64> {
+    at.map(|t| (Local::now() - t).num_seconds())
+}
+
+#[get("/healthz")]
+async fn healthz(health: web::Data<Health>) -> impl Responder {
+    let Ok(s) = health.state.lock() else {
+        return HttpResponse::ServiceUnavailable().finish();
+    };
+    let body = serde_json::json!({
+        "ok": Health::healthy(&s),
+        "watchers": s.watchers,
+        "last_event_age_secs": age_secs(s.last_event_at),
+    });
+    if Health::healthy(&s) {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
+
+#[get("/status")]
+async fn status(health: web::Data<Health>) -> impl Responder {
+    let Ok(s) = health.state.lock() else {
+        return HttpResponse::InternalServerError().finish();
+    };
+    HttpResponse::Ok().json(serde_json::json!({
+        "ok": Health::healthy(&s),
+        "started_at": health.started_at,
+        "watchers": s.watchers,
+        "events_seen": s.events_seen,
+        "outcomes": s.outcomes,
+        "publish_ok": s.publish_ok,
+        "publish_failed": s.publish_failed,
+        "last_event_at": s.last_event_at,
+        "last_event_age_secs": age_secs(s.last_event_at),
+        "last_publish_at": s.last_publish_at,
+        "recent": s.recent,
+    }))
+}
+
+#[get("/metrics")]
+async fn metrics(health: web::Data<Health>) -> impl Responder {
+    let Ok(s) = health.state.lock() else {
+        return HttpResponse::InternalServerError().finish();
+    };
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "# HELP notify_new_movie_up Whether every dir watcher is alive"
+    );
+    let _ = writeln!(out, "# TYPE notify_new_movie_up gauge");
+    let _ = writeln!(out, "notify_new_movie_up {}", Health::healthy(&s) as u8);
+    let _ = writeln!(out, "# TYPE notify_new_movie_watcher_up gauge");
+    for (root, alive) in &s.watchers {
+        let _ = writeln!(
+            out,
+            "notify_new_movie_watcher_up{{root=\"{}\"}} {}",
+            label_value(root),
+            *alive as u8
+        );
+    }
+    let _ = writeln!(
+        out,
+        "# HELP notify_new_movie_events_total Entries detected by the dir watchers"
+    );
+    let _ = writeln!(out, "# TYPE notify_new_movie_events_total counter");
+    let _ = writeln!(out, "notify_new_movie_events_total {}", s.events_seen);
+    let _ = writeln!(out, "# TYPE notify_new_movie_outcomes_total counter");
+    for (outcome, n) in &s.outcomes {
+        let _ = writeln!(
+            out,
+            "notify_new_movie_outcomes_total{{outcome=\"{outcome}\"}} {n}"
+        );
+    }
+    let _ = writeln!(out, "# TYPE notify_new_movie_publish_total counter");
+    let _ = writeln!(
+        out,
+        "notify_new_movie_publish_total{{result=\"ok\"}} {}",
+        s.publish_ok
+    );
+    let _ = writeln!(
+        out,
+        "notify_new_movie_publish_total{{result=\"failed\"}} {}",
+        s.publish_failed
+    );
+    let _ = writeln!(
+        out,
+        "# TYPE notify_new_movie_last_event_timestamp_seconds gauge"
+    );
+    let _ = writeln!(
+        out,
+        "notify_new_movie_last_event_timestamp_seconds {}",
+        s.last_event_at.map(|t| t.timestamp()).unwrap_or(0)
+    );
+    let _ = writeln!(
+        out,
+        "# TYPE notify_new_movie_last_publish_timestamp_seconds gauge"
+    );
+    let _ = writeln!(
+        out,
+        "notify_new_movie_last_publish_timestamp_seconds {}",
+        s.last_publish_at.map(|t| t.timestamp()).unwrap_or(0)
+    );
+    let _ = writeln!(out, "# TYPE notify_new_movie_start_timestamp_seconds gauge");
+    let _ = writeln!(
+        out,
+        "notify_new_movie_start_timestamp_seconds {}",
+        health.started_at.timestamp()
+    );
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(out)
+}
+
+/// Register `/healthz`, `/status` and `/metrics`. Needs the `Health` as app data.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(healthz).service(status).service(metrics);
+}
+
+/// Serve `/healthz`, `/status` and `/metrics` on `bind` in the background.
+pub fn spawn_server(bind: &str, health: Arc<Health>) -> std::io::Result<()> {
+    let data = web::Data::from(health);
+    let server = HttpServer::new(move || App::new().app_data(data.clone()).configure(configure))
+        .workers(1)
+        .disable_signals()
+        .bind(bind)?
+        .run();
+    println!("notify_new_movie health endpoints on http://{bind}");
+    tokio::spawn(server);
+    Ok(())
+}
@@ -0,0 +1,4 @@
+pub mod dedup;
+pub mod health;
+pub mod notifier;
+pub mod routing;
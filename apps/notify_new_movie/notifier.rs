@@ -0,0 +1,61 @@
+use anyhow::Result;
+use aws_sdk_sns::{types::MessageAttributeValue, Client};
+
+use crate::routing::MessageMeta;
+
+/// Where rendered notifications go
+pub enum Notifier {
+    /// Publish to an SNS topic (SMS subscribers etc.)
+    Sns { client: Client, topic_arn: String },
+    /// Print what would have been published as one JSON line on stdout; never touches AWS
+    DryRun { topic_arn: Option<String> },
+}
+
+impl Notifier {
+    /// Human-readable target, e.g. `sns:arn:aws:sns:…` or `stdout`
+    pub fn channel(&self) -> String {
+        match self {
+            Notifier::Sns { topic_arn, .. } => format!("sns:{topic_arn}"),
+            Notifier::DryRun { .. } => "stdout".to_string(),
+        }
+    }
+
+    /// Deliver `msg` with its routing attributes, returning the provider's message id (if any).
+    pub async fn send(&self, name: &str, msg: &str, meta: &MessageMeta) -> Result<Option<String>> {
+        match self {
+            Notifier::Sns { client, topic_arn } => {
+                // Mark as Transactional (helps delivery; not strictly required)
+                let sms_type = MessageAttributeValue::builder()
+                    .data_type("String")
+                    .string_value("Transactional")
+                    .build()?;
+
+                let mut req = client
+                    .publish()
+                    .topic_arn(topic_arn)
+                    .message(msg)
+                    .message_attributes("AWS.SNS.SMS.SMSType", sms_type);
+                for (key, value) in meta.to_attributes()? {
+                    req = req.message_attributes(key, value);
+                }
+                let out = req.send().await?;
+                Ok(out.message_id().map(|s| s.to_string()))
+            }
+            Notifier::DryRun { topic_arn } => {
+                let line = serde_json::json!({
+                    "dry_run": true,
+                    // Where this would have gone without --dry-run
+                    "channel": topic_arn
+                        .as_ref()
+                        .map(|arn| format!("sns:{arn}"))
+                        .unwrap_or_else(|| "sns".to_string()),
+                    "name": name,
+                    "message": msg,
+                    "attributes": meta,
+                });
+                println!("{line}");
+                Ok(None)
+            }
+        }
+    }
+}
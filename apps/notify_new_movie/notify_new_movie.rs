@@ -1,21 +1,32 @@
 use anyhow::Result;
 use aws_config::BehaviorVersion;
-use aws_sdk_sns::{types::MessageAttributeValue, Client};
-use clap::Parser;
+use aws_sdk_sns::Client;
+use chrono::Local;
+use clap::{Parser, Subcommand};
 use rand::seq::SliceRandom;
 use rand::thread_rng;
-use std::{path::PathBuf, time::Duration};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 use tokio::sync::mpsc;
 
+use notify_new_movie::dedup::{NotifiedStore, Outcome, Release, RepeatPolicy};
+use notify_new_movie::health::{self, Health, RecentEvent};
+use notify_new_movie::notifier::Notifier;
+use notify_new_movie::routing::{self, MessageMeta, RecipientsConfig};
+
 #[derive(Parser, Debug)]
 #[command(
     name = "notify_new_movie",
-    about = "Watch and publish NEW events to SNS"
+    about = "Watch and publish NEW events to SNS",
+    subcommand_negates_reqs = true
 )]
 struct Args {
-    /// SNS topic ARN (must match the region you use)
-    #[arg(long)]
-    topic_arn: String,
+    /// SNS topic ARN (must match the region you use). Optional with --dry-run.
+    #[arg(long, required_unless_present = "dry_run")]
+    topic_arn: Option<String>,
 
     /// AWS region override (optional). If omitted, uses your CLI/default config.
     #[arg(long)]
@@ -24,80 +35,264 @@ struct Args {
     /// Debounce seconds
     #[arg(long, default_value_t = 2)]
     debounce_secs: u64,
+
+    /// What to do when an already-notified movie shows up again (re-copy, 1080p → 4K upgrade)
+    #[arg(long, value_enum, default_value_t = RepeatPolicy::Upgrade)]
+    on_repeat: RepeatPolicy,
+
+    /// JSON file remembering which movies we've notified about (defaults to the user data dir)
+    #[arg(long)]
+    state_file: Option<PathBuf>,
+
+    /// Print rendered messages to stdout as JSON instead of publishing (no AWS calls)
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Process this file/folder as if it had just appeared under the watch root, then exit
+    #[arg(long)]
+    once: Option<PathBuf>,
+
+    /// Directory to watch as `label=path` (repeatable). The label is sent as the `root` attribute.
+    #[arg(long = "root", value_parser = parse_root, default_value = "movies=/movies")]
+    roots: Vec<(String, PathBuf)>,
+
+    /// Serve /healthz, /status and /metrics on this address (e.g. 0.0.0.0:8090)
+    #[arg(long)]
+    http_bind: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Generate SNS subscription filter policies from a recipients file (and apply them with --apply)
+    FilterPolicies {
+        /// JSON recipients file, see README
+        #[arg(long)]
+        recipients: PathBuf,
+
+        /// Push policies to SNS, subscribing missing endpoints (needs --topic-arn)
+        #[arg(long)]
+        apply: bool,
+    },
+}
 
-    // Resolve watch root from env with fallback
-    let root = PathBuf::from("/movies");
+fn parse_root(s: &str) -> Result<(String, PathBuf), String> {
+    match s.split_once('=') {
+        Some((label, path)) if !label.is_empty() && !path.is_empty() => {
+            Ok((label.to_string(), PathBuf::from(path)))
+        }
+        _ => Err(format!("expected label=path, got {s:?}")),
+    }
+}
 
+async fn sns_client(region: &Option<String>) -> Client {
     // --- AWS config (fix deprecations) ---
     let mut cfg_loader = aws_config::defaults(BehaviorVersion::latest());
-    if let Some(r) = &args.region {
+    if let Some(r) = region {
         cfg_loader = cfg_loader.region(aws_sdk_sns::config::Region::new(r.clone()));
     }
     let cfg = cfg_loader.load().await;
-    let sns = Client::new(&cfg);
-
-    // --- Dir watcher (blocking channel) -> async bridge ---
-    let rx_blocking =
-        lib::dirwatch::dirwatch::watch_dir(&root, Duration::from_secs(args.debounce_secs))?;
-    let (tx_async, mut rx_async) = mpsc::unbounded_channel::<(String, String)>();
-    std::thread::spawn(move || {
-        while let Ok(ev) = rx_blocking.recv() {
-            let _ = tx_async.send(ev);
-        }
+    Client::new(&cfg)
+}
+
+/// Everything needed to turn a detected entry into a notification
+struct Pipeline {
+    notified: NotifiedStore,
+    on_repeat: RepeatPolicy,
+    notifier: Notifier,
+    http: reqwest::Client,
+    health: Arc<Health>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    if let Some(Command::FilterPolicies { recipients, apply }) = &args.command {
+        let config = RecipientsConfig::load(recipients)?;
+        let sns = if *apply {
+            let Some(_) = &args.topic_arn else {
+                anyhow::bail!("--apply needs --topic-arn");
+            };
+            Some(sns_client(&args.region).await)
+        } else {
+            None
+        };
+        let topic_arn = sns.as_ref().and(args.topic_arn.as_deref());
+        return routing::sync_filter_policies(sns.as_ref(), topic_arn, &config).await;
+    }
+
+    let notifier = match (&args.topic_arn, args.dry_run) {
+        (Some(topic_arn), false) => Notifier::Sns {
+            client: sns_client(&args.region).await,
+            topic_arn: topic_arn.clone(),
+        },
+        (topic_arn, _) => Notifier::DryRun {
+            topic_arn: topic_arn.clone(),
+        },
+    };
+
+    let state_file = args.state_file.clone().unwrap_or_else(|| {
+        dirs::data_local_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("alfred")
+            .join("notify_new_movie.json")
     });
+    let mut notified = NotifiedStore::open(&state_file)?;
+    if args.dry_run {
+        notified = notified.read_only();
+    }
+    let already = notified.len();
+    let mut pipeline = Pipeline {
+        notified,
+        on_repeat: args.on_repeat,
+        notifier,
+        http: reqwest::Client::new(),
+        health: Health::new(),
+    };
+
+    if let Some(path) = &args.once {
+        let name = path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or("(unknown)")
+            .to_string();
+        let label = root_label_for(&args.roots, path);
+        let ts = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        handle_new_entry(&label, &name, &ts, &mut pipeline).await;
+        return Ok(());
+    }
+
+    if let Some(bind) = &args.http_bind {
+        health::spawn_server(bind, pipeline.health.clone())?;
+    }
+
+    // --- Dir watchers (blocking channels) -> one async stream tagged with the root label ---
+    let (tx_async, mut rx_async) = mpsc::unbounded_channel::<(String, String, String)>();
+    for (label, root) in &args.roots {
+        let rx_blocking =
+            lib::dirwatch::dirwatch::watch_dir(root, Duration::from_secs(args.debounce_secs))?;
+        println!(
+            "notify_new_movie watching {} ({label}) → {}",
+            root.display(),
+            pipeline.notifier.channel()
+        );
+        let tx_async = tx_async.clone();
+        let label = label.clone();
+        let health = pipeline.health.clone();
+        health.set_watcher_alive(&label, true);
+        std::thread::spawn(move || {
+            while let Ok((name, ts)) = rx_blocking.recv() {
+                let _ = tx_async.send((label.clone(), name, ts));
+            }
+            // The watcher thread only hangs up when it died.
+            eprintln!("dir watcher for {label} stopped");
+            health.set_watcher_alive(&label, false);
+        });
+    }
+    drop(tx_async);
 
     println!(
-        "notify_new_movie watching {} → SNS topic {}",
-        root.display(),
-        &args.topic_arn
+        "{already} movies already notified, state in {}",
+        state_file.display()
     );
 
-    while let Some((name, _ts)) = rx_async.recv().await {
-        let phrases = [
-            "🎬 New Movie Added:",
-            "🍿 Fresh Flick:",
-            "📀 Just Landed:",
-            "🎥 Now Watching:",
-            "✨ Incoming Title:",
-            "🆕 Added to Library:",
-            "🎞️ Hot Drop:",
-            "📽️ Newly Detected:",
-            "⭐ Fresh Upload:",
-            "🎉 Surprise Addition:",
-        ];
-
-        let mut rng = thread_rng();
-        let prefix = phrases.choose(&mut rng).unwrap();
-        let msg = format!("{prefix} {name}");
-
-        // Mark as Transactional (helps delivery; not strictly required)
-        let sms_type = MessageAttributeValue::builder()
-            .data_type("String")
-            .string_value("Transactional")
-            .build()
-            .unwrap();
-
-        let resp = sns
-            .publish()
-            .topic_arn(&args.topic_arn)
-            .message(msg)
-            .message_attributes("AWS.SNS.SMS.SMSType", sms_type)
-            .send()
-            .await;
-
-        match resp {
-            Ok(out) => println!(
-                "Published: {} ({name})",
-                out.message_id().unwrap_or("no-id")
-            ),
-            Err(e) => eprintln!("SNS publish failed for {name}: {e:?}"),
-        }
+    while let Some((label, name, ts)) = rx_async.recv().await {
+        handle_new_entry(&label, &name, &ts, &mut pipeline).await;
     }
 
     Ok(())
 }
+
+/// Label of the configured root containing `path`, else the first root's label.
+fn root_label_for(roots: &[(String, PathBuf)], path: &Path) -> String {
+    roots
+        .iter()
+        .find(|(_, root)| path.starts_with(root))
+        .or(roots.first())
+        .map(|(label, _)| label.clone())
+        .unwrap_or_default()
+}
+
+/// Decide whether `name` deserves a notification, render it and hand it to the notifier.
+async fn handle_new_entry(root_label: &str, name: &str, ts: &str, pipeline: &mut Pipeline) {
+    pipeline.health.record_event();
+    let mut recent = RecentEvent {
+        at: Local::now(),
+        root: root_label.to_string(),
+        name: name.to_string(),
+        outcome: "new".to_string(),
+        publish: None,
+    };
+    let release = Release::parse(name);
+    // Better a duplicate text than a missed one: the release is only recorded once the
+    // message went out (or was deliberately suppressed).
+    let record = |notified: &mut NotifiedStore| {
+        if let Err(e) = notified.record(&release, ts) {
+            eprintln!("Could not update notified state for {name}: {e:#}");
+        }
+    };
+    let upgrade = match (pipeline.notified.classify(&release), pipeline.on_repeat) {
+        (Outcome::New, _) => None,
+        (Outcome::Upgraded { from, to }, RepeatPolicy::Upgrade) => {
+            recent.outcome = "upgraded".to_string();
+            Some((from, to))
+        }
+        _ => {
+            println!("Skipping repeat for {name} (already notified)");
+            recent.outcome = "suppressed".to_string();
+            record(&mut pipeline.notified);
+            pipeline.health.record_result(recent);
+            return;
+        }
+    };
+
+    let phrases = [
+        "🎬 New Movie Added:",
+        "🍿 Fresh Flick:",
+        "📀 Just Landed:",
+        "🎥 Now Watching:",
+        "✨ Incoming Title:",
+        "🆕 Added to Library:",
+        "🎞️ Hot Drop:",
+        "📽️ Newly Detected:",
+        "⭐ Fresh Upload:",
+        "🎉 Surprise Addition:",
+    ];
+
+    let msg = match upgrade {
+        Some((from, to)) => format!("⬆️ Upgraded: {name} ({from} → {to})"),
+        None => {
+            let mut rng = thread_rng();
+            let prefix = phrases.choose(&mut rng).unwrap();
+            format!("{prefix} {name}")
+        }
+    };
+
+    let meta = MessageMeta::resolve(&pipeline.http, &release, root_label).await;
+
+    recent.publish = Some(match pipeline.notifier.send(name, &msg, &meta).await {
+        Ok(_) if matches!(pipeline.notifier, Notifier::DryRun { .. }) => {
+            record(&mut pipeline.notified);
+            "dry-run".to_string()
+        }
+        Ok(id) => {
+            println!(
+                "Published: {} ({name})",
+                id.as_deref().unwrap_or("no message id")
+            );
+            record(&mut pipeline.notified);
+            "ok".to_string()
+        }
+        Err(e) => {
+            eprintln!(
+                "Publish to {} failed for {name}: {e:?}",
+                pipeline.notifier.channel()
+            );
+            format!("failed: {e}")
+        }
+    });
+    pipeline.health.record_result(recent);
+}
@@ -0,0 +1,297 @@
+use anyhow::{Context, Result};
+use aws_sdk_sns::{types::MessageAttributeValue, Client};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+
+use lib::clients::tmdb::{
+    get_movie_by_id::get_movie_by_id,
+    get_release_dates::get_certification,
+    search_movie::{search_movie, MovieSearchResult},
+};
+
+use crate::dedup::Release;
+
+/// SNS message attributes attached to every publish so subscriptions can filter on them
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MessageMeta {
+    /// TMDB genre names, e.g. ["Animation", "Family"]
+    pub genres: Vec<String>,
+    /// US certification, e.g. "PG"
+    pub certification: Option<String>,
+    /// Label of the watch root the file appeared in, e.g. "movies" or "kids"
+    pub root: String,
+    /// e.g. "2160p"
+    pub resolution: Option<String>,
+}
+
+impl MessageMeta {
+    /// Build attributes for a release, enriching from TMDB by its `{tmdb-…}` id or, failing
+    /// that, a title/year search.
+    pub async fn resolve(http: &reqwest::Client, release: &Release, root: &str) -> Self {
+        let mut meta = MessageMeta {
+            root: root.to_string(),
+            resolution: release.resolution.map(|r| r.to_string()),
+            ..Default::default()
+        };
+        if std::env::var("TMDB_API_KEY").is_err() {
+            return meta;
+        }
+        let id = match release.tmdb_id {
+            Some(id) => id,
+            None => match search_movie(http, &release.title, release.year).await {
+                Ok(hits) => match search_match(release, &hits) {
+                    Some(id) => id,
+                    None => {
+                        eprintln!("No TMDB match for {:?} ({:?})", release.title, release.year);
+                        return meta;
+                    }
+                },
+                Err(e) => {
+                    eprintln!("TMDB search failed for {:?}: {e:#}", release.title);
+                    return meta;
+                }
+            },
+        };
+        match get_movie_by_id(http, id).await {
+            Ok(movie) => meta.genres = movie.genres.into_iter().map(|g| g.name).collect(),
+            Err(e) => eprintln!("TMDB lookup failed for tmdb_id={id}: {e:#}"),
+        }
+        match get_certification(http, id, "US").await {
+            Ok(cert) => meta.certification = cert,
+            Err(e) => eprintln!("TMDB certification lookup failed for tmdb_id={id}: {e:#}"),
+        }
+        meta
+    }
+
+    /// Render as SNS message attributes (attributes with no value are omitted).
+    pub fn to_attributes(&self) -> Result<Vec<(&'static str, MessageAttributeValue)>> {
+        let mut out = vec![(
+            "root",
+            MessageAttributeValue::builder()
+                .data_type("String")
+                .string_value(&self.root)
+                .build()?,
+        )];
+        if !self.genres.is_empty() {
+            out.push((
+                "genres",
+                MessageAttributeValue::builder()
+                    .data_type("String.Array")
+                    .string_value(serde_json::to_string(&self.genres)?)
+                    .build()?,
+            ));
+        }
+        if let Some(c) = &self.certification {
+            out.push((
+                "certification",
+                MessageAttributeValue::builder()
+                    .data_type("String")
+                    .string_value(c)
+                    .build()?,
+            ));
+        }
+        if let Some(r) = &self.resolution {
+            out.push((
+                "resolution",
+                MessageAttributeValue::builder()
+                    .data_type("String")
+                    .string_value(r)
+                    .build()?,
+            ));
+        }
+        Ok(out)
+    }
+}
+
+/// The first search hit with the release's title (ignoring case and punctuation) and a
+/// release year at most one off. Without a year the title alone has to match.
+pub fn search_match(release: &Release, hits: &[MovieSearchResult]) -> Option<u32> {
+    let key = |title: &str| {
+        Release {
+            title: title.to_string(),
+            year: None,
+            tmdb_id: None,
+            resolution: None,
+        }
+        .title_key()
+    };
+    let wanted = key(&release.title);
+    hits.iter()
+        .find(|h| {
+            let year = h
+                .release_date
+                .as_deref()
+                .and_then(|d| d.get(0..4))
+                .and_then(|y| y.parse::<u16>().ok());
+            key(&h.title) == wanted
+                && match (release.year, year) {
+                    (Some(w), Some(y)) => w.abs_diff(y) <= 1,
+                    (Some(_), None) => false,
+                    (None, _) => true,
+                }
+        })
+        .map(|h| h.id)
+}
+
+/// One subscriber in the recipients file. Empty lists mean "no restriction".
+#[derive(Debug, Clone, Deserialize)]
+pub struct Recipient {
+    pub name: String,
+    /// SNS protocol, e.g. "sms" or "email"
+    #[serde(default = "default_protocol")]
+    pub protocol: String,
+    /// Phone number / email address
+    pub endpoint: String,
+    #[serde(default)]
+    pub genres: Vec<String>,
+    #[serde(default)]
+    pub certifications: Vec<String>,
+    #[serde(default)]
+    pub roots: Vec<String>,
+    #[serde(default)]
+    pub resolutions: Vec<String>,
+}
+
+fn default_protocol() -> String {
+    "sms".to_string()
+}
+
+/// Recipients config file, e.g.
+/// `{ "recipients": [ { "name": "kids", "endpoint": "+15555550100", "genres": ["Animation", "Family"] } ] }`
+#[derive(Debug, Clone, Deserialize)]
+pub struct RecipientsConfig {
+    pub recipients: Vec<Recipient>,
+}
+
+impl RecipientsConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("reading recipients file {}", path.display()))?;
+        Ok(serde_json::from_str(&raw)?)
+    }
+}
+
+impl Recipient {
+    /// SNS filter policy for this recipient, or `None` when they should get everything.
+    pub fn filter_policy(&self) -> Option<serde_json::Value> {
+        let mut policy = BTreeMap::new();
+        for (key, values) in [
+            ("genres", &self.genres),
+            ("certification", &self.certifications),
+            ("root", &self.roots),
+            ("resolution", &self.resolutions),
+        ] {
+            if !values.is_empty() {
+                policy.insert(key, values.clone());
+            }
+        }
+        if policy.is_empty() {
+            None
+        } else {
+            Some(serde_json::json!(policy))
+        }
+    }
+}
+
+/// Print each recipient's generated filter policy and, with `apply`, push them to SNS
+/// (subscribing endpoints that aren't on the topic yet).
+pub async fn sync_filter_policies(
+    sns: Option<&Client>,
+    topic_arn: Option<&str>,
+    config: &RecipientsConfig,
+) -> Result<()> {
+    let existing = match (sns, topic_arn) {
+        (Some(sns), Some(topic_arn)) => list_subscriptions(sns, topic_arn).await?,
+        _ => Vec::new(),
+    };
+
+    for r in &config.recipients {
+        let policy = r.filter_policy();
+        // An empty object clears any previous policy, i.e. "send me everything".
+        let policy_str = policy
+            .as_ref()
+            .map(|p| p.to_string())
+            .unwrap_or_else(|| "{}".to_string());
+        println!(
+            "{}",
+            serde_json::json!({
+                "name": r.name,
+                "protocol": r.protocol,
+                "endpoint": r.endpoint,
+                "filter_policy": policy,
+            })
+        );
+
+        let (Some(sns), Some(topic_arn)) = (sns, topic_arn) else {
+            continue;
+        };
+        let sub = existing
+            .iter()
+            .find(|(protocol, endpoint, _)| *protocol == r.protocol && *endpoint == r.endpoint);
+        match sub {
+            Some((_, _, arn)) if arn == "PendingConfirmation" => {
+                eprintln!("{}: subscription pending confirmation; skipping", r.name);
+            }
+            Some((_, _, arn)) => {
+                sns.set_subscription_attributes()
+                    .subscription_arn(arn)
+                    .attribute_name("FilterPolicy")
+                    .attribute_value(&policy_str)
+                    .send()
+                    .await
+                    .with_context(|| format!("setting filter policy for {}", r.name))?;
+                println!("{}: filter policy applied to {arn}", r.name);
+            }
+            None => {
+                let mut req = sns
+                    .subscribe()
+                    .topic_arn(topic_arn)
+                    .protocol(&r.protocol)
+                    .endpoint(&r.endpoint)
+                    .return_subscription_arn(true);
+                if policy.is_some() {
+                    req = req.attributes("FilterPolicy", &policy_str);
+                }
+                let out = req
+                    .send()
+                    .await
+                    .with_context(|| format!("subscribing {}", r.name))?;
+                println!(
+                    "{}: subscribed ({})",
+                    r.name,
+                    out.subscription_arn().unwrap_or("no-arn")
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `(protocol, endpoint, subscription_arn)` for every subscription on the topic
+async fn list_subscriptions(
+    sns: &Client,
+    topic_arn: &str,
+) -> Result<Vec<(String, String, String)>> {
+    let mut out = Vec::new();
+    let mut next: Option<String> = None;
+    loop {
+        let page = sns
+            .list_subscriptions_by_topic()
+            .topic_arn(topic_arn)
+            .set_next_token(next.take())
+            .send()
+            .await?;
+        for s in page.subscriptions() {
+            out.push((
+                s.protocol().unwrap_or_default().to_string(),
+                s.endpoint().unwrap_or_default().to_string(),
+                s.subscription_arn().unwrap_or_default().to_string(),
+            ));
+        }
+        match page.next_token() {
+            Some(t) => next = Some(t.to_string()),
+            None => break,
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,90 @@
+use notify_new_movie::dedup::{NotifiedStore, Outcome, Release, Resolution};
+
+#[test]
+fn parses_titles_years_resolutions_and_tmdb_tags() {
+    let r = Release::parse("Arrival (2016) {tmdb-329865}");
+    assert_eq!(
+        (r.title.as_str(), r.year, r.tmdb_id, r.resolution),
+        ("Arrival", Some(2016), Some(329865), None)
+    );
+
+    let r = Release::parse("Arrival.2016.2160p.UHD.BluRay.mkv");
+    assert_eq!(
+        (r.title.as_str(), r.year, r.resolution),
+        ("Arrival", Some(2016), Some(Resolution(2160)))
+    );
+
+    // A year in the title isn't the release year.
+    let r = Release::parse("Blade.Runner.2049.2017.1080p.WEB-DL.mkv");
+    assert_eq!(
+        (r.title.as_str(), r.year, r.resolution),
+        ("Blade Runner 2049", Some(2017), Some(Resolution(1080)))
+    );
+    let r = Release::parse("2001 A Space Odyssey (1968)");
+    assert_eq!(
+        (r.title.as_str(), r.year),
+        ("2001 A Space Odyssey", Some(1968))
+    );
+
+    // Edition tags after the year are dropped; `[tmdbid-…]` and "4K" are understood.
+    let r = Release::parse("Blade Runner (1982) {edition-Final Cut} [tmdbid-78] 4K");
+    assert_eq!(
+        (r.title.as_str(), r.year, r.tmdb_id, r.resolution),
+        ("Blade Runner", Some(1982), Some(78), Some(Resolution(2160)))
+    );
+
+    let r = Release::parse("Some_Movie.720p.mkv");
+    assert_eq!(
+        (r.title.as_str(), r.year, r.resolution),
+        ("Some Movie", None, Some(Resolution(720)))
+    );
+    assert_eq!(
+        Release::parse("The.Matrix.1999.1080p").title_key(),
+        "the matrix|1999"
+    );
+}
+
+#[test]
+fn records_new_upgraded_and_repeated_releases() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notified.json");
+    let mut store = NotifiedStore::open(&path).unwrap();
+    let hd = Release::parse("Arrival (2016) 1080p");
+
+    // Classifying doesn't record anything, so a failed publish is retried.
+    assert_eq!(store.classify(&hd), Outcome::New);
+    assert_eq!(NotifiedStore::open(&path).unwrap().len(), 0);
+
+    assert_eq!(
+        store.record(&hd, "2025-10-01T10:00:00").unwrap(),
+        Outcome::New
+    );
+    let mut store = NotifiedStore::open(&path).unwrap();
+    assert_eq!(store.len(), 1);
+    assert_eq!(store.classify(&hd), Outcome::Repeat);
+
+    let uhd = Release::parse("Arrival.2016.2160p.UHD.BluRay.mkv");
+    let upgrade = Outcome::Upgraded {
+        from: Resolution(1080),
+        to: Resolution(2160),
+    };
+    assert_eq!(store.classify(&uhd), upgrade);
+    assert_eq!(store.record(&uhd, "2025-10-02T10:00:00").unwrap(), upgrade);
+    // The best resolution seen so far is kept.
+    assert_eq!(
+        store.record(&hd, "2025-10-03T10:00:00").unwrap(),
+        Outcome::Repeat
+    );
+
+    // A later `{tmdb-…}` rename matches on title and year, then on the id alone.
+    let tagged = Release::parse("Arrival (2016) {tmdb-329865}");
+    assert_eq!(
+        store.record(&tagged, "2025-10-04T10:00:00").unwrap(),
+        Outcome::Repeat
+    );
+    assert_eq!(
+        store.classify(&Release::parse("Arrival Renamed {tmdb-329865}")),
+        Outcome::Repeat
+    );
+    assert_eq!(store.len(), 1);
+}
@@ -0,0 +1,38 @@
+use notify_new_movie::dedup::{NotifiedStore, Outcome, Release};
+use notify_new_movie::notifier::Notifier;
+use notify_new_movie::routing::MessageMeta;
+
+#[tokio::test]
+async fn dry_runs_print_instead_of_publishing() {
+    let notifier = Notifier::DryRun {
+        topic_arn: Some("arn:aws:sns:eu-west-1:123:movies".into()),
+    };
+    assert_eq!(notifier.channel(), "stdout");
+    // No AWS client, and no message id comes back.
+    let meta = MessageMeta {
+        root: "movies".into(),
+        ..Default::default()
+    };
+    let sent = notifier
+        .send("Arrival (2016)", "New movie: Arrival", &meta)
+        .await;
+    assert_eq!(sent.unwrap(), None);
+}
+
+#[test]
+fn read_only_stores_never_write() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("notified.json");
+    let mut store = NotifiedStore::open(&path).unwrap().read_only();
+    let r = Release::parse("Arrival (2016)");
+    assert_eq!(
+        store.record(&r, "2025-10-01T10:00:00").unwrap(),
+        Outcome::New
+    );
+    // Still remembered for the rest of the run
+    assert_eq!(
+        store.record(&r, "2025-10-01T10:05:00").unwrap(),
+        Outcome::Repeat
+    );
+    assert!(!path.exists());
+}
@@ -0,0 +1,85 @@
+use actix_web::{http::StatusCode, test, web, App};
+use chrono::Local;
+use notify_new_movie::health::{self, Health, RecentEvent};
+use notify_new_movie::notifier::Notifier;
+use notify_new_movie::routing::MessageMeta;
+use serde_json::Value;
+
+fn event(outcome: &str, publish: Option<&str>) -> RecentEvent {
+    RecentEvent {
+        at: Local::now(),
+        root: "movies".into(),
+        name: "Arrival (2016)".into(),
+        outcome: outcome.into(),
+        publish: publish.map(String::from),
+    }
+}
+
+#[actix_rt::test]
+async fn reports_watchers_and_publish_counts() {
+    let health = Health::new();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::from(health.clone()))
+            .configure(health::configure),
+    )
+    .await;
+
+    // No watcher has started yet.
+    let req = test::TestRequest::get().uri("/healthz").to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+
+    health.set_watcher_alive("movies", true);
+    health.record_event();
+    health.record_result(event("new", Some("ok")));
+    health.record_result(event("upgraded", Some("failed: throttled")));
+    health.record_result(event("suppressed", None));
+    let req = test::TestRequest::get().uri("/healthz").to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+    let req = test::TestRequest::get().uri("/status").to_request();
+    let status: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(status["events_seen"], 1);
+    assert_eq!(status["publish_ok"], 1);
+    assert_eq!(status["publish_failed"], 1);
+    assert_eq!(status["recent"][0]["outcome"], "suppressed");
+
+    let req = test::TestRequest::get().uri("/metrics").to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    let metrics = String::from_utf8_lossy(&body);
+    assert!(metrics.contains("notify_new_movie_up 1\n"));
+    assert!(metrics.contains("notify_new_movie_outcomes_total{outcome=\"upgraded\"} 1\n"));
+    assert!(metrics.contains("notify_new_movie_publish_total{result=\"failed\"} 1\n"));
+
+    // Root labels are escaped for the exposition format.
+    health.set_watcher_alive("say \"hi\"\\\n", true);
+    let req = test::TestRequest::get().uri("/metrics").to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    let metrics = String::from_utf8_lossy(&body);
+    assert!(metrics.contains(r#"notify_new_movie_watcher_up{root="say \"hi\"\\\n"} 1"#));
+
+    health.set_watcher_alive("movies", false);
+    let req = test::TestRequest::get().uri("/healthz").to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+}
+
+#[actix_rt::test]
+async fn dry_run_never_publishes() {
+    let notifier = Notifier::DryRun { topic_arn: None };
+    assert_eq!(notifier.channel(), "stdout");
+    let sent = notifier
+        .send(
+            "Arrival (2016)",
+            "🎬 New Movie Added: Arrival (2016)",
+            &MessageMeta::default(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(sent, None);
+}
@@ -0,0 +1,80 @@
+use lib::clients::tmdb::search_movie::MovieSearchResult;
+use notify_new_movie::dedup::Release;
+use notify_new_movie::routing::{search_match, MessageMeta, RecipientsConfig};
+use serde_json::json;
+
+#[test]
+fn builds_filter_policies_from_recipients() {
+    let config: RecipientsConfig = serde_json::from_value(json!({
+        "recipients": [
+            { "name": "kids", "endpoint": "+15555550100", "genres": ["Animation", "Family"], "certifications": ["G", "PG"] },
+            { "name": "4k", "protocol": "email", "endpoint": "a@example.com", "roots": ["movies"], "resolutions": ["2160p"] },
+            { "name": "everything", "endpoint": "+15555550101" }
+        ]
+    }))
+    .unwrap();
+    let [kids, uhd, all] = &config.recipients[..] else {
+        panic!("expected three recipients");
+    };
+    assert_eq!(kids.protocol, "sms");
+    assert_eq!(
+        kids.filter_policy(),
+        Some(json!({ "genres": ["Animation", "Family"], "certification": ["G", "PG"] }))
+    );
+    assert_eq!(
+        uhd.filter_policy(),
+        Some(json!({ "root": ["movies"], "resolution": ["2160p"] }))
+    );
+    assert_eq!(all.filter_policy(), None);
+}
+
+#[test]
+fn renders_message_attributes() {
+    let bare = MessageMeta {
+        root: "movies".into(),
+        ..Default::default()
+    };
+    let attrs = bare.to_attributes().unwrap();
+    assert_eq!(attrs.len(), 1);
+    assert_eq!(attrs[0].0, "root");
+    assert_eq!(attrs[0].1.string_value(), Some("movies"));
+
+    let full = MessageMeta {
+        genres: vec!["Animation".into(), "Family".into()],
+        certification: Some("PG".into()),
+        root: "kids".into(),
+        resolution: Some("1080p".into()),
+    };
+    let attrs = full.to_attributes().unwrap();
+    let names: Vec<_> = attrs.iter().map(|(n, _)| *n).collect();
+    assert_eq!(names, ["root", "genres", "certification", "resolution"]);
+    assert_eq!(attrs[1].1.data_type(), "String.Array");
+    assert_eq!(attrs[1].1.string_value(), Some(r#"["Animation","Family"]"#));
+    assert_eq!(attrs[2].1.string_value(), Some("PG"));
+}
+
+#[test]
+fn matches_untagged_releases_by_title_and_year() {
+    let hit = |id, title: &str, date: Option<&str>| MovieSearchResult {
+        id,
+        title: title.into(),
+        release_date: date.map(String::from),
+        ..Default::default()
+    };
+    let hits = [
+        hit(1, "Arrival of the Fittest", Some("2016-11-11")),
+        hit(2, "Arrival", Some("1996-05-31")),
+        hit(329865, "Arrival", Some("2016-11-10")),
+    ];
+
+    let release = Release::parse("Arrival.2016.2160p.UHD.BluRay.mkv");
+    assert_eq!(search_match(&release, &hits), Some(329865));
+    // Off by a year, e.g. a festival premiere
+    let release = Release::parse("Arrival (2017).mkv");
+    assert_eq!(search_match(&release, &hits), Some(329865));
+    let release = Release::parse("Arrival (2005).mkv");
+    assert_eq!(search_match(&release, &hits), None);
+    // Without a year the first title match wins
+    let release = Release::parse("arrival.mkv");
+    assert_eq!(search_match(&release, &hits), Some(2));
+}
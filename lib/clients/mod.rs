@@ -1,2 +1,4 @@
 pub mod openai;
+pub mod plex;
+pub mod radarr;
 pub mod tmdb;
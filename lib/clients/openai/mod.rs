@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::env;
@@ -41,7 +41,7 @@ pub struct MessageContent {
 }
 
 pub async fn get_recommendations(client: &Client, prompt: &str) -> Result<String> {
-    let api_key = env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY not set");
+    let api_key = env::var("OPENAI_API_KEY").context("OPENAI_API_KEY not set")?;
 
     let req = ChatRequest {
         model: "gpt-4o-mini".to_string(),
@@ -75,7 +75,7 @@ pub async fn get_recommendations(client: &Client, prompt: &str) -> Result<String
 
     let content = resp
         .choices
-        .get(0)
+        .first()
         .map(|c| c.message.content.clone())
         .unwrap_or_default();
 
@@ -0,0 +1,117 @@
+use anyhow::Result;
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Deserialize)]
+struct Container<T> {
+    #[serde(rename = "MediaContainer")]
+    media_container: T,
+}
+
+#[derive(Debug, Deserialize)]
+struct Sections {
+    #[serde(rename = "Directory", default)]
+    directory: Vec<Section>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Section {
+    key: String,
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Items {
+    #[serde(rename = "Metadata", default)]
+    metadata: Vec<Item>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Item {
+    view_count: Option<u32>,
+    /// Shows: episodes watched
+    viewed_leaf_count: Option<u32>,
+    #[serde(rename = "Guid", default)]
+    guid: Vec<Guid>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Guid {
+    id: String,
+}
+
+/// TMDB ids of everything the token's account has watched on the server
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlexWatched {
+    pub movies: Vec<u32>,
+    /// Series with at least one watched episode
+    pub shows: Vec<u32>,
+}
+
+fn plex_get(client: &Client, base_url: &str, token: &str, path: &str) -> RequestBuilder {
+    client
+        .get(format!("{}{path}", base_url.trim_end_matches('/')))
+        .header("Accept", "application/json")
+        .header("X-Plex-Token", token)
+        .header("X-Plex-Client-Identifier", "alfred")
+        .header("X-Plex-Product", "alfred")
+        .header("X-Plex-Version", "1.0")
+        .timeout(Duration::from_secs(30))
+}
+
+fn tmdb_guid(item: &Item) -> Option<u32> {
+    item.guid
+        .iter()
+        .find_map(|g| g.id.strip_prefix("tmdb://")?.parse().ok())
+}
+
+/// Watched movies and series across the server's movie and TV libraries, or only
+/// `section` (a library key) when given. Items without a TMDB guid are skipped.
+pub async fn get_watched(
+    client: &Client,
+    base_url: &str,
+    token: &str,
+    section: Option<&str>,
+) -> Result<PlexWatched> {
+    let sections: Container<Sections> = plex_get(client, base_url, token, "/library/sections")
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let mut watched = PlexWatched::default();
+    for s in sections.media_container.directory {
+        if section.is_some_and(|k| k != s.key) {
+            continue;
+        }
+        let (kind, out) = match s.kind.as_str() {
+            "movie" => (1, &mut watched.movies),
+            "show" => (2, &mut watched.shows),
+            _ => continue,
+        };
+        let path = format!("/library/sections/{}/all?type={kind}&includeGuids=1", s.key);
+        let items: Container<Items> = plex_get(client, base_url, token, &path)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        out.extend(
+            items
+                .media_container
+                .metadata
+                .iter()
+                .filter(|i| i.view_count.unwrap_or(0) > 0 || i.viewed_leaf_count.unwrap_or(0) > 0)
+                .filter_map(tmdb_guid),
+        );
+    }
+    watched.movies.sort_unstable();
+    watched.movies.dedup();
+    watched.shows.sort_unstable();
+    watched.shows.dedup();
+    Ok(watched)
+}
@@ -0,0 +1,34 @@
+use anyhow::Result;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// One movie in the Radarr library (`/api/v3/movie`); only the fields we use
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RadarrMovie {
+    pub tmdb_id: Option<u32>,
+    pub title: Option<String>,
+    #[serde(default)]
+    pub has_file: bool,
+    #[serde(default)]
+    pub monitored: bool,
+}
+
+/// Every movie Radarr knows about, downloaded or still wanted.
+pub async fn get_movies(
+    client: &Client,
+    base_url: &str,
+    api_key: &str,
+) -> Result<Vec<RadarrMovie>> {
+    let url = format!("{}/api/v3/movie", base_url.trim_end_matches('/'));
+    let resp = client
+        .get(url)
+        .header("X-Api-Key", api_key)
+        .timeout(Duration::from_secs(30))
+        .send()
+        .await?
+        .error_for_status()?;
+    let movies: Vec<RadarrMovie> = resp.json().await?;
+    Ok(movies)
+}
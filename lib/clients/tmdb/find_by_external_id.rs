@@ -0,0 +1,41 @@
+use anyhow::Result;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::env;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FindResult {
+    pub id: u32,
+}
+
+/// Response type for `/find/{external_id}` (TMDB v3); only the ids are kept
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TmdbFind {
+    #[serde(default)]
+    pub movie_results: Vec<FindResult>,
+    #[serde(default)]
+    pub tv_results: Vec<FindResult>,
+}
+
+/// Look up TMDB ids by an external id. `source` is e.g. "tvdb_id" or "imdb_id".
+pub async fn find_by_external_id(
+    client: &Client,
+    external_id: &str,
+    source: &str,
+) -> Result<TmdbFind> {
+    let api_key = env::var("TMDB_API_KEY")?;
+    let url = format!(
+        "https://api.themoviedb.org/3/find/{}?external_source={}&api_key={}",
+        external_id, source, api_key
+    );
+
+    let resp = client.get(url).send().await?.error_for_status()?;
+    let found: TmdbFind = resp.json().await?;
+    Ok(found)
+}
+
+/// TMDB tv series id for a TVDB series id, if TMDB knows it
+pub async fn tmdb_tv_id_for_tvdb(client: &Client, tvdb_id: u32) -> Result<Option<u32>> {
+    let found = find_by_external_id(client, &tvdb_id.to_string(), "tvdb_id").await?;
+    Ok(found.tv_results.first().map(|r| r.id))
+}
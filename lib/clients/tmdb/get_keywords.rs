@@ -0,0 +1,39 @@
+use anyhow::Result;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::env;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Keyword {
+    pub id: u32,
+    pub name: String,
+}
+
+/// Response type for `/movie/{movie_id}/keywords` and `/tv/{series_id}/keywords` (TMDB v3).
+/// Movies list them under `keywords`, series under `results`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TmdbKeywords {
+    pub id: u32,
+    #[serde(default, alias = "results")]
+    pub keywords: Vec<Keyword>,
+}
+
+pub async fn get_movie_keywords(client: &Client, movie_id: u32) -> Result<Vec<Keyword>> {
+    get_keywords(client, "movie", movie_id).await
+}
+
+pub async fn get_tv_keywords(client: &Client, series_id: u32) -> Result<Vec<Keyword>> {
+    get_keywords(client, "tv", series_id).await
+}
+
+async fn get_keywords(client: &Client, kind: &str, id: u32) -> Result<Vec<Keyword>> {
+    let api_key = env::var("TMDB_API_KEY")?;
+    let url = format!(
+        "https://api.themoviedb.org/3/{}/{}/keywords?api_key={}",
+        kind, id, api_key
+    );
+
+    let resp = client.get(url).send().await?.error_for_status()?;
+    let found: TmdbKeywords = resp.json().await?;
+    Ok(found.keywords)
+}
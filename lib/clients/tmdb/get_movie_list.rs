@@ -0,0 +1,47 @@
+use anyhow::Result;
+use reqwest::Client;
+use serde::Deserialize;
+use std::env;
+
+use super::search_movie::MovieSearchResult;
+
+/// TMDB's curated movie lists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovieList {
+    Popular,
+    TopRated,
+}
+
+impl MovieList {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MovieList::Popular => "popular",
+            MovieList::TopRated => "top_rated",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct MovieListPage {
+    #[serde(default)]
+    results: Vec<MovieSearchResult>,
+}
+
+/// One page (20 titles, 1-based) of `/movie/popular` or `/movie/top_rated`.
+pub async fn get_movie_list(
+    client: &Client,
+    list: MovieList,
+    page: u32,
+) -> Result<Vec<MovieSearchResult>> {
+    let api_key = env::var("TMDB_API_KEY")?;
+    let url = format!(
+        "https://api.themoviedb.org/3/movie/{}?api_key={}&page={}",
+        list.as_str(),
+        api_key,
+        page
+    );
+
+    let resp = client.get(url).send().await?.error_for_status()?;
+    let found: MovieListPage = resp.json().await?;
+    Ok(found.results)
+}
@@ -0,0 +1,66 @@
+use anyhow::Result;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::env;
+
+use super::get_keywords::Keyword;
+use super::get_movie_by_id::{BelongsToCollection, Genre};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CastMember {
+    pub id: u32,
+    pub name: String,
+    /// Billing position, 0 first
+    pub order: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CrewMember {
+    pub id: u32,
+    pub name: String,
+    pub job: Option<String>,
+}
+
+/// `keywords` as appended to the movie (no `id`, unlike `/movie/{id}/keywords`)
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct KeywordList {
+    #[serde(default)]
+    pub keywords: Vec<Keyword>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Credits {
+    #[serde(default)]
+    pub cast: Vec<CastMember>,
+    #[serde(default)]
+    pub crew: Vec<CrewMember>,
+}
+
+/// `/movie/{movie_id}?append_to_response=keywords,credits` (TMDB v3): what a film is
+/// about and who made it, in one request
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MovieMetadata {
+    pub id: u32,
+    pub title: String,
+    pub overview: Option<String>,
+    pub release_date: Option<String>,
+    #[serde(default)]
+    pub genres: Vec<Genre>,
+    pub belongs_to_collection: Option<BelongsToCollection>,
+    #[serde(default)]
+    pub keywords: KeywordList,
+    #[serde(default)]
+    pub credits: Credits,
+}
+
+pub async fn get_movie_metadata(client: &Client, movie_id: u32) -> Result<MovieMetadata> {
+    let api_key = env::var("TMDB_API_KEY")?;
+    let url = format!(
+        "https://api.themoviedb.org/3/movie/{}?api_key={}&append_to_response=keywords,credits",
+        movie_id, api_key
+    );
+
+    let resp = client.get(url).send().await?.error_for_status()?;
+    let movie: MovieMetadata = resp.json().await?;
+    Ok(movie)
+}
@@ -0,0 +1,77 @@
+use anyhow::Result;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::env;
+
+/// Which of TMDB's per-title lists to read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RelatedList {
+    /// `/recommendations`: what people who liked the title also liked
+    Recommendations,
+    /// `/similar`: matched on genres and keywords
+    Similar,
+}
+
+impl RelatedList {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RelatedList::Recommendations => "recommendations",
+            RelatedList::Similar => "similar",
+        }
+    }
+}
+
+/// One entry of a `/recommendations` or `/similar` list; movies have `title` and
+/// `release_date`, series `name` and `first_air_date`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct RelatedTitle {
+    pub id: u32,
+    pub title: Option<String>,
+    pub name: Option<String>,
+    pub release_date: Option<String>,
+    pub first_air_date: Option<String>,
+    #[serde(default)]
+    pub genre_ids: Vec<u32>,
+    pub popularity: Option<f64>,
+    pub vote_average: Option<f64>,
+    pub vote_count: Option<u32>,
+    #[serde(default)]
+    pub adult: bool,
+}
+
+impl RelatedTitle {
+    /// "YYYY-MM-DD", whichever of the two dates the title has
+    pub fn released(&self) -> Option<&str> {
+        self.release_date
+            .as_deref()
+            .or(self.first_air_date.as_deref())
+            .filter(|d| !d.is_empty())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RelatedPage {
+    #[serde(default)]
+    results: Vec<RelatedTitle>,
+}
+
+/// The first page of `/{movie|tv}/{id}/{recommendations|similar}`.
+pub async fn get_related(
+    client: &Client,
+    media_type: &str,
+    id: u32,
+    list: RelatedList,
+) -> Result<Vec<RelatedTitle>> {
+    let api_key = env::var("TMDB_API_KEY")?;
+    let url = format!(
+        "https://api.themoviedb.org/3/{}/{}/{}?api_key={}",
+        media_type,
+        id,
+        list.as_str(),
+        api_key
+    );
+
+    let resp = client.get(url).send().await?.error_for_status()?;
+    let page: RelatedPage = resp.json().await?;
+    Ok(page.results)
+}
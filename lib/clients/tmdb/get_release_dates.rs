@@ -0,0 +1,57 @@
+use anyhow::Result;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::env;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ReleaseDate {
+    pub certification: String,
+    pub release_date: Option<String>,
+    #[serde(rename = "type")]
+    pub release_type: Option<u8>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CountryReleaseDates {
+    pub iso_3166_1: String,
+    pub release_dates: Vec<ReleaseDate>,
+}
+
+/// Response type for `/movie/{movie_id}/release_dates` (TMDB v3)
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TmdbReleaseDates {
+    pub id: u32,
+    pub results: Vec<CountryReleaseDates>,
+}
+
+pub async fn get_release_dates(client: &Client, movie_id: u32) -> Result<TmdbReleaseDates> {
+    let api_key = env::var("TMDB_API_KEY")?;
+    let url = format!(
+        "https://api.themoviedb.org/3/movie/{}/release_dates?api_key={}",
+        movie_id, api_key
+    );
+
+    let resp = client.get(url).send().await?.error_for_status()?;
+    let dates: TmdbReleaseDates = resp.json().await?;
+    Ok(dates)
+}
+
+/// First non-empty certification (e.g. "PG-13") for a country code like "US"
+pub async fn get_certification(
+    client: &Client,
+    movie_id: u32,
+    country: &str,
+) -> Result<Option<String>> {
+    let dates = get_release_dates(client, movie_id).await?;
+    Ok(dates
+        .results
+        .iter()
+        .find(|c| c.iso_3166_1.eq_ignore_ascii_case(country))
+        .and_then(|c| {
+            c.release_dates
+                .iter()
+                .map(|d| d.certification.trim())
+                .find(|s| !s.is_empty())
+                .map(|s| s.to_string())
+        }))
+}
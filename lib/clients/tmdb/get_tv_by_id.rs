@@ -0,0 +1,42 @@
+use anyhow::Result;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::env;
+
+use super::get_movie_by_id::Genre;
+
+/// Subset of the response for `/tv/{series_id}` (TMDB v3)
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TmdbTv {
+    pub id: u32,
+    pub name: String,
+    pub original_name: Option<String>,
+    pub original_language: Option<String>,
+    pub overview: Option<String>,
+    pub first_air_date: Option<String>,
+    pub last_air_date: Option<String>,
+    #[serde(default)]
+    pub genres: Vec<Genre>,
+    #[serde(default)]
+    pub episode_run_time: Vec<u32>,
+    pub number_of_seasons: Option<u32>,
+    pub number_of_episodes: Option<u32>,
+    pub status: Option<String>,
+    pub poster_path: Option<String>,
+    pub popularity: Option<f64>,
+    pub vote_average: Option<f64>,
+    pub vote_count: Option<u32>,
+    pub adult: Option<bool>,
+}
+
+pub async fn get_tv_by_id(client: &Client, series_id: u32) -> Result<TmdbTv> {
+    let api_key = env::var("TMDB_API_KEY")?;
+    let url = format!(
+        "https://api.themoviedb.org/3/tv/{}?api_key={}",
+        series_id, api_key
+    );
+
+    let resp = client.get(url).send().await?.error_for_status()?;
+    let tv: TmdbTv = resp.json().await?;
+    Ok(tv)
+}
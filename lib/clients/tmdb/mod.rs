@@ -1 +1,10 @@
+pub mod find_by_external_id;
+pub mod get_keywords;
 pub mod get_movie_by_id;
+pub mod get_movie_list;
+pub mod get_movie_metadata;
+pub mod get_related;
+pub mod get_release_dates;
+pub mod get_tv_by_id;
+pub mod search_movie;
+pub mod search_tv;
@@ -0,0 +1,44 @@
+use anyhow::Result;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::env;
+
+/// One hit from `/search/movie`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct MovieSearchResult {
+    pub id: u32,
+    pub title: String,
+    pub original_title: Option<String>,
+    pub release_date: Option<String>,
+    pub popularity: Option<f64>,
+    pub vote_count: Option<u32>,
+    #[serde(default)]
+    pub adult: bool,
+}
+
+/// Response type for `/search/movie` (TMDB v3); only the first page is read
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TmdbMovieSearch {
+    #[serde(default)]
+    pub results: Vec<MovieSearchResult>,
+    pub total_results: Option<u32>,
+}
+
+/// Search movies by title, optionally narrowed to a release year.
+pub async fn search_movie(
+    client: &Client,
+    query: &str,
+    year: Option<u16>,
+) -> Result<Vec<MovieSearchResult>> {
+    let api_key = env::var("TMDB_API_KEY")?;
+    let mut req = client
+        .get("https://api.themoviedb.org/3/search/movie")
+        .query(&[("api_key", api_key.as_str()), ("query", query)]);
+    if let Some(y) = year {
+        req = req.query(&[("year", y)]);
+    }
+
+    let resp = req.send().await?.error_for_status()?;
+    let found: TmdbMovieSearch = resp.json().await?;
+    Ok(found.results)
+}
@@ -0,0 +1,36 @@
+use anyhow::Result;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::env;
+
+/// One hit from `/search/tv`
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct TvSearchResult {
+    pub id: u32,
+    pub name: String,
+    pub original_name: Option<String>,
+    pub first_air_date: Option<String>,
+    pub popularity: Option<f64>,
+    pub vote_count: Option<u32>,
+}
+
+/// Response type for `/search/tv` (TMDB v3); only the first page is read
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TmdbTvSearch {
+    #[serde(default)]
+    pub results: Vec<TvSearchResult>,
+    pub total_results: Option<u32>,
+}
+
+/// Search TV series by name.
+pub async fn search_tv(client: &Client, query: &str) -> Result<Vec<TvSearchResult>> {
+    let api_key = env::var("TMDB_API_KEY")?;
+    let resp = client
+        .get("https://api.themoviedb.org/3/search/tv")
+        .query(&[("api_key", api_key.as_str()), ("query", query)])
+        .send()
+        .await?
+        .error_for_status()?;
+    let found: TmdbTvSearch = resp.json().await?;
+    Ok(found.results)
+}
@@ -1,2 +1,4 @@
 pub mod clients;
 pub mod dirwatch;
+pub mod scheduler;
+pub mod store;
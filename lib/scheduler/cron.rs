@@ -0,0 +1,168 @@
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Datelike, Duration, NaiveDateTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use std::fmt;
+
+use super::Schedule;
+
+/// A standard 5-field cron expression (`minute hour day-of-month month day-of-week`)
+/// evaluated in a timezone. Fields take `*`, numbers, ranges (`1-5`), steps (`*/15`,
+/// `8-18/2`), lists and `jan`…`dec` / `sun`…`sat`; `@hourly`, `@daily`, `@weekly`,
+/// `@monthly` and `@yearly` are accepted too.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Cron {
+    expr: String,
+    tz: Tz,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Cron matches a day if *either* day field matches when both are restricted.
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl fmt::Debug for Cron {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cron({:?} {})", self.expr, self.tz)
+    }
+}
+
+impl fmt::Display for Cron {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.expr, self.tz)
+    }
+}
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+fn value(s: &str, names: &[&str], offset: u32) -> Option<u32> {
+    let lower = s.to_ascii_lowercase();
+    names
+        .iter()
+        .position(|n| *n == lower)
+        .map(|i| i as u32 + offset)
+        .or_else(|| s.parse().ok())
+}
+
+/// Parse one field into a bitset of allowed values. Returns (bits, restricted).
+fn field(s: &str, min: u32, max: u32, names: &[&str], offset: u32) -> Result<(u64, bool)> {
+    let mut bits = 0u64;
+    for part in s.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((r, step)) => (
+                r,
+                step.parse::<u32>()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .ok_or_else(|| anyhow!("bad step in {part:?}"))?,
+            ),
+            None => (part, 1),
+        };
+        let (lo, hi) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            let a = value(a, names, offset).ok_or_else(|| anyhow!("bad value {a:?}"))?;
+            let b = value(b, names, offset).ok_or_else(|| anyhow!("bad value {b:?}"))?;
+            (a, b)
+        } else {
+            let v = value(range, names, offset).ok_or_else(|| anyhow!("bad value {range:?}"))?;
+            // "5/15" means "from 5, every 15"
+            (v, if step > 1 { max } else { v })
+        };
+        if lo < min || hi > max || lo > hi {
+            bail!("{part:?} is outside {min}-{max}");
+        }
+        for v in (lo..=hi).step_by(step as usize) {
+            bits |= 1 << v;
+        }
+    }
+    // Like vixie cron, a field starting with `*` doesn't count as restricting the day.
+    Ok((bits, !s.starts_with('*')))
+}
+
+fn has(bits: u64, v: u32) -> bool {
+    bits & (1 << v) != 0
+}
+
+impl Cron {
+    pub fn parse(expr: &str, tz: Tz) -> Result<Self> {
+        let expr = expr.trim();
+        let expanded = match expr.to_ascii_lowercase().as_str() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            _ => expr,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [m, h, dom, mon, dow] = fields[..] else {
+            bail!("cron expression {expr:?} needs 5 fields");
+        };
+        let with = |e: anyhow::Error| anyhow!("cron expression {expr:?}: {e}");
+        let (minutes, _) = field(m, 0, 59, &[], 0).map_err(with)?;
+        let (hours, _) = field(h, 0, 23, &[], 0).map_err(with)?;
+        let (days, days_restricted) = field(dom, 1, 31, &[], 0).map_err(with)?;
+        let (months, _) = field(mon, 1, 12, &MONTHS, 1).map_err(with)?;
+        let (mut weekdays, weekdays_restricted) = field(dow, 0, 7, &WEEKDAYS, 0).map_err(with)?;
+        // 7 is Sunday too
+        if has(weekdays, 7) {
+            weekdays |= 1;
+        }
+        Ok(Self {
+            expr: expr.to_string(),
+            tz,
+            minutes,
+            hours,
+            days,
+            months,
+            weekdays,
+            days_restricted,
+            weekdays_restricted,
+        })
+    }
+
+    fn day_matches(&self, t: &NaiveDateTime) -> bool {
+        let dom = has(self.days, t.day());
+        let dow = has(self.weekdays, t.weekday().num_days_from_sunday());
+        let day = match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => dom || dow,
+            _ => dom && dow,
+        };
+        day && has(self.months, t.month())
+    }
+}
+
+impl Schedule for Cron {
+    fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let local = after.with_timezone(&self.tz).naive_local();
+        let mut t = local.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        // Expressions like "0 0 30 2 *" never match; give up after a few years.
+        let give_up = t + Duration::days(366 * 5);
+        while t < give_up {
+            if !self.day_matches(&t) {
+                t = t.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+                continue;
+            }
+            if !has(self.hours, t.hour()) {
+                t = t.with_minute(0)? + Duration::hours(1);
+                continue;
+            }
+            if !has(self.minutes, t.minute()) {
+                t += Duration::minutes(1);
+                continue;
+            }
+            // Skip times a DST change removes, and the repeat of an hour it doubles.
+            match self.tz.from_local_datetime(&t).earliest() {
+                Some(at) if at.with_timezone(&Utc) > after => return Some(at.with_timezone(&Utc)),
+                _ => t += Duration::minutes(1),
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,251 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use std::{
+    collections::hash_map::RandomState,
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::store::StoreHandle;
+
+pub mod cron;
+
+pub use cron::Cron;
+
+/// When a job fires
+pub trait Schedule: Send + Sync {
+    /// The first fire time strictly after `after`; `None` if it never fires again.
+    fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>>;
+}
+
+/// What to do at startup about runs that were due while the process was down
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CatchUp {
+    /// Wait for the next scheduled time
+    Skip,
+    /// Run once straight away, however many runs were missed
+    #[default]
+    RunOnce,
+}
+
+impl FromStr for CatchUp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "skip" => Ok(Self::Skip),
+            "run-once" | "run_once" | "once" => Ok(Self::RunOnce),
+            other => Err(format!(
+                "unknown catch-up policy {other:?} (skip or run-once)"
+            )),
+        }
+    }
+}
+
+type JobFuture = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
+
+/// A named piece of background work and when to run it. The name keys its persisted
+/// state, so keep it stable.
+pub struct Job {
+    pub name: String,
+    schedule: Box<dyn Schedule>,
+    catch_up: CatchUp,
+    jitter: Duration,
+    max_runtime: Duration,
+    run: Arc<dyn Fn() -> JobFuture + Send + Sync>,
+}
+
+impl Job {
+    pub fn new<F, Fut>(name: impl Into<String>, schedule: impl Schedule + 'static, run: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        Self {
+            name: name.into(),
+            schedule: Box::new(schedule),
+            catch_up: CatchUp::default(),
+            jitter: Duration::ZERO,
+            max_runtime: Duration::from_secs(60 * 60),
+            run: Arc::new(move || Box::pin(run()) as JobFuture),
+        }
+    }
+
+    pub fn catch_up(mut self, catch_up: CatchUp) -> Self {
+        self.catch_up = catch_up;
+        self
+    }
+
+    /// Delay each run by a random amount up to `jitter`.
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// After this long a run is assumed to have died, and the job may start again.
+    pub fn max_runtime(mut self, max_runtime: Duration) -> Self {
+        self.max_runtime = max_runtime;
+        self
+    }
+
+    /// Apply `JOB_<NAME>_CRON` (evaluated in `tz`), `JOB_<NAME>_CATCH_UP` and
+    /// `JOB_<NAME>_JITTER_SECS` if set. Invalid values are logged and ignored.
+    pub fn with_env_overrides(mut self, tz: Tz) -> Self {
+        let prefix = format!(
+            "JOB_{}_",
+            self.name.to_ascii_uppercase().replace(['-', ' '], "_")
+        );
+        let var = |k: &str| {
+            std::env::var(format!("{prefix}{k}"))
+                .ok()
+                .filter(|v| !v.trim().is_empty())
+        };
+        if let Some(expr) = var("CRON") {
+            match Cron::parse(&expr, tz) {
+                Ok(c) => self.schedule = Box::new(c),
+                Err(e) => log::error!("Ignoring {prefix}CRON: {e:#}"),
+            }
+        }
+        if let Some(c) = var("CATCH_UP") {
+            match c.parse() {
+                Ok(c) => self.catch_up = c,
+                Err(e) => log::error!("Ignoring {prefix}CATCH_UP: {e}"),
+            }
+        }
+        if let Some(j) = var("JITTER_SECS") {
+            match j.trim().parse() {
+                Ok(secs) => self.jitter = Duration::from_secs(secs),
+                Err(_) => log::error!("Ignoring {prefix}JITTER_SECS: {j:?} is not a number"),
+            }
+        }
+        self
+    }
+
+    /// When the first run after startup should happen, given when the job last started.
+    /// A job that has never run waits for its first scheduled time.
+    pub fn first_run(
+        &self,
+        last_started: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        let missed = last_started
+            .and_then(|last| self.schedule.next_after(last))
+            .filter(|due| *due <= now);
+        match (missed, self.catch_up) {
+            (Some(_), CatchUp::RunOnce) => Some(now),
+            _ => self.schedule.next_after(now),
+        }
+    }
+}
+
+fn random_delay(max: Duration) -> Duration {
+    if max.is_zero() {
+        return Duration::ZERO;
+    }
+    // RandomState is seeded per instance, which is plenty for spreading out runs.
+    let r = RandomState::new().build_hasher().finish();
+    Duration::from_millis(r % (max.as_millis() as u64 + 1))
+}
+
+/// Run `f` as job `name` unless a run of it is already in progress in any process using
+/// `store`. Returns `Ok(None)` when skipped for that reason. The outcome is recorded
+/// either way `f` goes.
+pub async fn run_exclusive<F, Fut, R>(
+    store: &StoreHandle,
+    name: &str,
+    max_runtime: Duration,
+    f: F,
+) -> Result<Option<R>>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<R>>,
+{
+    let job = name.to_string();
+    let started = store
+        .write(move |s| s.try_start_job(&job, Utc::now(), max_runtime))
+        .await?;
+    if !started {
+        return Ok(None);
+    }
+    let out = f().await;
+    let job = name.to_string();
+    let error = out.as_ref().err().map(|e| format!("{e:#}"));
+    store
+        .write(move |s| s.finish_job(&job, Utc::now(), error.as_deref()))
+        .await?;
+    out.map(Some)
+}
+
+/// Runs registered [`Job`]s on their schedules, persisting each job's last and next run
+/// in the store so restarts neither lose nor repeat work.
+pub struct Scheduler {
+    store: StoreHandle,
+    jobs: Vec<Job>,
+}
+
+impl Scheduler {
+    pub fn new(store: StoreHandle) -> Self {
+        Self {
+            store,
+            jobs: Vec::new(),
+        }
+    }
+
+    pub fn register(&mut self, job: Job) -> &mut Self {
+        self.jobs.push(job);
+        self
+    }
+
+    /// Start one task per job on the current tokio runtime.
+    pub fn spawn(self) -> Vec<tokio::task::JoinHandle<()>> {
+        self.jobs
+            .into_iter()
+            .map(|job| tokio::spawn(drive(self.store.clone(), job)))
+            .collect()
+    }
+}
+
+async fn drive(store: StoreHandle, job: Job) {
+    let name = job.name.clone();
+    let state = {
+        let name = name.clone();
+        store.read(move |s| s.job_state(&name)).await
+    };
+    let last_started = match state {
+        Ok(s) => s.and_then(|s| s.last_started_at),
+        Err(e) => {
+            log::error!("Could not load state for job {name}: {e:#}");
+            None
+        }
+    };
+    let mut next = job.first_run(last_started, Utc::now());
+
+    // A job's runs never overlap within this loop; run_exclusive covers other processes.
+    while let Some(due) = next {
+        let at = due + random_delay(job.jitter);
+        let job_name = name.clone();
+        if let Err(e) = store
+            .write(move |s| s.set_job_next_run(&job_name, Some(at)))
+            .await
+        {
+            log::error!("Could not save next run of job {name}: {e:#}");
+        }
+        log::info!("Job {name}: next run at {at}");
+        tokio::time::sleep((at - Utc::now()).to_std().unwrap_or_default()).await;
+
+        let t0 = std::time::Instant::now();
+        match run_exclusive(&store, &name, job.max_runtime, || (job.run)()).await {
+            Ok(Some(())) => log::info!("Job {name} finished in {:?}", t0.elapsed()),
+            Ok(None) => log::warn!("Job {name} is already running elsewhere; skipped"),
+            Err(e) => log::error!("Job {name} failed after {:?}: {e:#}", t0.elapsed()),
+        }
+        next = job.schedule.next_after(Utc::now().max(due));
+    }
+    log::info!("Job {name} has no further runs");
+    let _ = store.write(move |s| s.set_job_next_run(&name, None)).await;
+}
@@ -0,0 +1,224 @@
+#[path = "../../store/tests/common/mod.rs"]
+mod common;
+
+use chrono::{DateTime, Duration as ChronoDuration, TimeZone, Utc};
+use chrono_tz::Tz;
+use common::temp_db;
+use lib::scheduler::{run_exclusive, CatchUp, Cron, Job, Schedule, Scheduler};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+fn utc(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+}
+
+fn next(expr: &str, tz: Tz, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    Cron::parse(expr, tz).unwrap().next_after(after)
+}
+
+/// Fires every 100ms
+struct Soon;
+
+impl Schedule for Soon {
+    fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        Some(after + ChronoDuration::milliseconds(100))
+    }
+}
+
+#[test]
+fn cron_next_after() {
+    let monday = utc(2025, 9, 29, 12, 7);
+    assert_eq!(
+        next("*/15 * * * *", Tz::UTC, monday),
+        Some(utc(2025, 9, 29, 12, 15))
+    );
+    // Strictly after: a time that matches exactly moves on to the next one.
+    assert_eq!(
+        next("15 12 * * *", Tz::UTC, utc(2025, 9, 29, 12, 15)),
+        Some(utc(2025, 9, 30, 12, 15))
+    );
+    assert_eq!(
+        next("0 9 * * mon-fri", Tz::UTC, utc(2025, 10, 3, 10, 0)),
+        Some(utc(2025, 10, 6, 9, 0))
+    );
+    // Both day fields restricted: either may match (the 13th, or a Friday).
+    assert_eq!(
+        next("0 0 13 * fri", Tz::UTC, monday),
+        Some(utc(2025, 10, 3, 0, 0))
+    );
+    assert_eq!(
+        next("@monthly", Tz::UTC, monday),
+        Some(utc(2025, 10, 1, 0, 0))
+    );
+    assert_eq!(next("0 0 30 2 *", Tz::UTC, monday), None);
+}
+
+#[test]
+fn cron_in_a_timezone_across_dst() {
+    let ny: Tz = "America/New_York".parse().unwrap();
+    // 06:00 EDT is 10:00 UTC
+    assert_eq!(
+        next("0 6 * * *", ny, utc(2025, 9, 29, 12, 0)),
+        Some(utc(2025, 9, 30, 10, 0))
+    );
+    // 02:30 doesn't exist on 2025-03-09 in New York; the next one is on the 10th.
+    assert_eq!(
+        next("30 2 * * *", ny, utc(2025, 3, 9, 5, 0)),
+        Some(utc(2025, 3, 10, 6, 30))
+    );
+}
+
+#[test]
+fn cron_rejects_bad_expressions() {
+    for bad in [
+        "* * * *",
+        "60 * * * *",
+        "* 24 * * *",
+        "*/0 * * * *",
+        "* * * foo *",
+    ] {
+        assert!(Cron::parse(bad, Tz::UTC).is_err(), "{bad}");
+    }
+}
+
+#[test]
+fn missed_runs_follow_the_catch_up_policy() {
+    let hourly = || Cron::parse("@hourly", Tz::UTC).unwrap();
+    let now = utc(2025, 9, 29, 12, 30);
+    let three_hours_ago = Some(utc(2025, 9, 29, 9, 30));
+
+    let once = Job::new("j", hourly(), || async { Ok(()) });
+    assert_eq!(once.first_run(three_hours_ago, now), Some(now));
+    // Never ran: wait for the schedule instead of firing at boot.
+    assert_eq!(once.first_run(None, now), Some(utc(2025, 9, 29, 13, 0)));
+    // Ran this hour already: nothing missed.
+    assert_eq!(
+        once.first_run(Some(utc(2025, 9, 29, 12, 0)), now),
+        Some(utc(2025, 9, 29, 13, 0))
+    );
+
+    let skip = Job::new("j", hourly(), || async { Ok(()) }).catch_up(CatchUp::Skip);
+    assert_eq!(
+        skip.first_run(three_hours_ago, now),
+        Some(utc(2025, 9, 29, 13, 0))
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn runs_never_overlap_across_processes() {
+    let db = temp_db();
+    // Two handles stand in for the server and a one-off CLI run.
+    let a = db.spawn();
+    let b = db.spawn();
+    let hour = Duration::from_secs(3600);
+
+    let long = run_exclusive(&a, "recommendations", hour, || async {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        Ok(1)
+    });
+    let second = async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        run_exclusive(&b, "recommendations", hour, || async { Ok(2) }).await
+    };
+    let (first, second) = tokio::join!(long, second);
+    assert_eq!(first.unwrap(), Some(1));
+    assert_eq!(second.unwrap(), None);
+
+    let failed = run_exclusive(&b, "recommendations", hour, || async {
+        anyhow::bail!("openai down");
+        #[allow(unreachable_code)]
+        Ok(())
+    })
+    .await;
+    assert!(failed.is_err());
+    let state = a
+        .read(|s| s.job_state("recommendations"))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(state.last_status.as_deref(), Some("error"));
+    assert_eq!(state.last_error.as_deref(), Some("openai down"));
+    assert_eq!(state.running_since, None);
+}
+
+#[test]
+fn a_dead_run_goes_stale() {
+    let db = temp_db();
+    let store = db.open();
+    let t0 = utc(2025, 9, 29, 12, 0);
+    let limit = Duration::from_secs(3600);
+    assert!(store.try_start_job("scan", t0, limit).unwrap());
+    assert!(!store
+        .try_start_job("scan", t0 + ChronoDuration::minutes(30), limit)
+        .unwrap());
+    assert!(store
+        .try_start_job("scan", t0 + ChronoDuration::minutes(61), limit)
+        .unwrap());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scheduler_runs_jobs_and_persists_state() {
+    let db = temp_db();
+    let store = db.spawn();
+    let runs = Arc::new(AtomicUsize::new(0));
+
+    let mut scheduler = Scheduler::new(store.clone());
+    let counter = runs.clone();
+    scheduler.register(Job::new("tick", Soon, move || {
+        let counter = counter.clone();
+        async move {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }));
+    let tasks = scheduler.spawn();
+    tokio::time::sleep(Duration::from_millis(800)).await;
+    for t in tasks {
+        t.abort();
+    }
+
+    assert!(runs.load(Ordering::SeqCst) >= 2);
+    let state = store.read(|s| s.job_state("tick")).await.unwrap().unwrap();
+    assert_eq!(state.last_status.as_deref(), Some("ok"));
+    assert!(state.last_started_at.is_some());
+    assert!(state.next_run_at.is_some());
+}
+
+#[test]
+fn a_run_left_by_an_exited_process_is_taken_over() {
+    let db = temp_db();
+    let store = db.open();
+    let t0 = utc(2025, 9, 29, 12, 0);
+    let limit = Duration::from_secs(3600);
+    assert!(store.try_start_job("scan", t0, limit).unwrap());
+    let later = t0 + ChronoDuration::minutes(1);
+    // Still this process's run
+    assert!(!store.try_start_job("scan", later, limit).unwrap());
+
+    let conn = rusqlite::Connection::open(&db.path).unwrap();
+    let owner: Option<String> = conn
+        .query_row(
+            "SELECT running_owner FROM jobs WHERE name = 'scan'",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap();
+    let Some(owner) = owner else {
+        // No /proc to tell processes apart: only age counts.
+        return;
+    };
+    // The same host, but a pid past any pid_max, as if the engine had crashed
+    let mut parts: Vec<&str> = owner.split('/').collect();
+    parts[2] = "4194305";
+    conn.execute(
+        "UPDATE jobs SET running_owner = ?1 WHERE name = 'scan'",
+        [parts.join("/")],
+    )
+    .unwrap();
+    assert!(store.try_start_job("scan", later, limit).unwrap());
+}
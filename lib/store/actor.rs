@@ -0,0 +1,98 @@
+use anyhow::{anyhow, Result};
+use std::{path::PathBuf, sync::mpsc, thread};
+use tokio::sync::oneshot;
+
+use super::Store;
+
+/// A unit of work run on the storage thread
+type Command = Box<dyn FnOnce(&mut Store) + Send>;
+
+/// Cheap, cloneable handle to the one thread that owns the store's connection. Every
+/// change goes through its command channel, so a webhook arriving while recommendations
+/// are being generated is queued instead of racing the write.
+#[derive(Debug, Clone)]
+pub struct StoreHandle {
+    tx: mpsc::Sender<Command>,
+}
+
+impl StoreHandle {
+    /// Open the store at `path` on a dedicated thread.
+    pub fn spawn(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let (tx, rx) = mpsc::channel::<Command>();
+        let (ready_tx, ready_rx) = mpsc::channel();
+        thread::Builder::new().name("store".into()).spawn(move || {
+            let mut store = match Store::open(&path) {
+                Ok(s) => {
+                    let _ = ready_tx.send(Ok(()));
+                    s
+                }
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e));
+                    return;
+                }
+            };
+            // Exits once every handle has been dropped.
+            for cmd in rx {
+                cmd(&mut store);
+            }
+        })?;
+        ready_rx
+            .recv()
+            .map_err(|_| anyhow!("store thread exited during open"))??;
+        Ok(Self { tx })
+    }
+
+    fn send<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut Store) -> Result<R> + Send + 'static,
+    ) -> Result<oneshot::Receiver<Result<R>>> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.tx
+            .send(Box::new(move |store: &mut Store| {
+                let _ = reply_tx.send(f(store));
+            }))
+            .map_err(|_| anyhow!("store thread has stopped"))?;
+        Ok(reply_rx)
+    }
+
+    /// Run a change on the storage thread while holding the cross-process write lock.
+    pub async fn write<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut Store) -> Result<R> + Send + 'static,
+    ) -> Result<R> {
+        self.send(|store| store.locked_write(f))?
+            .await
+            .map_err(|_| anyhow!("store thread dropped the reply"))?
+    }
+
+    /// Run queries on the storage thread against one consistent snapshot.
+    pub async fn read<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Store) -> Result<R> + Send + 'static,
+    ) -> Result<R> {
+        self.send(|store| store.snapshot(f))?
+            .await
+            .map_err(|_| anyhow!("store thread dropped the reply"))?
+    }
+
+    /// [`write`](Self::write) for synchronous callers. Don't call from an async task.
+    pub fn write_blocking<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut Store) -> Result<R> + Send + 'static,
+    ) -> Result<R> {
+        self.send(|store| store.locked_write(f))?
+            .blocking_recv()
+            .map_err(|_| anyhow!("store thread dropped the reply"))?
+    }
+
+    /// [`read`](Self::read) for synchronous callers. Don't call from an async task.
+    pub fn read_blocking<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Store) -> Result<R> + Send + 'static,
+    ) -> Result<R> {
+        self.send(|store| store.snapshot(f))?
+            .blocking_recv()
+            .map_err(|_| anyhow!("store thread dropped the reply"))?
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::{Context, Result};
+use rusqlite::params;
+use serde_json::Value;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use super::{insert_event, EventRow, Store};
+
+/// What an NDJSON import did
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ImportReport {
+    pub buckets: usize,
+    pub events: usize,
+    pub recommendations: usize,
+    /// Buckets already in the database (the import is safe to re-run)
+    pub skipped_buckets: usize,
+    /// Lines that weren't JSON objects
+    pub bad_lines: usize,
+}
+
+fn str_field(v: &Value, key: &str) -> Option<String> {
+    v.get(key)
+        .and_then(|v| v.as_str())
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+}
+
+fn id_field(v: &Value, key: &str) -> Option<u32> {
+    let v = v.get(key)?;
+    v.as_u64()
+        .and_then(|n| u32::try_from(n).ok())
+        .or_else(|| v.as_str().and_then(|s| s.trim().parse().ok()))
+}
+
+/// Best-effort typed columns for an event written by any version of the engine.
+pub fn event_row_from_json(v: &Value, default_user: Option<&str>) -> EventRow {
+    EventRow {
+        ts: str_field(v, "ts"),
+        source: str_field(v, "source").unwrap_or_else(|| "tautulli".to_string()),
+        event: str_field(v, "event").unwrap_or_else(|| "watched".to_string()),
+        user: str_field(v, "user").or(default_user.map(String::from)),
+        title: str_field(v, "title").unwrap_or_default(),
+        media_type: str_field(v, "media_type"),
+        tmdb_id: id_field(v, "tmdb_id"),
+        tvdb_id: id_field(v, "tvdb_id"),
+        show_title: str_field(v, "show_title"),
+        data: v.to_string(),
+    }
+}
+
+impl Store {
+    /// One-shot import of the engine's old NDJSON store (one bucket per line, with its
+    /// `events` and `recommendations`). Buckets that already exist are left alone.
+    pub fn import_ndjson(&mut self, path: &Path) -> Result<ImportReport> {
+        let f = File::open(path).with_context(|| format!("open {}", path.display()))?;
+        let mut report = ImportReport::default();
+        let tx = self
+            .conn
+            .transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+        for line in BufReader::new(f).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let Ok(b) = serde_json::from_str::<Value>(&line) else {
+                report.bad_lines += 1;
+                continue;
+            };
+            let Some(tag) = str_field(&b, "bucket") else {
+                report.bad_lines += 1;
+                continue;
+            };
+            let user = str_field(&b, "user");
+            let inserted = tx.execute(
+                "INSERT INTO buckets (tag, user, started_at, updated_at, recommendations_generated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5) ON CONFLICT (tag, user) DO NOTHING",
+                params![
+                    tag,
+                    user.as_deref().unwrap_or(""),
+                    str_field(&b, "started_at"),
+                    str_field(&b, "updated_at"),
+                    str_field(&b, "recommendations_generated_at"),
+                ],
+            )?;
+            if inserted == 0 {
+                report.skipped_buckets += 1;
+                continue;
+            }
+            let bucket_id = tx.last_insert_rowid();
+            report.buckets += 1;
+
+            for ev in b
+                .get("events")
+                .and_then(|e| e.as_array())
+                .into_iter()
+                .flatten()
+            {
+                insert_event(
+                    &tx,
+                    Some(bucket_id),
+                    &event_row_from_json(ev, user.as_deref()),
+                )?;
+                report.events += 1;
+            }
+            let recs = b.get("recommendations").and_then(|r| r.as_array());
+            for (i, r) in recs.into_iter().flatten().enumerate() {
+                let Some(tmdb_id) = id_field(r, "tmdb_id") else {
+                    continue;
+                };
+                tx.execute(
+                    "INSERT INTO recommendations (bucket_id, position, tmdb_id, media_type)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![
+                        bucket_id,
+                        i as i64,
+                        tmdb_id,
+                        str_field(r, "media_type").unwrap_or_else(|| "movie".to_string())
+                    ],
+                )?;
+                report.recommendations += 1;
+            }
+        }
+        tx.commit()?;
+        Ok(report)
+    }
+
+    /// Import an analytics NDJSON file (one event per line). Not idempotent: run it once.
+    pub fn import_analytics_ndjson(&mut self, path: &Path) -> Result<ImportReport> {
+        let f = File::open(path).with_context(|| format!("open {}", path.display()))?;
+        let mut report = ImportReport::default();
+        let tx = self
+            .conn
+            .transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+        for line in BufReader::new(f).lines() {
+            let line = line?;
+            match serde_json::from_str::<Value>(&line) {
+                Ok(v) if v.is_object() => {
+                    insert_event(&tx, None, &event_row_from_json(&v, None))?;
+                    report.events += 1;
+                }
+                _ => report.bad_lines += 1,
+            }
+        }
+        tx.commit()?;
+        Ok(report)
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, Utc};
+use once_cell::sync::Lazy;
+use rusqlite::{params, OptionalExtension, Row};
+use std::time::Duration;
+
+use super::Store;
+
+/// What the scheduler remembers about a job between restarts
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JobState {
+    pub name: String,
+    pub last_started_at: Option<DateTime<Utc>>,
+    pub last_finished_at: Option<DateTime<Utc>>,
+    /// "ok" or "error"
+    pub last_status: Option<String>,
+    pub last_error: Option<String>,
+    pub next_run_at: Option<DateTime<Utc>>,
+    /// Set while a run is in progress
+    pub running_since: Option<DateTime<Utc>>,
+}
+
+fn ts(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn parse_ts(s: Option<String>) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(&s?)
+        .ok()
+        .map(|d| d.with_timezone(&Utc))
+}
+
+fn read_proc(path: &str) -> Option<String> {
+    let s = std::fs::read_to_string(path).ok()?;
+    let s = s.trim();
+    (!s.is_empty()).then(|| s.to_string())
+}
+
+/// When process `pid` started, in clock ticks since boot; `None` if there's no such process.
+fn start_time(pid: &str) -> Option<String> {
+    let stat = read_proc(&format!("/proc/{pid}/stat"))?;
+    // The command name is parenthesised and may hold spaces; `starttime` is the 22nd field.
+    stat.rsplit_once(')')?
+        .1
+        .split_whitespace()
+        .nth(19)
+        .map(String::from)
+}
+
+fn host_and_boot() -> Option<(String, String)> {
+    Some((
+        read_proc("/proc/sys/kernel/hostname")?,
+        read_proc("/proc/sys/kernel/random/boot_id")?,
+    ))
+}
+
+/// This process as a job's `running_owner`. `None` where `/proc` can't tell.
+static OWNER: Lazy<Option<String>> = Lazy::new(|| {
+    let (host, boot) = host_and_boot()?;
+    let pid = std::process::id().to_string();
+    let start = start_time(&pid)?;
+    Some(format!("{host}/{boot}/{pid}/{start}"))
+});
+
+/// Whether the process that recorded `owner` has certainly gone: it ran on this host (or
+/// container) but in an earlier boot, or its pid is now free or reused. Owners elsewhere
+/// can't be checked, and their runs only go stale with age.
+fn owner_gone(owner: &str) -> bool {
+    let Some((host, boot)) = host_and_boot() else {
+        return false;
+    };
+    let parts: Vec<&str> = owner.split('/').collect();
+    let [owner_host, owner_boot, pid, start] = parts[..] else {
+        return false;
+    };
+    owner_host == host && (owner_boot != boot || start_time(pid).as_deref() != Some(start))
+}
+
+const JOB_COLUMNS: &str = "name, last_started_at, last_finished_at, last_status, last_error, \
+     next_run_at, running_since";
+
+fn job_from_row(r: &Row) -> rusqlite::Result<JobState> {
+    Ok(JobState {
+        name: r.get(0)?,
+        last_started_at: parse_ts(r.get(1)?),
+        last_finished_at: parse_ts(r.get(2)?),
+        last_status: r.get(3)?,
+        last_error: r.get(4)?,
+        next_run_at: parse_ts(r.get(5)?),
+        running_since: parse_ts(r.get(6)?),
+    })
+}
+
+impl Store {
+    pub fn job_state(&self, name: &str) -> Result<Option<JobState>> {
+        Ok(self
+            .conn
+            .query_row(
+                &format!("SELECT {JOB_COLUMNS} FROM jobs WHERE name = ?1"),
+                [name],
+                job_from_row,
+            )
+            .optional()?)
+    }
+
+    pub fn jobs(&self) -> Result<Vec<JobState>> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!("SELECT {JOB_COLUMNS} FROM jobs ORDER BY name"))?;
+        let rows = stmt.query_map([], job_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn set_job_next_run(&self, name: &str, at: Option<DateTime<Utc>>) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO jobs (name, next_run_at) VALUES (?1, ?2)
+             ON CONFLICT (name) DO UPDATE SET next_run_at = excluded.next_run_at",
+            params![name, at.map(ts)],
+        )?;
+        Ok(())
+    }
+
+    /// Mark `name` as running unless a run started less than `stale_after` ago is still
+    /// going (here or in another process). A run whose process is known to have died, say
+    /// in a crash before a restart, doesn't wait out `stale_after`. Returns whether this
+    /// caller may run it.
+    pub fn try_start_job(
+        &self,
+        name: &str,
+        now: DateTime<Utc>,
+        stale_after: Duration,
+    ) -> Result<bool> {
+        let (since, owner): (Option<String>, Option<String>) = self
+            .conn
+            .query_row(
+                "SELECT running_since, running_owner FROM jobs WHERE name = ?1",
+                [name],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .optional()?
+            .unwrap_or_default();
+        if let Some(since) = parse_ts(since) {
+            let age = (now - since).to_std().unwrap_or_default();
+            if owner.as_deref().is_some_and(owner_gone) {
+                log::warn!(
+                    "Job {name} was left running since {since} by a process that has exited"
+                );
+            } else if age < stale_after {
+                return Ok(false);
+            } else {
+                log::warn!("Job {name} has been marked running since {since}; assuming it died");
+            }
+        }
+        self.conn.execute(
+            "INSERT INTO jobs (name, last_started_at, running_since, running_owner)
+             VALUES (?1, ?2, ?2, ?3)
+             ON CONFLICT (name) DO UPDATE SET
+                 last_started_at = excluded.last_started_at,
+                 running_since = excluded.running_since,
+                 running_owner = excluded.running_owner",
+            params![name, ts(now), *OWNER],
+        )?;
+        Ok(true)
+    }
+
+    /// Record the end of a run started with [`Store::try_start_job`].
+    pub fn finish_job(&self, name: &str, now: DateTime<Utc>, error: Option<&str>) -> Result<()> {
+        self.conn.execute(
+            "UPDATE jobs SET running_since = NULL, running_owner = NULL, last_finished_at = ?2,
+                 last_status = ?3, last_error = ?4
+             WHERE name = ?1",
+            params![
+                name,
+                ts(now),
+                if error.is_some() { "error" } else { "ok" },
+                error
+            ],
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+use anyhow::{Context, Result};
+use fs4::fs_std::FileExt;
+use std::{
+    fs::{File, OpenOptions},
+    path::{Path, PathBuf},
+};
+
+/// Advisory `<db>.lock` file shared by every process using a store: writers take it
+/// exclusively for each change, readers take it shared for a consistent page of reads.
+#[derive(Debug)]
+pub struct StoreLock {
+    file: File,
+}
+
+/// Held lock; released on drop.
+#[derive(Debug)]
+pub struct LockGuard {
+    file: File,
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+pub fn lock_path(db_path: &Path) -> PathBuf {
+    let mut name = db_path.as_os_str().to_owned();
+    name.push(".lock");
+    PathBuf::from(name)
+}
+
+impl StoreLock {
+    pub fn open(db_path: &Path) -> Result<Self> {
+        let path = lock_path(db_path);
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("open {}", path.display()))?;
+        Ok(Self { file })
+    }
+
+    pub fn exclusive(&self) -> Result<LockGuard> {
+        FileExt::lock_exclusive(&self.file)?;
+        // The clone shares the lock, so the guard can unlock without borrowing `self`.
+        Ok(LockGuard {
+            file: self.file.try_clone()?,
+        })
+    }
+
+    pub fn shared(&self) -> Result<LockGuard> {
+        FileExt::lock_shared(&self.file)?;
+        Ok(LockGuard {
+            file: self.file.try_clone()?,
+        })
+    }
+
+    /// `None` if another process holds it exclusively.
+    pub fn try_shared(&self) -> Result<Option<LockGuard>> {
+        if !FileExt::try_lock_shared(&self.file)? {
+            return Ok(None);
+        }
+        Ok(Some(LockGuard {
+            file: self.file.try_clone()?,
+        }))
+    }
+}
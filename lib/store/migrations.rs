@@ -0,0 +1,150 @@
+use anyhow::Result;
+use rusqlite::Connection;
+
+/// Schema history, applied in order. `PRAGMA user_version` records how many have run,
+/// so only ever append here — never edit a migration that has shipped.
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema
+    r#"
+    CREATE TABLE buckets (
+        id INTEGER PRIMARY KEY,
+        tag TEXT NOT NULL,
+        -- '' is the shared bucket (events without a user)
+        user TEXT NOT NULL DEFAULT '',
+        started_at TEXT,
+        updated_at TEXT,
+        recommendations_generated_at TEXT,
+        UNIQUE (tag, user)
+    );
+
+    CREATE TABLE events (
+        id INTEGER PRIMARY KEY,
+        -- NULL for analytics-only events (plays, pauses, abandoned stops…)
+        bucket_id INTEGER REFERENCES buckets(id) ON DELETE CASCADE,
+        ts TEXT,
+        source TEXT NOT NULL,
+        event TEXT NOT NULL,
+        user TEXT,
+        title TEXT NOT NULL DEFAULT '',
+        media_type TEXT,
+        tmdb_id INTEGER,
+        tvdb_id INTEGER,
+        show_title TEXT,
+        -- the full normalized event as JSON
+        data TEXT NOT NULL
+    );
+    CREATE INDEX events_bucket ON events(bucket_id);
+    CREATE INDEX events_user_tmdb ON events(user, tmdb_id);
+
+    CREATE TABLE recommendations (
+        bucket_id INTEGER NOT NULL REFERENCES buckets(id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        tmdb_id INTEGER NOT NULL,
+        media_type TEXT NOT NULL DEFAULT 'movie',
+        PRIMARY KEY (bucket_id, position)
+    );
+
+    CREATE TABLE enrichment_cache (
+        kind TEXT NOT NULL,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        fetched_at INTEGER NOT NULL,
+        PRIMARY KEY (kind, key)
+    );
+    "#,
+    // 2: background job bookkeeping for lib::scheduler
+    r#"
+    CREATE TABLE jobs (
+        name TEXT PRIMARY KEY,
+        last_started_at TEXT,
+        last_finished_at TEXT,
+        -- 'ok' or 'error'
+        last_status TEXT,
+        last_error TEXT,
+        next_run_at TEXT,
+        -- set while a run is in progress in any process
+        running_since TEXT
+    );
+    "#,
+    // 3: index of buckets moved out to the gzipped monthly archive files
+    r#"
+    CREATE TABLE archived_buckets (
+        tag TEXT NOT NULL,
+        user TEXT NOT NULL DEFAULT '',
+        -- "YYYY-MM" of started_at
+        month TEXT NOT NULL,
+        -- archive file name, relative to the archive directory
+        file TEXT NOT NULL,
+        started_at TEXT,
+        events INTEGER NOT NULL,
+        recommendations INTEGER NOT NULL,
+        archived_at TEXT NOT NULL,
+        PRIMARY KEY (tag, user)
+    );
+    CREATE INDEX events_ts ON events(ts);
+    CREATE INDEX buckets_started_at ON buckets(started_at);
+    "#,
+    // 4: what the engine dropped while generating a bucket's recommendations (JSON)
+    r#"
+    ALTER TABLE buckets ADD COLUMN recommendation_report TEXT;
+    "#,
+    // 5: match confidence for recommendations resolved from a title/year
+    r#"
+    ALTER TABLE recommendations ADD COLUMN confidence REAL;
+    "#,
+    // 6: titles watched in archived buckets, so exclusions outlive the raw events
+    r#"
+    CREATE TABLE archived_watches (
+        -- "movie" or "tv"
+        media_type TEXT NOT NULL,
+        tmdb_id INTEGER NOT NULL,
+        PRIMARY KEY (media_type, tmdb_id)
+    );
+    "#,
+    // 7: which recommender produced a recommendation ("llm" or "tmdb")
+    r#"
+    ALTER TABLE recommendations ADD COLUMN source TEXT;
+    "#,
+    // 8: why a title was recommended, and the bucket's watches (JSON tmdb id array) it follows from
+    r#"
+    ALTER TABLE recommendations ADD COLUMN reason TEXT;
+    ALTER TABLE recommendations ADD COLUMN because_of TEXT;
+    "#,
+    // 9: what each user thought of a title; kept when buckets are archived
+    r#"
+    CREATE TABLE feedback (
+        -- '' for the shared buckets
+        user TEXT NOT NULL DEFAULT '',
+        -- "movie" or "tv"
+        media_type TEXT NOT NULL,
+        tmdb_id INTEGER NOT NULL,
+        -- "like", "dislike", "not_interested" or "seen"
+        kind TEXT NOT NULL,
+        -- the recommender that last suggested it to the user, if any
+        source TEXT,
+        created_at TEXT NOT NULL,
+        PRIMARY KEY (user, media_type, tmdb_id)
+    );
+    "#,
+    // 10: the process running a job, "<hostname>/<boot id>/<pid>/<start time>" (Linux only)
+    r#"
+    ALTER TABLE jobs ADD COLUMN running_owner TEXT;
+    "#,
+];
+
+/// Bring `conn` up to the latest schema. Safe to call from several processes at once.
+pub fn migrate(conn: &mut Connection) -> Result<()> {
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+    let version: usize = tx.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+    for (i, sql) in MIGRATIONS.iter().enumerate().skip(version) {
+        tx.execute_batch(sql)?;
+        tx.pragma_update(None, "user_version", i + 1)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Number of migrations this build knows about
+pub fn latest_version() -> usize {
+    MIGRATIONS.len()
+}
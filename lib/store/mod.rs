@@ -0,0 +1,558 @@
+use anyhow::Result;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+use std::{path::Path, time::Duration};
+
+pub mod actor;
+pub mod import;
+pub mod jobs;
+pub mod lock;
+pub mod migrations;
+pub mod retention;
+
+pub use actor::StoreHandle;
+use lock::StoreLock;
+
+/// One (bucket tag, user) window of completed watches
+#[derive(Debug, Clone, Serialize)]
+pub struct Bucket {
+    pub id: i64,
+    /// e.g. "2025-09-29T12Z"; the format depends on the engine's bucketing strategy
+    pub tag: String,
+    /// `None` for the shared bucket
+    pub user: Option<String>,
+    pub started_at: Option<String>,
+    pub updated_at: Option<String>,
+    /// Number of events in the bucket
+    pub count: usize,
+    pub recommendations_generated_at: Option<String>,
+}
+
+/// A user's verdict on a title, usually one that was recommended to them
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Feedback {
+    /// `None` for the shared buckets
+    pub user: Option<String>,
+    /// "movie" or "tv"
+    pub media_type: String,
+    pub tmdb_id: u32,
+    /// "like", "dislike", "not_interested" or "seen"
+    pub kind: String,
+    /// The recommender that last suggested it to the user, if any
+    pub source: Option<String>,
+    pub created_at: String,
+}
+
+/// The columns we query on, plus the full event as JSON in `data`
+#[derive(Debug, Clone, Default)]
+pub struct EventRow {
+    pub ts: Option<String>,
+    pub source: String,
+    pub event: String,
+    pub user: Option<String>,
+    pub title: String,
+    pub media_type: Option<String>,
+    pub tmdb_id: Option<u32>,
+    pub tvdb_id: Option<u32>,
+    pub show_title: Option<String>,
+    pub data: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct StoredEvent {
+    pub id: i64,
+    pub bucket_id: Option<i64>,
+    pub row: EventRow,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Recommendation {
+    pub tmdb_id: u32,
+    /// "movie" or "tv"
+    pub media_type: String,
+    /// How well a title the LLM named matched on TMDB (0-1); `None` if it gave the id
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+    /// The recommender that produced it: "llm" or "tmdb"; `None` for older rows
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// One short sentence on why it fits
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Tmdb ids of the bucket's watches it follows from
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub because_of: Vec<u32>,
+}
+
+/// SQLite-backed watch history shared by the recommendation engine and the dashboard.
+/// WAL mode lets the dashboard read while the engine writes; multi-row changes are
+/// transactional, and [`Store::snapshot`] keeps a page of reads consistent.
+///
+/// In a long-running process, go through a [`StoreHandle`] so writes are serialized.
+pub struct Store {
+    conn: Connection,
+    lock: StoreLock,
+}
+
+/// An event's `media_type` folded to what a TMDB id points at
+const WATCHED_MEDIA_TYPE: &str =
+    "CASE WHEN media_type IN ('episode', 'show', 'season') THEN 'tv' ELSE 'movie' END";
+
+const BUCKET_COLUMNS: &str = "b.id, b.tag, b.user, b.started_at, b.updated_at, \
+     b.recommendations_generated_at, (SELECT COUNT(*) FROM events e WHERE e.bucket_id = b.id)";
+
+fn bucket_from_row(r: &Row) -> rusqlite::Result<Bucket> {
+    let user: String = r.get(2)?;
+    Ok(Bucket {
+        id: r.get(0)?,
+        tag: r.get(1)?,
+        user: Some(user).filter(|u| !u.is_empty()),
+        started_at: r.get(3)?,
+        updated_at: r.get(4)?,
+        recommendations_generated_at: r.get(5)?,
+        count: r.get::<_, i64>(6)? as usize,
+    })
+}
+
+const FEEDBACK_COLUMNS: &str = "user, media_type, tmdb_id, kind, source, created_at";
+
+fn feedback_from_row(r: &Row) -> rusqlite::Result<Feedback> {
+    let user: String = r.get(0)?;
+    Ok(Feedback {
+        user: Some(user).filter(|u| !u.is_empty()),
+        media_type: r.get(1)?,
+        tmdb_id: r.get(2)?,
+        kind: r.get(3)?,
+        source: r.get(4)?,
+        created_at: r.get(5)?,
+    })
+}
+
+fn event_from_row(r: &Row) -> rusqlite::Result<StoredEvent> {
+    Ok(StoredEvent {
+        id: r.get(0)?,
+        bucket_id: r.get(1)?,
+        row: EventRow {
+            ts: r.get(2)?,
+            source: r.get(3)?,
+            event: r.get(4)?,
+            user: r.get(5)?,
+            title: r.get(6)?,
+            media_type: r.get(7)?,
+            tmdb_id: r.get(8)?,
+            tvdb_id: r.get(9)?,
+            show_title: r.get(10)?,
+            data: r.get(11)?,
+        },
+    })
+}
+
+fn insert_event(conn: &Connection, bucket_id: Option<i64>, ev: &EventRow) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO events (bucket_id, ts, source, event, user, title, media_type, tmdb_id, \
+         tvdb_id, show_title, data) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            bucket_id,
+            ev.ts,
+            ev.source,
+            ev.event,
+            ev.user,
+            ev.title,
+            ev.media_type,
+            ev.tmdb_id,
+            ev.tvdb_id,
+            ev.show_title,
+            ev.data
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+fn now_unix() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+impl Store {
+    /// `DB_PATH`, or for older setups `NDJSON_PATH` with a `.sqlite3` extension.
+    pub fn path_from_env() -> Result<String> {
+        if let Ok(path) = std::env::var("DB_PATH") {
+            return Ok(path);
+        }
+        let ndjson = std::env::var("NDJSON_PATH")
+            .map_err(|_| anyhow::anyhow!("DB_PATH or NDJSON_PATH must be set"))?;
+        Ok(Path::new(&ndjson)
+            .with_extension("sqlite3")
+            .to_string_lossy()
+            .into_owned())
+    }
+
+    /// Open (creating if needed) and migrate the database at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut conn = Connection::open(path)?;
+        // Another process may hold the write lock briefly; wait rather than fail.
+        conn.busy_timeout(Duration::from_secs(5))?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+        let lock = StoreLock::open(path)?;
+        {
+            let _guard = lock.exclusive()?;
+            migrations::migrate(&mut conn)?;
+        }
+        Ok(Self { conn, lock })
+    }
+
+    /// Run `f` holding the cross-process write lock, so no reader's snapshot or other
+    /// process's change interleaves with it.
+    pub fn locked_write<R>(&mut self, f: impl FnOnce(&mut Self) -> Result<R>) -> Result<R> {
+        let _guard = self.lock.exclusive()?;
+        f(self)
+    }
+
+    /// Run several queries against one point-in-time view (shared lock + read transaction).
+    pub fn snapshot<R>(&self, f: impl FnOnce(&Self) -> Result<R>) -> Result<R> {
+        let _guard = self.lock.shared()?;
+        self.conn.execute_batch("BEGIN DEFERRED")?;
+        let out = f(self);
+        self.conn.execute_batch("COMMIT")?;
+        out
+    }
+
+    /// Add a completed watch to the (tag, user) bucket, creating the bucket if needed.
+    /// Returns the bucket id.
+    pub fn append_event(
+        &mut self,
+        bucket_tag: &str,
+        user: Option<&str>,
+        now: &str,
+        ev: &EventRow,
+    ) -> Result<i64> {
+        let tx = self
+            .conn
+            .transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+        tx.execute(
+            "INSERT INTO buckets (tag, user, started_at, updated_at) VALUES (?1, ?2, ?3, ?3)
+             ON CONFLICT (tag, user) DO UPDATE SET updated_at = excluded.updated_at",
+            params![bucket_tag, user.unwrap_or(""), now],
+        )?;
+        let bucket_id: i64 = tx.query_row(
+            "SELECT id FROM buckets WHERE tag = ?1 AND user = ?2",
+            params![bucket_tag, user.unwrap_or("")],
+            |r| r.get(0),
+        )?;
+        insert_event(&tx, Some(bucket_id), ev)?;
+        tx.commit()?;
+        Ok(bucket_id)
+    }
+
+    /// Keep an event that doesn't feed recommendations (analytics only).
+    pub fn record_event(&self, ev: &EventRow) -> Result<i64> {
+        insert_event(&self.conn, None, ev)
+    }
+
+    pub fn bucket(&self, bucket_tag: &str, user: Option<&str>) -> Result<Option<Bucket>> {
+        Ok(self
+            .conn
+            .query_row(
+                &format!("SELECT {BUCKET_COLUMNS} FROM buckets b WHERE b.tag = ?1 AND b.user = ?2"),
+                params![bucket_tag, user.unwrap_or("")],
+                bucket_from_row,
+            )
+            .optional()?)
+    }
+
+    pub fn bucket_by_id(&self, bucket_id: i64) -> Result<Option<Bucket>> {
+        Ok(self
+            .conn
+            .query_row(
+                &format!("SELECT {BUCKET_COLUMNS} FROM buckets b WHERE b.id = ?1"),
+                [bucket_id],
+                bucket_from_row,
+            )
+            .optional()?)
+    }
+
+    /// Newest buckets first, optionally for one user. Ordered by first watch rather than
+    /// tag, since tags from different bucketing strategies don't sort together.
+    pub fn buckets(&self, user: Option<&str>, limit: usize) -> Result<Vec<Bucket>> {
+        self.buckets_page(user, limit, 0)
+    }
+
+    /// [`Store::buckets`], skipping the first `offset`.
+    pub fn buckets_page(
+        &self,
+        user: Option<&str>,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<Bucket>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {BUCKET_COLUMNS} FROM buckets b
+             WHERE ?1 IS NULL OR b.user = ?1
+             ORDER BY b.started_at DESC, b.id DESC LIMIT ?2 OFFSET ?3"
+        ))?;
+        let rows = stmt.query_map(params![user, limit as i64, offset as i64], bucket_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn count_buckets(&self, user: Option<&str>) -> Result<usize> {
+        let n: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM buckets WHERE ?1 IS NULL OR user = ?1",
+            [user],
+            |r| r.get(0),
+        )?;
+        Ok(n as usize)
+    }
+
+    /// Remove a bucket with its events and recommendations. Returns whether it existed.
+    pub fn delete_bucket(&self, bucket_id: i64) -> Result<bool> {
+        Ok(self
+            .conn
+            .execute("DELETE FROM buckets WHERE id = ?1", [bucket_id])?
+            > 0)
+    }
+
+    /// Remove one event (bucketed or analytics). Returns whether it existed.
+    pub fn delete_event(&self, event_id: i64) -> Result<bool> {
+        Ok(self
+            .conn
+            .execute("DELETE FROM events WHERE id = ?1", [event_id])?
+            > 0)
+    }
+
+    /// Every bucket, oldest first.
+    pub fn all_buckets(&self) -> Result<Vec<Bucket>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {BUCKET_COLUMNS} FROM buckets b ORDER BY b.id"
+        ))?;
+        let rows = stmt.query_map([], bucket_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Distinct named users that have buckets
+    pub fn users(&self) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT DISTINCT user FROM buckets WHERE user != '' ORDER BY user")?;
+        let rows = stmt.query_map([], |r| r.get(0))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// The user's most recently created bucket.
+    pub fn latest_bucket(&self, user: Option<&str>) -> Result<Option<Bucket>> {
+        Ok(self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT {BUCKET_COLUMNS} FROM buckets b WHERE b.user = ?1
+                     ORDER BY b.id DESC LIMIT 1"
+                ),
+                [user.unwrap_or("")],
+                bucket_from_row,
+            )
+            .optional()?)
+    }
+
+    /// Every bucket that has no recommendations yet, oldest first.
+    pub fn unprocessed_buckets(&self) -> Result<Vec<Bucket>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {BUCKET_COLUMNS} FROM buckets b
+             WHERE b.recommendations_generated_at IS NULL ORDER BY b.id"
+        ))?;
+        let rows = stmt.query_map([], bucket_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn bucket_events(&self, bucket_id: i64) -> Result<Vec<StoredEvent>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, bucket_id, ts, source, event, user, title, media_type, tmdb_id, tvdb_id, \
+             show_title, data FROM events WHERE bucket_id = ?1 ORDER BY id",
+        )?;
+        let rows = stmt.query_map([bucket_id], event_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Every completed watch still in the database for one user (`None`: the shared
+    /// buckets), oldest first. Events without a time get their bucket's start.
+    pub fn watch_history(&self, user: Option<&str>) -> Result<Vec<StoredEvent>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT e.id, e.bucket_id, COALESCE(e.ts, b.started_at), e.source, e.event, e.user, \
+             e.title, e.media_type, e.tmdb_id, e.tvdb_id, e.show_title, e.data \
+             FROM events e JOIN buckets b ON b.id = e.bucket_id \
+             WHERE b.user = ?1 ORDER BY COALESCE(e.ts, b.started_at), e.id",
+        )?;
+        let rows = stmt.query_map([user.unwrap_or("")], event_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Every title anyone has watched, as ("movie" | "tv", tmdb id): completed watches
+    /// still in the database plus those recorded when their buckets were archived.
+    pub fn watched_titles(&self) -> Result<Vec<(String, u32)>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {WATCHED_MEDIA_TYPE}, tmdb_id FROM events \
+             WHERE bucket_id IS NOT NULL AND tmdb_id IS NOT NULL \
+             UNION SELECT media_type, tmdb_id FROM archived_watches ORDER BY 1, 2"
+        ))?;
+        let rows = stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn recommendations(&self, bucket_id: i64) -> Result<Vec<Recommendation>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT tmdb_id, media_type, confidence, source, reason, because_of FROM recommendations WHERE bucket_id = ?1 \
+             ORDER BY position",
+        )?;
+        let rows = stmt.query_map([bucket_id], |r| {
+            Ok(Recommendation {
+                tmdb_id: r.get(0)?,
+                media_type: r.get(1)?,
+                confidence: r.get(2)?,
+                source: r.get(3)?,
+                reason: r.get(4)?,
+                because_of: r
+                    .get::<_, Option<String>>(5)?
+                    .and_then(|s| serde_json::from_str(&s).ok())
+                    .unwrap_or_default(),
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Replace a bucket's recommendations and stamp `recommendations_generated_at`.
+    pub fn set_recommendations(
+        &mut self,
+        bucket_id: i64,
+        recs: &[Recommendation],
+        generated_at: &str,
+    ) -> Result<()> {
+        let tx = self
+            .conn
+            .transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+        tx.execute(
+            "DELETE FROM recommendations WHERE bucket_id = ?1",
+            [bucket_id],
+        )?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO recommendations \
+                 (bucket_id, position, tmdb_id, media_type, confidence, source, reason, \
+                 because_of) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )?;
+            for (i, r) in recs.iter().enumerate() {
+                stmt.execute(params![
+                    bucket_id,
+                    i as i64,
+                    r.tmdb_id,
+                    r.media_type,
+                    r.confidence,
+                    r.source,
+                    r.reason,
+                    (!r.because_of.is_empty())
+                        .then(|| serde_json::to_string(&r.because_of))
+                        .transpose()?
+                ])?;
+            }
+        }
+        tx.execute(
+            "UPDATE buckets SET recommendations_generated_at = ?2 WHERE id = ?1",
+            params![bucket_id, generated_at],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Attach the engine's report on a generation run (JSON) to a bucket.
+    pub fn set_recommendation_report(&self, bucket_id: i64, report: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE buckets SET recommendation_report = ?2 WHERE id = ?1",
+            params![bucket_id, report],
+        )?;
+        Ok(())
+    }
+
+    pub fn recommendation_report(&self, bucket_id: i64) -> Result<Option<String>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT recommendation_report FROM buckets WHERE id = ?1",
+                [bucket_id],
+                |r| r.get(0),
+            )
+            .optional()?
+            .flatten())
+    }
+
+    /// Record a user's verdict on a title, replacing any earlier one. The recommender
+    /// that most recently suggested it to them is noted alongside.
+    pub fn put_feedback(
+        &self,
+        user: Option<&str>,
+        media_type: &str,
+        tmdb_id: u32,
+        kind: &str,
+        created_at: &str,
+    ) -> Result<Feedback> {
+        let user = user.unwrap_or("");
+        self.conn.execute(
+            "INSERT INTO feedback (user, media_type, tmdb_id, kind, source, created_at) \
+             VALUES (?1, ?2, ?3, ?4, (SELECT r.source FROM recommendations r \
+             JOIN buckets b ON b.id = r.bucket_id \
+             WHERE b.user = ?1 AND r.media_type = ?2 AND r.tmdb_id = ?3 \
+             ORDER BY b.recommendations_generated_at DESC, b.id DESC LIMIT 1), ?5) \
+             ON CONFLICT (user, media_type, tmdb_id) DO UPDATE SET kind = excluded.kind, \
+             source = excluded.source, created_at = excluded.created_at",
+            params![user, media_type, tmdb_id, kind, created_at],
+        )?;
+        Ok(self.conn.query_row(
+            &format!(
+                "SELECT {FEEDBACK_COLUMNS} FROM feedback \
+                 WHERE user = ?1 AND media_type = ?2 AND tmdb_id = ?3"
+            ),
+            params![user, media_type, tmdb_id],
+            feedback_from_row,
+        )?)
+    }
+
+    /// One user's feedback (`None`: the shared buckets'), newest first
+    pub fn feedback(&self, user: Option<&str>) -> Result<Vec<Feedback>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {FEEDBACK_COLUMNS} FROM feedback WHERE user = ?1 \
+             ORDER BY created_at DESC, rowid DESC"
+        ))?;
+        let rows = stmt.query_map([user.unwrap_or("")], feedback_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Everyone's feedback, newest first
+    pub fn all_feedback(&self) -> Result<Vec<Feedback>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {FEEDBACK_COLUMNS} FROM feedback ORDER BY created_at DESC, rowid DESC"
+        ))?;
+        let rows = stmt.query_map([], feedback_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Cached enrichment (e.g. a TMDB response) no older than `max_age`.
+    pub fn cache_get(&self, kind: &str, key: &str, max_age: Duration) -> Result<Option<String>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT value FROM enrichment_cache WHERE kind = ?1 AND key = ?2 AND fetched_at >= ?3",
+                params![kind, key, now_unix() - max_age.as_secs() as i64],
+                |r| r.get(0),
+            )
+            .optional()?)
+    }
+
+    pub fn cache_put(&self, kind: &str, key: &str, value: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO enrichment_cache (kind, key, value, fetched_at) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (kind, key) DO UPDATE SET value = excluded.value, fetched_at = excluded.fetched_at",
+            params![kind, key, value, now_unix()],
+        )?;
+        Ok(())
+    }
+}
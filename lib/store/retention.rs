@@ -0,0 +1,330 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration as ChronoDuration, Months, SecondsFormat, Utc};
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use super::{Bucket, Store};
+
+/// How long full detail is kept. `None` keeps it forever.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// Blank each event's raw webhook body once it is this many days old
+    pub raw_days: Option<u32>,
+    /// Move buckets this many months old into `archive_dir`
+    pub archive_months: Option<u32>,
+    /// Holds one `YYYY-MM.ndjson.gz` per month
+    pub archive_dir: PathBuf,
+}
+
+impl RetentionPolicy {
+    /// Reads `RETENTION_RAW_DAYS`, `RETENTION_ARCHIVE_MONTHS` and `ARCHIVE_DIR` (default:
+    /// `archive/` next to the database).
+    pub fn from_env(db_path: &Path) -> Self {
+        let num = |k: &str| {
+            std::env::var(k)
+                .ok()
+                .filter(|v| !v.trim().is_empty())
+                .and_then(|v| match v.trim().parse::<u32>() {
+                    Ok(n) => Some(n),
+                    Err(_) => {
+                        log::error!("Ignoring {k}: {v:?} is not a number");
+                        None
+                    }
+                })
+        };
+        let archive_dir = std::env::var("ARCHIVE_DIR")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| {
+                db_path
+                    .parent()
+                    .unwrap_or_else(|| Path::new("."))
+                    .join("archive")
+            });
+        Self {
+            raw_days: num("RETENTION_RAW_DAYS"),
+            archive_months: num("RETENTION_ARCHIVE_MONTHS"),
+            archive_dir,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.raw_days.is_some() || self.archive_months.is_some()
+    }
+}
+
+/// What a retention pass did
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct RetentionReport {
+    pub raw_stripped: usize,
+    pub buckets_archived: usize,
+    pub events_archived: usize,
+    /// Archive files written to, by name
+    pub files: Vec<String>,
+}
+
+/// A bucket that now lives in an archive file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ArchivedBucket {
+    pub tag: String,
+    pub user: Option<String>,
+    pub month: String,
+    pub file: String,
+    pub started_at: Option<String>,
+    pub events: usize,
+    pub recommendations: usize,
+    pub archived_at: String,
+}
+
+fn ts(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// "YYYY-MM" a bucket is filed under: when it started, else a date-looking tag.
+fn month_of(b: &Bucket) -> String {
+    let is_month = |s: &str| {
+        s.len() >= 7
+            && s.as_bytes()[4] == b'-'
+            && s[..4].bytes().all(|c| c.is_ascii_digit())
+            && s[5..7].bytes().all(|c| c.is_ascii_digit())
+    };
+    [b.started_at.as_deref(), Some(b.tag.as_str())]
+        .into_iter()
+        .flatten()
+        .find(|s| is_month(s))
+        .map(|s| s[..7].to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+const ARCHIVE_COLUMNS: &str =
+    "tag, user, month, file, started_at, events, recommendations, archived_at";
+
+fn archived_from_row(r: &rusqlite::Row) -> rusqlite::Result<ArchivedBucket> {
+    let user: String = r.get(1)?;
+    Ok(ArchivedBucket {
+        tag: r.get(0)?,
+        user: Some(user).filter(|u| !u.is_empty()),
+        month: r.get(2)?,
+        file: r.get(3)?,
+        started_at: r.get(4)?,
+        events: r.get::<_, i64>(5)? as usize,
+        recommendations: r.get::<_, i64>(6)? as usize,
+        archived_at: r.get(7)?,
+    })
+}
+
+impl Store {
+    /// Blank the raw webhook body of events from before `cutoff`. Returns how many changed.
+    pub fn strip_raw_before(&self, cutoff: DateTime<Utc>) -> Result<usize> {
+        Ok(self.conn.execute(
+            "UPDATE events SET data = json_set(data, '$.raw', '')
+             WHERE ts < ?1 AND json_valid(data) AND json_extract(data, '$.raw') != ''",
+            [ts(cutoff)],
+        )?)
+    }
+
+    /// Move buckets that started before `cutoff` (with their events and recommendations)
+    /// into gzipped monthly NDJSON files under `dir`, in the format `import_ndjson` reads.
+    /// Only buckets with recommendations or that `is_closed` (by the engine's bucketing
+    /// strategy) go; one still filling up or waiting for recommendations stays.
+    pub fn archive_before(
+        &mut self,
+        cutoff: DateTime<Utc>,
+        dir: &Path,
+        is_closed: impl Fn(&Bucket) -> bool,
+    ) -> Result<RetentionReport> {
+        let mut report = RetentionReport::default();
+        let old: Vec<Bucket> = {
+            let mut stmt = self.conn.prepare(&format!(
+                "SELECT {} FROM buckets b WHERE b.started_at < ?1 ORDER BY b.id",
+                super::BUCKET_COLUMNS
+            ))?;
+            let rows = stmt.query_map([ts(cutoff)], super::bucket_from_row)?;
+            rows.collect::<rusqlite::Result<Vec<Bucket>>>()?
+                .into_iter()
+                .filter(|b| b.recommendations_generated_at.is_some() || is_closed(b))
+                .collect()
+        };
+        if old.is_empty() {
+            return Ok(report);
+        }
+        fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+
+        let mut by_month: BTreeMap<String, Vec<(Bucket, Value)>> = BTreeMap::new();
+        for b in old {
+            let events: Vec<Value> = self
+                .bucket_events(b.id)?
+                .into_iter()
+                .map(|e| serde_json::from_str(&e.row.data).unwrap_or(Value::Null))
+                .collect();
+            let recs = self.recommendations(b.id)?;
+            let report = self
+                .recommendation_report(b.id)?
+                .and_then(|r| serde_json::from_str::<Value>(&r).ok());
+            let line = json!({
+                "bucket": b.tag,
+                "user": b.user,
+                "started_at": b.started_at,
+                "updated_at": b.updated_at,
+                "count": events.len(),
+                "events": events,
+                "recommendations": recs,
+                "recommendations_generated_at": b.recommendations_generated_at,
+                "recommendation_report": report,
+            });
+            by_month.entry(month_of(&b)).or_default().push((b, line));
+        }
+
+        let now = ts(Utc::now());
+        for (month, buckets) in by_month {
+            let file = format!("{month}.ndjson.gz");
+            let path = dir.join(&file);
+            // Written (and synced) before the rows are deleted: a crash in between leaves a
+            // duplicate in the archive, never a lost bucket. Each pass appends a gzip member.
+            let out = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .with_context(|| format!("open {}", path.display()))?;
+            let mut gz = GzEncoder::new(out, Compression::default());
+            for (_, line) in &buckets {
+                serde_json::to_writer(&mut gz, line)?;
+                gz.write_all(b"\n")?;
+            }
+            gz.finish()?.sync_all()?;
+
+            let tx = self.conn.transaction()?;
+            for (b, line) in &buckets {
+                tx.execute(
+                    &format!(
+                        "INSERT OR REPLACE INTO archived_buckets ({ARCHIVE_COLUMNS})
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"
+                    ),
+                    params![
+                        b.tag,
+                        b.user.as_deref().unwrap_or(""),
+                        month,
+                        file,
+                        b.started_at,
+                        b.count as i64,
+                        line["recommendations"].as_array().map_or(0, |r| r.len()) as i64,
+                        now
+                    ],
+                )?;
+                tx.execute(
+                    &format!(
+                        "INSERT OR IGNORE INTO archived_watches (media_type, tmdb_id)
+                         SELECT {}, tmdb_id FROM events
+                         WHERE bucket_id = ?1 AND tmdb_id IS NOT NULL",
+                        super::WATCHED_MEDIA_TYPE
+                    ),
+                    [b.id],
+                )?;
+                tx.execute("DELETE FROM buckets WHERE id = ?1", [b.id])?;
+                report.buckets_archived += 1;
+                report.events_archived += b.count;
+            }
+            tx.commit()?;
+            report.files.push(file);
+        }
+        Ok(report)
+    }
+
+    /// Strip and archive per `policy`, relative to `now`. See [`Store::archive_before`]
+    /// for `is_closed`.
+    pub fn apply_retention(
+        &mut self,
+        policy: &RetentionPolicy,
+        now: DateTime<Utc>,
+        is_closed: impl Fn(&Bucket) -> bool,
+    ) -> Result<RetentionReport> {
+        let mut report = RetentionReport::default();
+        if let Some(months) = policy.archive_months {
+            let cutoff = now
+                .checked_sub_months(Months::new(months))
+                .unwrap_or(DateTime::<Utc>::MIN_UTC);
+            report = self.archive_before(cutoff, &policy.archive_dir, is_closed)?;
+        }
+        if let Some(days) = policy.raw_days {
+            report.raw_stripped = self.strip_raw_before(now - ChronoDuration::days(days.into()))?;
+        }
+        Ok(report)
+    }
+
+    /// Archived buckets, newest first, optionally for one user.
+    pub fn archived_buckets(
+        &self,
+        user: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<ArchivedBucket>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {ARCHIVE_COLUMNS} FROM archived_buckets
+             WHERE ?1 IS NULL OR user = ?1
+             ORDER BY started_at DESC, month DESC LIMIT ?2"
+        ))?;
+        let rows = stmt.query_map(params![user, limit as i64], archived_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn archived_bucket(&self, tag: &str, user: Option<&str>) -> Result<Option<ArchivedBucket>> {
+        Ok(self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT {ARCHIVE_COLUMNS} FROM archived_buckets WHERE tag = ?1 AND user = ?2"
+                ),
+                params![tag, user.unwrap_or("")],
+                archived_from_row,
+            )
+            .optional()?)
+    }
+
+    /// Checkpoint the WAL and rebuild the database file so freed pages go back to the OS.
+    pub fn vacuum(&self) -> Result<()> {
+        self.conn
+            .execute_batch("PRAGMA wal_checkpoint(TRUNCATE); VACUUM;")?;
+        self.conn
+            .execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")?;
+        Ok(())
+    }
+}
+
+/// The full bucket (as its archive line) for an [`ArchivedBucket`], read from `dir`.
+/// The newest copy wins if an interrupted pass left a duplicate.
+pub fn read_archived(dir: &Path, entry: &ArchivedBucket) -> Result<Option<Value>> {
+    let path = dir.join(&entry.file);
+    let f = File::open(&path).with_context(|| format!("open {}", path.display()))?;
+    let mut found = None;
+    for line in BufReader::new(MultiGzDecoder::new(f)).lines() {
+        let Ok(v) = serde_json::from_str::<Value>(&line?) else {
+            continue;
+        };
+        let user = v.get("user").and_then(|u| u.as_str());
+        if v.get("bucket").and_then(|t| t.as_str()) == Some(entry.tag.as_str())
+            && user == entry.user.as_deref()
+        {
+            found = Some(v);
+        }
+    }
+    Ok(found)
+}
+
+/// Bytes used by the database file plus its WAL.
+pub fn db_size(db_path: &Path) -> u64 {
+    let mut wal = db_path.as_os_str().to_owned();
+    wal.push("-wal");
+    [db_path.to_path_buf(), PathBuf::from(wal)]
+        .iter()
+        .filter_map(|p| fs::metadata(p).ok())
+        .map(|m| m.len())
+        .sum()
+}
@@ -0,0 +1,54 @@
+// Each test binary uses its own subset of these.
+#![allow(dead_code)]
+
+use lib::store::{EventRow, Store, StoreHandle};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// A database file in a fresh temporary directory, deleted with it
+pub struct TestDb {
+    dir: TempDir,
+    pub path: PathBuf,
+}
+
+pub fn temp_db() -> TestDb {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("alfred.sqlite3");
+    TestDb { dir, path }
+}
+
+impl TestDb {
+    /// The directory holding the database, for files kept next to it
+    pub fn dir(&self) -> &Path {
+        self.dir.path()
+    }
+
+    pub fn open(&self) -> Store {
+        Store::open(&self.path).unwrap()
+    }
+
+    pub fn spawn(&self) -> StoreHandle {
+        StoreHandle::spawn(&self.path).unwrap()
+    }
+}
+
+/// A movie watched through Tautulli, its data carrying a raw webhook body as ingest stores it.
+pub fn watched(tmdb_id: u32) -> EventRow {
+    EventRow {
+        source: "tautulli".into(),
+        event: "watched".into(),
+        title: format!("movie {tmdb_id}"),
+        media_type: Some("movie".into()),
+        tmdb_id: Some(tmdb_id),
+        data: format!(r#"{{"tmdb_id":{tmdb_id},"raw":"{{\"big\":\"payload\"}}"}}"#),
+        ..Default::default()
+    }
+}
+
+/// [`watched`], timestamped `ts`.
+pub fn watched_at(tmdb_id: u32, ts: &str) -> EventRow {
+    EventRow {
+        ts: Some(ts.into()),
+        ..watched(tmdb_id)
+    }
+}
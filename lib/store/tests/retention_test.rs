@@ -0,0 +1,172 @@
+mod common;
+
+use chrono::{TimeZone, Utc};
+use common::{temp_db, watched_at};
+use lib::store::retention::{self, RetentionPolicy};
+use lib::store::{EventRow, Recommendation};
+
+#[test]
+fn strips_raw_and_archives_old_buckets() {
+    let db = temp_db();
+    let mut store = db.open();
+
+    let old_ts = "2025-01-10T12:00:00Z";
+    let old = store
+        .append_event(
+            "2025-01-10T12Z",
+            Some("sam"),
+            old_ts,
+            &watched_at(603, old_ts),
+        )
+        .unwrap();
+    store
+        .set_recommendations(
+            old,
+            &[Recommendation {
+                tmdb_id: 604,
+                media_type: "movie".into(),
+                ..Default::default()
+            }],
+            "2025-01-10T18:01:00Z",
+        )
+        .unwrap();
+    let mid_ts = "2025-05-02T12:00:00Z";
+    store
+        .append_event("2025-05-02T12Z", None, mid_ts, &watched_at(949, mid_ts))
+        .unwrap();
+    let new_ts = "2025-06-20T12:00:00Z";
+    store
+        .append_event("2025-06-20T12Z", None, new_ts, &watched_at(155, new_ts))
+        .unwrap();
+
+    let policy = RetentionPolicy {
+        raw_days: Some(30),
+        archive_months: Some(3),
+        archive_dir: db.dir().join("archive"),
+    };
+    let now = Utc.with_ymd_and_hms(2025, 7, 1, 0, 0, 0).unwrap();
+    let report = store.apply_retention(&policy, now, |_| true).unwrap();
+    assert_eq!(report.buckets_archived, 1);
+    assert_eq!(report.events_archived, 1);
+    assert_eq!(report.files, vec!["2025-01.ndjson.gz".to_string()]);
+    // Only May's event is old enough to strip; January's left with its bucket.
+    assert_eq!(report.raw_stripped, 1);
+
+    assert!(store
+        .bucket("2025-01-10T12Z", Some("sam"))
+        .unwrap()
+        .is_none());
+    let mid = store.bucket("2025-05-02T12Z", None).unwrap().unwrap();
+    let data = &store.bucket_events(mid.id).unwrap()[0].row.data;
+    assert_eq!(data, r#"{"tmdb_id":949,"raw":""}"#);
+    let new = store.bucket("2025-06-20T12Z", None).unwrap().unwrap();
+    assert!(store.bucket_events(new.id).unwrap()[0]
+        .row
+        .data
+        .contains("payload"));
+
+    let archived = store.archived_buckets(Some("sam"), 10).unwrap();
+    assert_eq!(archived.len(), 1);
+    assert_eq!(archived[0].month, "2025-01");
+    assert_eq!(archived[0].recommendations, 1);
+    let entry = store
+        .archived_bucket("2025-01-10T12Z", Some("sam"))
+        .unwrap()
+        .unwrap();
+    let line = retention::read_archived(&policy.archive_dir, &entry)
+        .unwrap()
+        .unwrap();
+    assert_eq!(line["events"][0]["tmdb_id"], 603);
+    assert_eq!(line["recommendations"][0]["tmdb_id"], 604);
+
+    // A second pass appends a new gzip member to the same month's file.
+    let ts = "2025-01-20T12:00:00Z";
+    store
+        .append_event("2025-01-20T12Z", None, ts, &watched_at(1, ts))
+        .unwrap();
+    store.apply_retention(&policy, now, |_| true).unwrap();
+    let entry = store
+        .archived_bucket("2025-01-20T12Z", None)
+        .unwrap()
+        .unwrap();
+    assert_eq!(entry.file, "2025-01.ndjson.gz");
+    assert!(retention::read_archived(&policy.archive_dir, &entry)
+        .unwrap()
+        .is_some());
+    assert!(retention::read_archived(&policy.archive_dir, &archived[0])
+        .unwrap()
+        .is_some());
+
+    store.vacuum().unwrap();
+    assert!(retention::db_size(&db.path) > 0);
+}
+
+#[test]
+fn watched_titles_outlive_archiving() {
+    let db = temp_db();
+    let mut store = db.open();
+
+    let old_ts = "2025-01-10T12:00:00Z";
+    store
+        .append_event(
+            "2025-01-10T12Z",
+            Some("sam"),
+            old_ts,
+            &watched_at(603, old_ts),
+        )
+        .unwrap();
+    let ts = "2025-06-20T12:00:00Z";
+    let episode = EventRow {
+        media_type: Some("episode".into()),
+        show_title: Some("Severance".into()),
+        ..watched_at(95396, ts)
+    };
+    store
+        .append_event("2025-06-20T12Z", None, ts, &episode)
+        .unwrap();
+    store
+        .append_event("2025-06-20T12Z", None, ts, &watched_at(603, ts))
+        .unwrap();
+    let expected = vec![("movie".to_string(), 603), ("tv".to_string(), 95396)];
+    assert_eq!(store.watched_titles().unwrap(), expected);
+
+    let policy = RetentionPolicy {
+        raw_days: None,
+        archive_months: Some(1),
+        archive_dir: db.dir().join("archive"),
+    };
+    let now = Utc.with_ymd_and_hms(2025, 12, 1, 0, 0, 0).unwrap();
+    assert_eq!(
+        store
+            .apply_retention(&policy, now, |_| true)
+            .unwrap()
+            .buckets_archived,
+        2
+    );
+    assert_eq!(store.watched_titles().unwrap(), expected);
+}
+
+#[test]
+fn buckets_still_open_or_pending_are_not_archived() {
+    let db = temp_db();
+    let mut store = db.open();
+    for (tag, ts) in [
+        ("2025-01-10T12Z", "2025-01-10T12:00:00Z"),
+        ("2025-01-11T12Z", "2025-01-11T12:00:00Z"),
+    ] {
+        store
+            .append_event(tag, None, ts, &watched_at(603, ts))
+            .unwrap();
+    }
+
+    // Neither has recommendations; only the first is closed.
+    let cutoff = Utc.with_ymd_and_hms(2025, 6, 1, 0, 0, 0).unwrap();
+    let report = store
+        .archive_before(cutoff, &db.dir().join("archive"), |b| {
+            b.tag == "2025-01-10T12Z"
+        })
+        .unwrap();
+    assert_eq!(report.buckets_archived, 1);
+    assert!(store.bucket("2025-01-10T12Z", None).unwrap().is_none());
+    assert!(store.bucket("2025-01-11T12Z", None).unwrap().is_some());
+}
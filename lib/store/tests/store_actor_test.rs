@@ -0,0 +1,196 @@
+mod common;
+
+use common::{temp_db, watched};
+use lib::store::lock::StoreLock;
+use lib::store::{Recommendation, StoreHandle};
+use std::{
+    sync::{Arc, Barrier},
+    thread,
+    time::Duration,
+};
+
+const TAG: &str = "2025-09-29T12Z";
+
+fn append(store: &StoreHandle, tmdb_id: u32) {
+    store
+        .write_blocking(move |s| {
+            s.append_event(TAG, None, "2025-09-29T12:00:00Z", &watched(tmdb_id))
+        })
+        .unwrap();
+}
+
+#[test]
+fn webhooks_during_generation_are_not_lost() {
+    let db = temp_db();
+    let store = db.spawn();
+    append(&store, 603);
+    let bucket_id = store
+        .read_blocking(|s| Ok(s.bucket(TAG, None)?.unwrap().id))
+        .unwrap();
+
+    // Webhooks keep landing while generation reads the bucket, waits on the model and
+    // writes its recommendations back, over and over.
+    let start = Arc::new(Barrier::new(2));
+    let webhooks = {
+        let (store, start) = (store.clone(), start.clone());
+        thread::spawn(move || {
+            start.wait();
+            for i in 0..100 {
+                append(&store, 1000 + i);
+            }
+        })
+    };
+    start.wait();
+    for n in 1..=50u32 {
+        let events = store
+            .read_blocking(move |s| s.bucket_events(bucket_id))
+            .unwrap();
+        thread::sleep(Duration::from_millis(1));
+        let recs = vec![Recommendation {
+            tmdb_id: events.len() as u32,
+            media_type: "movie".into(),
+            ..Default::default()
+        }];
+        store
+            .write_blocking(move |s| s.set_recommendations(bucket_id, &recs, &n.to_string()))
+            .unwrap();
+    }
+    webhooks.join().unwrap();
+
+    let (count, recs) = store
+        .read_blocking(move |s| {
+            Ok((
+                s.bucket(TAG, None)?.unwrap().count,
+                s.recommendations(bucket_id)?.len(),
+            ))
+        })
+        .unwrap();
+    assert_eq!(count, 101);
+    assert_eq!(recs, 1);
+}
+
+#[test]
+fn storage_actor_keeps_webhook_during_generation() {
+    let db = temp_db();
+    let store = db.spawn();
+    append(&store, 603);
+
+    // Generation reads the bucket, a webhook lands while it waits on the model, then it
+    // writes its recommendations.
+    let (bucket, events) = store
+        .read_blocking(|s| {
+            let b = s.bucket(TAG, None)?.unwrap();
+            let events = s.bucket_events(b.id)?;
+            Ok((b, events))
+        })
+        .unwrap();
+    assert_eq!(events.len(), 1);
+    append(&store, 604);
+    let recs = vec![Recommendation {
+        tmdb_id: 1,
+        media_type: "movie".into(),
+        ..Default::default()
+    }];
+    store
+        .write_blocking(move |s| s.set_recommendations(bucket.id, &recs, "2025-09-29T18:00:00Z"))
+        .unwrap();
+
+    let (count, recs) = store
+        .read_blocking(move |s| {
+            Ok((
+                s.bucket(TAG, None)?.unwrap().count,
+                s.recommendations(bucket.id)?.len(),
+            ))
+        })
+        .unwrap();
+    assert_eq!(count, 2);
+    assert_eq!(recs, 1);
+}
+
+#[test]
+fn concurrent_writers_from_two_processes_all_land() {
+    let db = temp_db();
+    // Two handles on one file behave like two processes (separate connections and locks).
+    let handles = [db.spawn(), db.spawn()];
+    let threads: Vec<_> = (0..8u32)
+        .map(|t| {
+            let store = handles[t as usize % 2].clone();
+            thread::spawn(move || {
+                for i in 0..50 {
+                    append(&store, t * 1000 + i);
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    let count = handles[0]
+        .read_blocking(|s| Ok(s.bucket(TAG, None)?.unwrap().count))
+        .unwrap();
+    assert_eq!(count, 400);
+}
+
+#[test]
+fn reader_snapshot_never_sees_torn_recommendations() {
+    let db = temp_db();
+    let store = db.spawn();
+    append(&store, 603);
+    let bucket_id = store
+        .read_blocking(|s| Ok(s.bucket(TAG, None)?.unwrap().id))
+        .unwrap();
+
+    let start = Arc::new(Barrier::new(2));
+    let writer = {
+        let start = start.clone();
+        thread::spawn(move || {
+            start.wait();
+            for n in 1..=100u32 {
+                let recs: Vec<Recommendation> = (0..n)
+                    .map(|i| Recommendation {
+                        tmdb_id: i,
+                        media_type: "movie".into(),
+                        ..Default::default()
+                    })
+                    .collect();
+                store
+                    .write_blocking(move |s| {
+                        s.set_recommendations(bucket_id, &recs, &n.to_string())
+                    })
+                    .unwrap();
+            }
+        })
+    };
+
+    // The dashboard: its own connection, reading a page at a time.
+    let dashboard = db.open();
+    start.wait();
+    for _ in 0..200 {
+        let (stamp, len) = dashboard
+            .snapshot(|s| {
+                let b = s.bucket(TAG, None)?.unwrap();
+                Ok((
+                    b.recommendations_generated_at,
+                    s.recommendations(b.id)?.len(),
+                ))
+            })
+            .unwrap();
+        if let Some(stamp) = stamp {
+            assert_eq!(stamp.parse::<usize>().unwrap(), len);
+        }
+    }
+    writer.join().unwrap();
+}
+
+#[test]
+fn write_lock_excludes_readers_in_other_processes() {
+    let db = temp_db();
+    let writer = StoreLock::open(&db.path).unwrap();
+    let reader = StoreLock::open(&db.path).unwrap();
+
+    let guard = writer.exclusive().unwrap();
+    assert!(reader.try_shared().unwrap().is_none());
+    drop(guard);
+    thread::sleep(Duration::from_millis(10));
+    assert!(reader.try_shared().unwrap().is_some());
+}
@@ -0,0 +1,155 @@
+mod common;
+
+use common::{temp_db, watched};
+use lib::store::Recommendation;
+use std::{io::Write, time::Duration};
+
+#[test]
+fn buckets_events_and_recommendations_round_trip() {
+    let db = temp_db();
+    let mut store = db.open();
+
+    let a = store
+        .append_event(
+            "2025-09-29T12Z",
+            Some("patrick"),
+            "2025-09-29T12:01:00Z",
+            &watched(603),
+        )
+        .unwrap();
+    let b = store
+        .append_event(
+            "2025-09-29T12Z",
+            Some("patrick"),
+            "2025-09-29T13:00:00Z",
+            &watched(604),
+        )
+        .unwrap();
+    store
+        .append_event(
+            "2025-09-29T12Z",
+            None,
+            "2025-09-29T12:30:00Z",
+            &watched(949),
+        )
+        .unwrap();
+    assert_eq!(a, b);
+
+    let bucket = store
+        .bucket("2025-09-29T12Z", Some("patrick"))
+        .unwrap()
+        .unwrap();
+    assert_eq!(bucket.count, 2);
+    assert_eq!(bucket.started_at.as_deref(), Some("2025-09-29T12:01:00Z"));
+    assert_eq!(bucket.updated_at.as_deref(), Some("2025-09-29T13:00:00Z"));
+    assert_eq!(store.users().unwrap(), vec!["patrick".to_string()]);
+    assert_eq!(store.unprocessed_buckets().unwrap().len(), 2);
+
+    let recs = vec![
+        Recommendation {
+            tmdb_id: 1,
+            media_type: "movie".into(),
+            ..Default::default()
+        },
+        Recommendation {
+            tmdb_id: 1396,
+            media_type: "tv".into(),
+            confidence: Some(0.92),
+            source: Some("llm".into()),
+            reason: Some("Another slow-burn mystery".into()),
+            because_of: vec![1399],
+        },
+    ];
+    store
+        .set_recommendations(bucket.id, &recs, "2025-09-29T18:00:00Z")
+        .unwrap();
+    assert_eq!(store.recommendations(bucket.id).unwrap(), recs);
+    assert_eq!(store.unprocessed_buckets().unwrap().len(), 1);
+
+    // Feedback notes who recommended the title; a later verdict replaces the earlier one.
+    store
+        .put_feedback(Some("patrick"), "tv", 1396, "like", "2025-09-30T10:00:00Z")
+        .unwrap();
+    let fb = store
+        .put_feedback(
+            Some("patrick"),
+            "tv",
+            1396,
+            "dislike",
+            "2025-09-30T11:00:00Z",
+        )
+        .unwrap();
+    assert_eq!(
+        (fb.kind.as_str(), fb.source.as_deref()),
+        ("dislike", Some("llm"))
+    );
+    store
+        .put_feedback(None, "movie", 603, "seen", "2025-09-30T12:00:00Z")
+        .unwrap();
+    assert_eq!(store.feedback(Some("patrick")).unwrap(), vec![fb]);
+    let all = store.all_feedback().unwrap();
+    assert_eq!(
+        (all.len(), all[0].user.as_deref(), all[0].source.as_deref()),
+        (2, None, None)
+    );
+
+    // A second connection (e.g. the dashboard) sees the committed state.
+    let reader = db.open();
+    assert_eq!(reader.buckets(Some("patrick"), 10).unwrap().len(), 1);
+    assert_eq!(reader.buckets(None, 10).unwrap().len(), 2);
+}
+
+#[test]
+fn ndjson_import_is_idempotent() {
+    let db = temp_db();
+    let ndjson = db.dir().join("old.ndjson");
+    let mut f = std::fs::File::create(&ndjson).unwrap();
+    writeln!(
+        f,
+        r#"{{"bucket":"2025-09-29T12Z","started_at":"2025-09-29T12:01:00Z","count":2,"events":[{{"event":"watched","tmdb_id":603}},{{"event":"watched","tmdb_id":"604"}}],"recommendations":[{{"tmdb_id":1}},{{"tmdb_id":2}}],"recommendations_generated_at":"2025-09-29T18:00:00Z"}}"#
+    )
+    .unwrap();
+    writeln!(
+        f,
+        r#"{{"bucket":"2025-09-29T18Z","user":"sam","events":[{{"event":"watched","tmdb_id":949}}]}}"#
+    )
+    .unwrap();
+    writeln!(f, "not json").unwrap();
+
+    let mut store = db.open();
+    let report = store.import_ndjson(&ndjson).unwrap();
+    assert_eq!(report.buckets, 2);
+    assert_eq!(report.events, 3);
+    assert_eq!(report.recommendations, 2);
+    assert_eq!(report.bad_lines, 1);
+
+    let again = store.import_ndjson(&ndjson).unwrap();
+    assert_eq!(again.buckets, 0);
+    assert_eq!(again.skipped_buckets, 2);
+
+    let shared = store.bucket("2025-09-29T12Z", None).unwrap().unwrap();
+    assert_eq!(shared.count, 2);
+    let events = store.bucket_events(shared.id).unwrap();
+    assert_eq!(events[1].row.tmdb_id, Some(604));
+    assert_eq!(store.unprocessed_buckets().unwrap().len(), 1);
+}
+
+#[test]
+fn enrichment_cache_expires() {
+    let db = temp_db();
+    let store = db.open();
+    store
+        .cache_put("tmdb_movie", "603", r#"{"id":603}"#)
+        .unwrap();
+    assert_eq!(
+        store
+            .cache_get("tmdb_movie", "603", Duration::from_secs(60))
+            .unwrap()
+            .as_deref(),
+        Some(r#"{"id":603}"#)
+    );
+    assert!(store
+        .cache_get("tmdb_tv", "603", Duration::from_secs(60))
+        .unwrap()
+        .is_none());
+}